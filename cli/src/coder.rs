@@ -0,0 +1,913 @@
+//! Borsh serialization of JSON values, e.g., accounts, with the types of an
+//! IDL. Values are rendered as by the TypeScript client, except that public
+//! keys are base 58 strings and integers wider than 64 bits are strings.
+//!
+//! Zero copy accounts, i.e., with `bytemuck` serialization, are laid out as
+//! `#[repr(C)]` structs on BPF instead, with their fields aligned and padded.
+
+use anchor_syn::codegen::program::common::{sighash, SIGHASH_GLOBAL_NAMESPACE};
+use anchor_syn::idl::{
+    EnumFields, Idl, IdlEvent, IdlField, IdlInstruction, IdlSerialization, IdlType,
+    IdlTypeDefinition, IdlTypeDefinitionTy,
+};
+use anyhow::{anyhow, Result};
+use heck::SnakeCase;
+use serde_json::{Map, Value as JsonValue};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+
+/// Serializes an account of the IDL, prefixed with its discriminator.
+pub fn encode_account(idl: &Idl, name: &str, value: &JsonValue) -> Result<Vec<u8>> {
+    let ty_def = account_def(idl, name)?;
+    let mut data = account_discriminator(name, ty_def.version.unwrap_or(1)).to_vec();
+    match ty_def.serialization {
+        IdlSerialization::Borsh => encode_type_def(idl, ty_def, value, &mut data)?,
+        IdlSerialization::Bytemuck => {
+            let ty = IdlType::Defined(ty_def.name.clone());
+            let mut account = vec![0; c_layout(idl, &ty)?.size];
+            encode_c(idl, &ty, value, &mut account)?;
+            data.extend(account);
+        }
+    }
+    Ok(data)
+}
+
+/// Deserializes an account of the IDL, checking its discriminator. Accounts
+/// written with a previous version of a versioned account are decoded with
+/// the layout of that version.
+pub fn decode_account(idl: &Idl, name: &str, data: &[u8]) -> Result<JsonValue> {
+    let ty_def = account_def(idl, name)?;
+    let version = data_version(ty_def, data)
+        .ok_or_else(|| anyhow!("Account discriminator doesn't match {}", name))?;
+    if ty_def.serialization == IdlSerialization::Bytemuck {
+        let ty = IdlType::Defined(ty_def.name.clone());
+        let size = c_layout(idl, &ty)?.size;
+        if data.len() < 8 + size {
+            return Err(anyhow!("Unexpected end of data"));
+        }
+        return decode_c(idl, &ty, &data[8..8 + size]);
+    }
+    match ty_def.versions.iter().find(|v| v.version == version) {
+        Some(layout) => {
+            let layout = IdlTypeDefinition {
+                name: layout.name.clone(),
+                ty: layout.ty.clone(),
+                serialization: IdlSerialization::Borsh,
+                version: None,
+                versions: vec![],
+            };
+            decode_type_def(idl, &layout, &mut &data[8..])
+        }
+        None => decode_type_def(idl, ty_def, &mut &data[8..]),
+    }
+}
+
+/// The account of the IDL with the discriminator `data` starts with, if any.
+pub fn find_account<'a>(idl: &'a Idl, data: &[u8]) -> Option<&'a IdlTypeDefinition> {
+    if data.len() < 8 {
+        return None;
+    }
+    idl.accounts
+        .iter()
+        .find(|acc| data_version(acc, data).is_some())
+}
+
+/// The version of the layout an account's data was written with, if it's
+/// the data of the account.
+pub fn data_version(ty_def: &IdlTypeDefinition, data: &[u8]) -> Option<u8> {
+    if data.len() < 8 {
+        return None;
+    }
+    std::iter::once(ty_def.version.unwrap_or(1))
+        .chain(ty_def.versions.iter().map(|v| v.version))
+        .find(|version| data[..8] == account_discriminator(&ty_def.name, *version))
+}
+
+/// Serializes the data of an instruction of the IDL, i.e., its sighash
+/// followed by its arguments, given as an object keyed by argument name.
+pub fn encode_instruction(idl: &Idl, ix: &IdlInstruction, args: &JsonValue) -> Result<Vec<u8>> {
+    let empty = Map::new();
+    let args = match args {
+        JsonValue::Null => &empty,
+        JsonValue::Object(args) => args,
+        _ => return Err(anyhow!("Expected an object of arguments, found {}", args)),
+    };
+    if let Some(name) = args
+        .keys()
+        .find(|name| !ix.args.iter().any(|a| &a.name == *name))
+    {
+        return Err(anyhow!("Unknown argument {} of {}", name, ix.name));
+    }
+
+    let mut data = instruction_sighash(&ix.name).to_vec();
+    for arg in &ix.args {
+        let value = args
+            .get(&arg.name)
+            .ok_or_else(|| anyhow!("Missing argument {} of {}", arg.name, ix.name))?;
+        encode(idl, &arg.ty, value, &mut data)
+            .map_err(|e| anyhow!("Invalid argument {}: {}", arg.name, e))?;
+    }
+    Ok(data)
+}
+
+/// The instruction of the IDL with the sighash `data` starts with, if any.
+pub fn find_instruction<'a>(idl: &'a Idl, data: &[u8]) -> Option<&'a IdlInstruction> {
+    if data.len() < 8 {
+        return None;
+    }
+    idl.instructions
+        .iter()
+        .find(|ix| data[..8] == instruction_sighash(&ix.name))
+}
+
+/// Deserializes the arguments of an instruction of the IDL, following its
+/// sighash, as an object keyed by argument name.
+pub fn decode_instruction(idl: &Idl, ix: &IdlInstruction, data: &[u8]) -> Result<JsonValue> {
+    if data.len() < 8 || data[..8] != instruction_sighash(&ix.name) {
+        return Err(anyhow!("Instruction sighash doesn't match {}", ix.name));
+    }
+    decode_fields(idl, &ix.args, &mut &data[8..])
+}
+
+/// Deserializes an event of the IDL, following its discriminator.
+pub fn decode_event(idl: &Idl, event: &IdlEvent, data: &[u8]) -> Result<JsonValue> {
+    if data.len() < 8 || data[..8] != event_discriminator(&event.name) {
+        return Err(anyhow!("Event discriminator doesn't match {}", event.name));
+    }
+    let data = &mut &data[8..];
+    let mut obj = Map::new();
+    for field in &event.fields {
+        let value = decode(idl, &field.ty, data)
+            .map_err(|e| anyhow!("Invalid field {}: {}", field.name, e))?;
+        obj.insert(field.name.clone(), value);
+    }
+    Ok(JsonValue::Object(obj))
+}
+
+/// The event of the IDL with the discriminator `data` starts with, if any.
+pub fn find_event<'a>(idl: &'a Idl, data: &[u8]) -> Option<&'a IdlEvent> {
+    if data.len() < 8 {
+        return None;
+    }
+    idl.events
+        .as_ref()?
+        .iter()
+        .find(|event| data[..8] == event_discriminator(&event.name))
+}
+
+fn account_def<'a>(idl: &'a Idl, name: &str) -> Result<&'a IdlTypeDefinition> {
+    idl.accounts
+        .iter()
+        .find(|acc| acc.name == name)
+        .ok_or_else(|| anyhow!("Account {} not found in the {} IDL", name, idl.name))
+}
+
+fn type_def<'a>(idl: &'a Idl, name: &str) -> Result<&'a IdlTypeDefinition> {
+    idl.types
+        .iter()
+        .chain(idl.accounts.iter())
+        .find(|ty_def| ty_def.name == name)
+        .ok_or_else(|| anyhow!("Type {} not found in the IDL", name))
+}
+
+/// The sighash prefixing the data of a global instruction named `name`, as
+/// computed by the `#[program]` macro.
+pub fn instruction_sighash(name: &str) -> [u8; 8] {
+    sighash(SIGHASH_GLOBAL_NAMESPACE, &name.to_snake_case())
+}
+
+/// The discriminator prefixing the data of an account named `name`, written
+/// with the given version of its layout, as computed by `#[account]`.
+pub fn account_discriminator(name: &str, version: u8) -> [u8; 8] {
+    let mut preimage = format!("account:{}", name);
+    if version > 1 {
+        preimage.push_str(&format!(":v{}", version));
+    }
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&solana_sdk::hash::hash(preimage.as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+/// The discriminator prefixing the data of an event named `name`.
+pub fn event_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(
+        &solana_sdk::hash::hash(format!("event:{}", name).as_bytes()).to_bytes()[..8],
+    );
+    discriminator
+}
+
+/// Serializes `value` with borsh, as the type `ty`.
+pub fn encode(idl: &Idl, ty: &IdlType, value: &JsonValue, out: &mut Vec<u8>) -> Result<()> {
+    match ty {
+        IdlType::Bool => out.push(
+            value
+                .as_bool()
+                .ok_or_else(|| anyhow!("Expected a bool, found {}", value))? as u8,
+        ),
+        IdlType::U8 => out.extend(&parse_int::<u8>(value)?.to_le_bytes()),
+        IdlType::I8 => out.extend(&parse_int::<i8>(value)?.to_le_bytes()),
+        IdlType::U16 => out.extend(&parse_int::<u16>(value)?.to_le_bytes()),
+        IdlType::I16 => out.extend(&parse_int::<i16>(value)?.to_le_bytes()),
+        IdlType::U32 => out.extend(&parse_int::<u32>(value)?.to_le_bytes()),
+        IdlType::I32 => out.extend(&parse_int::<i32>(value)?.to_le_bytes()),
+        IdlType::U64 => out.extend(&parse_int::<u64>(value)?.to_le_bytes()),
+        IdlType::I64 => out.extend(&parse_int::<i64>(value)?.to_le_bytes()),
+        IdlType::U128 => out.extend(&parse_int::<u128>(value)?.to_le_bytes()),
+        IdlType::I128 => out.extend(&parse_int::<i128>(value)?.to_le_bytes()),
+        IdlType::Bytes => {
+            let bytes = as_array(value)?
+                .iter()
+                .map(parse_int::<u8>)
+                .collect::<Result<Vec<u8>>>()?;
+            out.extend(&(bytes.len() as u32).to_le_bytes());
+            out.extend(bytes);
+        }
+        IdlType::String => {
+            let s = value
+                .as_str()
+                .ok_or_else(|| anyhow!("Expected a string, found {}", value))?;
+            out.extend(&(s.len() as u32).to_le_bytes());
+            out.extend(s.as_bytes());
+        }
+        IdlType::PublicKey => {
+            let s = value
+                .as_str()
+                .ok_or_else(|| anyhow!("Expected a public key, found {}", value))?;
+            out.extend(Pubkey::from_str(s)?.as_ref());
+        }
+        IdlType::Defined(name) => encode_type_def(idl, type_def(idl, name)?, value, out)?,
+        IdlType::Option(ty) => match value {
+            JsonValue::Null => out.push(0),
+            value => {
+                out.push(1);
+                encode(idl, ty, value, out)?;
+            }
+        },
+        IdlType::Vec(ty) => {
+            let items = as_array(value)?;
+            out.extend(&(items.len() as u32).to_le_bytes());
+            for item in items {
+                encode(idl, ty, item, out)?;
+            }
+        }
+        IdlType::Array(ty, len) => {
+            let items = as_array(value)?;
+            if items.len() != *len {
+                return Err(anyhow!(
+                    "Expected an array of {} items, found {}",
+                    len,
+                    items.len()
+                ));
+            }
+            for item in items {
+                encode(idl, ty, item, out)?;
+            }
+        }
+        IdlType::RingBuffer(..) | IdlType::Slab(..) | IdlType::BitMap(_) => {
+            return Err(anyhow!("Zero copy containers aren't supported"))
+        }
+    }
+    Ok(())
+}
+
+fn encode_type_def(
+    idl: &Idl,
+    ty_def: &IdlTypeDefinition,
+    value: &JsonValue,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    match &ty_def.ty {
+        IdlTypeDefinitionTy::Struct { fields } => encode_fields(idl, fields, value, out),
+        IdlTypeDefinitionTy::Enum { variants } => {
+            // Unit variants are given by name, others as `{ "Variant": fields }`.
+            let (name, fields) = match value {
+                JsonValue::String(name) => (name, None),
+                JsonValue::Object(obj) if obj.len() == 1 => {
+                    let (name, fields) = obj.iter().next().unwrap();
+                    (name, Some(fields))
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "Expected a {} variant, found {}",
+                        ty_def.name,
+                        value
+                    ))
+                }
+            };
+            let idx = variants
+                .iter()
+                .position(|v| &v.name == name)
+                .ok_or_else(|| anyhow!("Variant {} not found in {}", name, ty_def.name))?;
+            out.push(idx as u8);
+            match (&variants[idx].fields, fields) {
+                (None, _) => Ok(()),
+                (Some(EnumFields::Named(fields)), Some(value)) => {
+                    encode_fields(idl, fields, value, out)
+                }
+                (Some(EnumFields::Tuple(types)), Some(value)) => {
+                    let items = as_array(value)?;
+                    if items.len() != types.len() {
+                        return Err(anyhow!("Expected {} fields for {}", types.len(), name));
+                    }
+                    for (ty, item) in types.iter().zip(items) {
+                        encode(idl, ty, item, out)?;
+                    }
+                    Ok(())
+                }
+                (Some(_), None) => Err(anyhow!("Missing the fields of {}", name)),
+            }
+        }
+    }
+}
+
+// Fields are looked up by their IDL (camelCase) name, or in snake case.
+fn encode_fields(
+    idl: &Idl,
+    fields: &[IdlField],
+    value: &JsonValue,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    for field in fields {
+        let field_value = value
+            .get(&field.name)
+            .or_else(|| value.get(&field.name.to_snake_case()))
+            .ok_or_else(|| anyhow!("Missing field {}", field.name))?;
+        encode(idl, &field.ty, field_value, out)
+            .map_err(|e| anyhow!("Invalid field {}: {}", field.name, e))?;
+    }
+    Ok(())
+}
+
+// Integers may be given as JSON numbers or, e.g., for u64s too large for
+// JavaScript, as strings.
+fn parse_int<T: FromStr>(value: &JsonValue) -> Result<T> {
+    let s = match value {
+        JsonValue::Number(n) => n.to_string(),
+        JsonValue::String(s) => s.clone(),
+        _ => return Err(anyhow!("Expected an integer, found {}", value)),
+    };
+    s.parse()
+        .map_err(|_| anyhow!("Invalid integer {} for its type", value))
+}
+
+fn as_array(value: &JsonValue) -> Result<&Vec<JsonValue>> {
+    value
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected an array, found {}", value))
+}
+
+/// Deserializes a value of the type `ty`, advancing `data` past it.
+pub fn decode(idl: &Idl, ty: &IdlType, data: &mut &[u8]) -> Result<JsonValue> {
+    let value = match ty {
+        IdlType::Bool => JsonValue::Bool(take(data, 1)?[0] != 0),
+        IdlType::U8 => take(data, 1)?[0].into(),
+        IdlType::I8 => (take(data, 1)?[0] as i8).into(),
+        IdlType::U16 => u16::from_le_bytes(take_array(data)?).into(),
+        IdlType::I16 => i16::from_le_bytes(take_array(data)?).into(),
+        IdlType::U32 => u32::from_le_bytes(take_array(data)?).into(),
+        IdlType::I32 => i32::from_le_bytes(take_array(data)?).into(),
+        IdlType::U64 => u64::from_le_bytes(take_array(data)?).into(),
+        IdlType::I64 => i64::from_le_bytes(take_array(data)?).into(),
+        IdlType::U128 => u128::from_le_bytes(take_array(data)?).to_string().into(),
+        IdlType::I128 => i128::from_le_bytes(take_array(data)?).to_string().into(),
+        IdlType::Bytes => {
+            let len = u32::from_le_bytes(take_array(data)?) as usize;
+            take(data, len)?.to_vec().into()
+        }
+        IdlType::String => {
+            let len = u32::from_le_bytes(take_array(data)?) as usize;
+            String::from_utf8(take(data, len)?.to_vec())?.into()
+        }
+        IdlType::PublicKey => Pubkey::new(take(data, 32)?).to_string().into(),
+        IdlType::Defined(name) => decode_type_def(idl, type_def(idl, name)?, data)?,
+        IdlType::Option(ty) => match take(data, 1)?[0] {
+            0 => JsonValue::Null,
+            _ => decode(idl, ty, data)?,
+        },
+        IdlType::Vec(ty) => {
+            let len = u32::from_le_bytes(take_array(data)?) as usize;
+            (0..len)
+                .map(|_| decode(idl, ty, data))
+                .collect::<Result<Vec<_>>>()?
+                .into()
+        }
+        IdlType::Array(ty, len) => (0..*len)
+            .map(|_| decode(idl, ty, data))
+            .collect::<Result<Vec<_>>>()?
+            .into(),
+        IdlType::RingBuffer(..) | IdlType::Slab(..) | IdlType::BitMap(_) => {
+            return Err(anyhow!("Zero copy containers aren't supported"))
+        }
+    };
+    Ok(value)
+}
+
+fn decode_type_def(idl: &Idl, ty_def: &IdlTypeDefinition, data: &mut &[u8]) -> Result<JsonValue> {
+    match &ty_def.ty {
+        IdlTypeDefinitionTy::Struct { fields } => decode_fields(idl, fields, data),
+        IdlTypeDefinitionTy::Enum { variants } => {
+            let idx = take(data, 1)?[0] as usize;
+            let variant = variants
+                .get(idx)
+                .ok_or_else(|| anyhow!("Invalid variant {} of {}", idx, ty_def.name))?;
+            let fields = match &variant.fields {
+                None => return Ok(JsonValue::String(variant.name.clone())),
+                Some(EnumFields::Named(fields)) => decode_fields(idl, fields, data)?,
+                Some(EnumFields::Tuple(types)) => types
+                    .iter()
+                    .map(|ty| decode(idl, ty, data))
+                    .collect::<Result<Vec<_>>>()?
+                    .into(),
+            };
+            let mut obj = Map::new();
+            obj.insert(variant.name.clone(), fields);
+            Ok(JsonValue::Object(obj))
+        }
+    }
+}
+
+fn decode_fields(idl: &Idl, fields: &[IdlField], data: &mut &[u8]) -> Result<JsonValue> {
+    let mut obj = Map::new();
+    for field in fields {
+        let value = decode(idl, &field.ty, data)
+            .map_err(|e| anyhow!("Invalid field {}: {}", field.name, e))?;
+        obj.insert(field.name.clone(), value);
+    }
+    Ok(JsonValue::Object(obj))
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if data.len() < len {
+        return Err(anyhow!("Unexpected end of data"));
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Ok(head)
+}
+
+fn take_array<const N: usize>(data: &mut &[u8]) -> Result<[u8; N]> {
+    let mut array = [0u8; N];
+    array.copy_from_slice(take(data, N)?);
+    Ok(array)
+}
+
+/// Size and alignment of a zero copy type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CLayout {
+    pub size: usize,
+    pub align: usize,
+}
+
+// Alignment of the `#[repr(C)]` layout on BPF, where no type is aligned to
+// more than 8 bytes.
+const MAX_ALIGN: usize = 8;
+
+/// The `#[repr(C)]` layout of a zero copy type on BPF.
+pub fn c_layout(idl: &Idl, ty: &IdlType) -> Result<CLayout> {
+    let primitive = |size: usize| CLayout {
+        size,
+        align: size.min(MAX_ALIGN),
+    };
+    let layout = match ty {
+        IdlType::Bool | IdlType::U8 | IdlType::I8 => primitive(1),
+        IdlType::U16 | IdlType::I16 => primitive(2),
+        IdlType::U32 | IdlType::I32 => primitive(4),
+        IdlType::U64 | IdlType::I64 => primitive(8),
+        IdlType::U128 | IdlType::I128 => primitive(16),
+        IdlType::PublicKey => CLayout { size: 32, align: 1 },
+        IdlType::Array(ty, len) => {
+            let item = c_layout(idl, ty)?;
+            CLayout {
+                size: item.size * len,
+                align: item.align,
+            }
+        }
+        IdlType::Defined(name) => {
+            let ty_def = type_def(idl, name)?;
+            match &ty_def.ty {
+                IdlTypeDefinitionTy::Struct { fields } => {
+                    c_struct_layout(idl, fields.iter().map(|f| &f.ty))?.1
+                }
+                // `#[zero_copy]` enums are stored as their `u8` discriminant,
+                // in a `ZeroCopyEnum`.
+                IdlTypeDefinitionTy::Enum { variants } => {
+                    if variants.iter().any(|v| v.fields.is_some()) {
+                        return Err(anyhow!("Enum {} isn't zero copy", name));
+                    }
+                    primitive(1)
+                }
+            }
+        }
+        IdlType::RingBuffer(..) | IdlType::Slab(..) | IdlType::BitMap(_) => {
+            let fields = container_fields(ty);
+            c_struct_layout(idl, fields.iter().map(|(_, ty)| ty))?.1
+        }
+        IdlType::Bytes | IdlType::String | IdlType::Option(_) | IdlType::Vec(_) => {
+            return Err(anyhow!("{:?} isn't zero copy", ty))
+        }
+    };
+    Ok(layout)
+}
+
+// Offsets of the fields of a `#[repr(C)]` struct, and its layout, including
+// the padding at its end.
+fn c_struct_layout<'a>(
+    idl: &Idl,
+    fields: impl Iterator<Item = &'a IdlType>,
+) -> Result<(Vec<usize>, CLayout)> {
+    let mut offsets = vec![];
+    let mut offset = 0;
+    let mut align = 1;
+    for ty in fields {
+        let field = c_layout(idl, ty)?;
+        offset = align_up(offset, field.align);
+        offsets.push(offset);
+        offset += field.size;
+        align = align.max(field.align);
+    }
+    let layout = CLayout {
+        size: align_up(offset, align),
+        align,
+    };
+    Ok((offsets, layout))
+}
+
+fn align_up(offset: usize, align: usize) -> usize {
+    (offset + align - 1) / align * align
+}
+
+// The fields of a container, as declared by `anchor_lang`, named as by the
+// TypeScript client.
+fn container_fields(ty: &IdlType) -> Vec<(&'static str, IdlType)> {
+    match ty {
+        IdlType::RingBuffer(item, len) => vec![
+            ("head", IdlType::U64),
+            ("len", IdlType::U64),
+            ("items", IdlType::Array(item.clone(), *len)),
+        ],
+        IdlType::Slab(item, len) => vec![
+            ("len", IdlType::U64),
+            ("bump", IdlType::U64),
+            ("freeHead", IdlType::U64),
+            ("next", IdlType::Array(Box::new(IdlType::U64), *len)),
+            ("items", IdlType::Array(item.clone(), *len)),
+        ],
+        IdlType::BitMap(len) => vec![("words", IdlType::Array(Box::new(IdlType::U64), *len))],
+        _ => vec![],
+    }
+}
+
+// The fields of a zero copy struct or container.
+fn c_fields(idl: &Idl, ty: &IdlType) -> Result<Option<Vec<(String, IdlType)>>> {
+    let fields = match ty {
+        IdlType::Defined(name) => match &type_def(idl, name)?.ty {
+            IdlTypeDefinitionTy::Struct { fields } => fields
+                .iter()
+                .map(|f| (f.name.clone(), f.ty.clone()))
+                .collect(),
+            IdlTypeDefinitionTy::Enum { .. } => return Ok(None),
+        },
+        IdlType::RingBuffer(..) | IdlType::Slab(..) | IdlType::BitMap(_) => container_fields(ty)
+            .into_iter()
+            .map(|(name, ty)| (name.to_string(), ty))
+            .collect(),
+        _ => return Ok(None),
+    };
+    Ok(Some(fields))
+}
+
+/// Deserializes a zero copy value of the type `ty` from `data`, which holds
+/// exactly its `#[repr(C)]` layout.
+pub fn decode_c(idl: &Idl, ty: &IdlType, data: &[u8]) -> Result<JsonValue> {
+    if let Some(fields) = c_fields(idl, ty)? {
+        let (offsets, _) = c_struct_layout(idl, fields.iter().map(|(_, ty)| ty))?;
+        let mut obj = Map::new();
+        for ((name, ty), offset) in fields.iter().zip(offsets) {
+            let size = c_layout(idl, ty)?.size;
+            let value = decode_c(idl, ty, &data[offset..offset + size])
+                .map_err(|e| anyhow!("Invalid field {}: {}", name, e))?;
+            obj.insert(name.clone(), value);
+        }
+        return Ok(JsonValue::Object(obj));
+    }
+    match ty {
+        IdlType::Array(item, len) => {
+            let size = c_layout(idl, item)?.size;
+            (0..*len)
+                .map(|idx| decode_c(idl, item, &data[idx * size..(idx + 1) * size]))
+                .collect::<Result<Vec<_>>>()
+                .map(Into::into)
+        }
+        // Enums are a single byte, as with borsh.
+        _ => decode(idl, ty, &mut &data[..]),
+    }
+}
+
+/// Serializes `value` as the zero copy type `ty` into `out`, which must be
+/// zeroed and hold exactly its `#[repr(C)]` layout. Padding is left zeroed.
+pub fn encode_c(idl: &Idl, ty: &IdlType, value: &JsonValue, out: &mut [u8]) -> Result<()> {
+    if let Some(fields) = c_fields(idl, ty)? {
+        let (offsets, _) = c_struct_layout(idl, fields.iter().map(|(_, ty)| ty))?;
+        for ((name, ty), offset) in fields.iter().zip(offsets) {
+            let field_value = value
+                .get(name)
+                .or_else(|| value.get(&name.to_snake_case()))
+                .ok_or_else(|| anyhow!("Missing field {}", name))?;
+            let size = c_layout(idl, ty)?.size;
+            encode_c(idl, ty, field_value, &mut out[offset..offset + size])
+                .map_err(|e| anyhow!("Invalid field {}: {}", name, e))?;
+        }
+        return Ok(());
+    }
+    match ty {
+        IdlType::Array(item, len) => {
+            let items = as_array(value)?;
+            if items.len() != *len {
+                return Err(anyhow!(
+                    "Expected an array of {} items, found {}",
+                    len,
+                    items.len()
+                ));
+            }
+            let size = c_layout(idl, item)?.size;
+            for (idx, item_value) in items.iter().enumerate() {
+                let out = &mut out[idx * size..(idx + 1) * size];
+                encode_c(idl, item, item_value, out)?;
+            }
+            Ok(())
+        }
+        _ => {
+            let mut data = Vec::with_capacity(out.len());
+            encode(idl, ty, value, &mut data)?;
+            if data.len() != out.len() {
+                return Err(anyhow!("{:?} isn't zero copy", ty));
+            }
+            out.copy_from_slice(&data);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // An IDL with nested defined types, enums with every kind of variant,
+    // and nested composite accounts.
+    pub(crate) fn idl() -> Idl {
+        serde_json::from_str(include_str!("../tests/fixtures/idl.json")).unwrap()
+    }
+
+    // Encodes `value`, checks it decodes back to itself, and returns the
+    // encoded bytes.
+    fn round_trip(idl: &Idl, ty: &IdlType, value: JsonValue) -> Vec<u8> {
+        let mut data = vec![];
+        encode(idl, ty, &value, &mut data).unwrap();
+        let mut rest = &data[..];
+        assert_eq!(decode(idl, ty, &mut rest).unwrap(), value);
+        assert!(rest.is_empty());
+        data
+    }
+
+    #[test]
+    fn integers() {
+        let idl = idl();
+        assert_eq!(round_trip(&idl, &IdlType::I8, json!(-1)), vec![0xff]);
+        assert_eq!(
+            round_trip(&idl, &IdlType::U64, json!(u64::MAX)),
+            u64::MAX.to_le_bytes()
+        );
+        // Integers wider than 64 bits are strings.
+        assert_eq!(
+            round_trip(&idl, &IdlType::U128, json!(u128::MAX.to_string())),
+            u128::MAX.to_le_bytes()
+        );
+        assert_eq!(
+            round_trip(&idl, &IdlType::I128, json!(i128::MIN.to_string())),
+            i128::MIN.to_le_bytes()
+        );
+
+        // Any integer may be given as a string.
+        let mut data = vec![];
+        encode(&idl, &IdlType::U32, &json!("42"), &mut data).unwrap();
+        assert_eq!(data, 42u32.to_le_bytes());
+
+        assert!(encode(&idl, &IdlType::U8, &json!(256), &mut vec![]).is_err());
+        assert!(encode(&idl, &IdlType::U64, &json!(-1), &mut vec![]).is_err());
+        assert!(decode(&idl, &IdlType::U128, &mut &[0u8; 15][..]).is_err());
+    }
+
+    #[test]
+    fn options_vecs_and_arrays() {
+        let idl = idl();
+        let option = IdlType::Option(Box::new(IdlType::U64));
+        assert_eq!(round_trip(&idl, &option, JsonValue::Null), vec![0]);
+        assert_eq!(
+            round_trip(&idl, &option, json!(7)),
+            [&[1u8][..], &7u64.to_le_bytes()].concat()
+        );
+
+        let array = IdlType::Array(Box::new(IdlType::U16), 3);
+        assert_eq!(
+            round_trip(&idl, &array, json!([1, 2, 3])),
+            vec![1, 0, 2, 0, 3, 0]
+        );
+        assert!(encode(&idl, &array, &json!([1, 2]), &mut vec![]).is_err());
+
+        let vec = IdlType::Vec(Box::new(IdlType::String));
+        assert_eq!(
+            round_trip(&idl, &vec, json!(["ab"])),
+            vec![1, 0, 0, 0, 2, 0, 0, 0, b'a', b'b']
+        );
+    }
+
+    #[test]
+    fn enums() {
+        let idl = idl();
+        let side = IdlType::Defined("Side".to_string());
+        assert_eq!(round_trip(&idl, &side, json!("Bid")), vec![0]);
+        assert_eq!(
+            round_trip(&idl, &side, json!({ "Ask": { "price": 5 } })),
+            [&[1u8][..], &5u64.to_le_bytes()].concat()
+        );
+        assert_eq!(
+            round_trip(&idl, &side, json!({ "Both": [7, "-1"] })),
+            [&[2u8, 7][..], &(-1i128).to_le_bytes()].concat()
+        );
+
+        assert!(encode(&idl, &side, &json!("Neither"), &mut vec![]).is_err());
+        assert!(encode(&idl, &side, &json!({ "Ask": null }), &mut vec![]).is_err());
+        assert!(decode(&idl, &side, &mut &[3u8][..]).is_err());
+    }
+
+    #[test]
+    fn nested_defined_types() {
+        let idl = idl();
+        let config = IdlType::Defined("Config".to_string());
+        assert_eq!(
+            round_trip(
+                &idl,
+                &config,
+                json!({ "fee": 10, "inner": { "flag": true, "delta": -3 } })
+            ),
+            [
+                &[1u8][..],
+                &10u64.to_le_bytes(),
+                &[1],
+                &(-3i64).to_le_bytes()
+            ]
+            .concat()
+        );
+        let missing = json!({ "fee": null, "inner": { "flag": true } });
+        assert!(encode(&idl, &config, &missing, &mut vec![]).is_err());
+    }
+
+    #[test]
+    fn accounts() {
+        let idl = idl();
+        let state = json!({
+            "authority": Pubkey::new_unique().to_string(),
+            "config": { "fee": null, "inner": { "flag": false, "delta": 1 } },
+            "side": { "Ask": { "price": 100 } },
+            "history": [u64::MAX.to_string(), "-170141183460469231731687303715884105728"],
+            "label": "main",
+            "limits": [1, 2, 65535],
+        });
+        let data = encode_account(&idl, "State", &state).unwrap();
+        assert_eq!(data[..8], account_discriminator("State", 1));
+        assert_eq!(find_account(&idl, &data).unwrap().name, "State");
+        assert_eq!(decode_account(&idl, "State", &data).unwrap(), state);
+
+        assert!(decode_account(&idl, "State", &data[1..]).is_err());
+        assert!(decode_account(&idl, "State", &data[..data.len() - 1]).is_err());
+    }
+
+    // The fixture IDL with a zero copy account, `Book`, whose fields need
+    // padding and contain a `ZeroCopyEnum` and every container.
+    fn zero_copy_idl() -> Idl {
+        let mut idl = idl();
+        idl.accounts.push(
+            serde_json::from_value(json!({
+                "name": "Book",
+                "serialization": "bytemuck",
+                "type": {
+                    "kind": "struct",
+                    "fields": [
+                        { "name": "side", "type": { "defined": "ZcSide" } },
+                        { "name": "price", "type": "u64" },
+                        { "name": "total", "type": "u128" },
+                        { "name": "events", "type": { "ringBuffer": ["u32", 2] } },
+                        { "name": "orders", "type": { "slab": [{ "defined": "Order" }, 2] } },
+                        { "name": "bits", "type": { "bitMap": 1 } },
+                    ]
+                }
+            }))
+            .unwrap(),
+        );
+        idl.types.push(
+            serde_json::from_value(json!({
+                "name": "ZcSide",
+                "serialization": "bytemuck",
+                "type": { "kind": "enum", "variants": [{ "name": "Bid" }, { "name": "Ask" }] }
+            }))
+            .unwrap(),
+        );
+        idl.types.push(
+            serde_json::from_value(json!({
+                "name": "Order",
+                "serialization": "bytemuck",
+                "type": {
+                    "kind": "struct",
+                    "fields": [
+                        { "name": "owner", "type": "publicKey" },
+                        { "name": "qty", "type": "u32" },
+                        { "name": "flag", "type": "u8" },
+                    ]
+                }
+            }))
+            .unwrap(),
+        );
+        idl
+    }
+
+    #[test]
+    fn c_layouts() {
+        let idl = zero_copy_idl();
+        let layout = |ty: IdlType| c_layout(&idl, &ty).unwrap();
+        // u128 is aligned to 8 bytes on BPF.
+        assert_eq!(layout(IdlType::U128), CLayout { size: 16, align: 8 });
+        assert_eq!(
+            layout(IdlType::Defined("ZcSide".to_string())),
+            CLayout { size: 1, align: 1 }
+        );
+        // Padded at the end, to a multiple of the alignment of `qty`.
+        let order = IdlType::Defined("Order".to_string());
+        assert_eq!(layout(order.clone()), CLayout { size: 40, align: 4 });
+        assert_eq!(
+            layout(IdlType::RingBuffer(Box::new(IdlType::U32), 2)).size,
+            16 + 8
+        );
+        assert_eq!(layout(IdlType::Slab(Box::new(order), 2)).size, 24 + 16 + 80);
+        assert_eq!(layout(IdlType::BitMap(3)).size, 24);
+        assert_eq!(
+            layout(IdlType::Defined("Book".to_string())).size,
+            8 + 8 + 16 + 24 + 120 + 8
+        );
+
+        assert!(c_layout(&idl, &IdlType::String).is_err());
+        // Enums with fields aren't zero copy.
+        assert!(c_layout(&idl, &IdlType::Defined("Side".to_string())).is_err());
+    }
+
+    #[test]
+    fn zero_copy_accounts() {
+        let idl = zero_copy_idl();
+        let owner = Pubkey::new_unique();
+        let order = json!({ "owner": owner.to_string(), "qty": 3, "flag": 1 });
+        let empty = json!({ "owner": Pubkey::default().to_string(), "qty": 0, "flag": 0 });
+        let book = json!({
+            "side": "Ask",
+            "price": 7,
+            "total": u128::MAX.to_string(),
+            "events": { "head": 1, "len": 1, "items": [0, 9] },
+            "orders": {
+                "len": 1,
+                "bump": 1,
+                "freeHead": 0,
+                "next": [u64::MAX, 0],
+                "items": [order, empty],
+            },
+            "bits": { "words": [5] },
+        });
+        let data = encode_account(&idl, "Book", &book).unwrap();
+        assert_eq!(data.len(), 8 + 184);
+        assert_eq!(find_account(&idl, &data).unwrap().name, "Book");
+        assert_eq!(decode_account(&idl, "Book", &data).unwrap(), book);
+
+        let data = &data[8..];
+        // The enum's discriminant, followed by padding up to `price`.
+        assert_eq!(data[..8], [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(data[8..16], 7u64.to_le_bytes());
+        assert_eq!(data[16..32], u128::MAX.to_le_bytes());
+        // The second item of the ring buffer.
+        assert_eq!(data[52..56], 9u32.to_le_bytes());
+        // The first item of the slab, after its header and `next` array,
+        // followed by the padding at the end of the item.
+        assert_eq!(data[96..128], owner.to_bytes());
+        assert_eq!(data[128..136], [3, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(data[176..184], 5u64.to_le_bytes());
+    }
+
+    #[test]
+    fn zero_copy_enum_checks_variant() {
+        let idl = zero_copy_idl();
+        let side = IdlType::Defined("ZcSide".to_string());
+        assert_eq!(decode_c(&idl, &side, &[1]).unwrap(), json!("Ask"));
+        assert!(decode_c(&idl, &side, &[2]).is_err());
+        let mut out = [0u8];
+        encode_c(&idl, &side, &json!("Ask"), &mut out).unwrap();
+        assert_eq!(out, [1]);
+    }
+}
@@ -23,6 +23,10 @@ pub struct ConfigOverride {
     /// Wallet override.
     #[clap(global = true, long = "provider.wallet")]
     pub wallet: Option<WalletPath>,
+    /// Anchor.toml profile to use. Defaults to the ANCHOR_PROFILE
+    /// environment variable.
+    #[clap(global = true, long)]
+    pub profile: Option<String>,
 }
 
 pub struct WithPath<T> {
@@ -89,6 +93,20 @@ impl Manifest {
         }
     }
 
+    // Canonicalized directories of all the path dependencies, e.g., other
+    // programs depended upon with the `cpi` feature. Relative paths are
+    // resolved against `manifest_dir`.
+    pub fn path_dependencies(&self, manifest_dir: &Path) -> Vec<PathBuf> {
+        self.dependencies
+            .values()
+            .filter_map(|dep| match dep {
+                cargo_toml::Dependency::Detailed(detail) => detail.path.as_ref(),
+                _ => None,
+            })
+            .filter_map(|path| manifest_dir.join(path).canonicalize().ok())
+            .collect()
+    }
+
     // Climbs each parent directory from the current dir until we find a Cargo.toml
     pub fn discover() -> Result<Option<WithPath<Manifest>>> {
         Manifest::discover_from_path(std::env::current_dir()?)
@@ -126,7 +144,54 @@ impl Deref for Manifest {
 }
 
 impl WithPath<Config> {
+    // Programs of the workspace, ordered such that every program comes after
+    // all the workspace programs it depends on.
     pub fn get_program_list(&self) -> Result<Vec<PathBuf>> {
+        let graph = self.program_graph()?;
+        let mut ordered = vec![];
+        for program in self.get_unordered_program_list()? {
+            visit_program(&program, &graph, &mut vec![], &mut ordered)?;
+        }
+        Ok(ordered)
+    }
+
+    // The program at `program`, and all the workspace programs it
+    // transitively depends on, in build order.
+    pub fn get_program_list_with_dependencies(&self, program: &Path) -> Result<Vec<PathBuf>> {
+        let graph = self.program_graph()?;
+        let mut ordered = vec![];
+        visit_program(program, &graph, &mut vec![], &mut ordered)?;
+        Ok(ordered)
+    }
+
+    // Path of the workspace program with the given lib name.
+    pub fn get_program_path(&self, program_name: &str) -> Result<PathBuf> {
+        for path in self.get_unordered_program_list()? {
+            if Manifest::from_path(path.join("Cargo.toml"))?.lib_name()? == program_name {
+                return Ok(path);
+            }
+        }
+        Err(anyhow!("{} is not part of the workspace", program_name))
+    }
+
+    // Maps each program of the workspace to the workspace programs it depends
+    // on through path dependencies.
+    pub fn program_graph(&self) -> Result<BTreeMap<PathBuf, Vec<PathBuf>>> {
+        let programs = self.get_unordered_program_list()?;
+        let mut graph = BTreeMap::new();
+        for program in &programs {
+            let cargo = Manifest::from_path(program.join("Cargo.toml"))?;
+            let deps = cargo
+                .path_dependencies(program)
+                .into_iter()
+                .filter(|dep| programs.contains(dep))
+                .collect();
+            graph.insert(program.clone(), deps);
+        }
+        Ok(graph)
+    }
+
+    fn get_unordered_program_list(&self) -> Result<Vec<PathBuf>> {
         // Canonicalize the workspace filepaths to compare with relative paths.
         let (members, exclude) = self.canonicalize_workspace()?;
 
@@ -202,6 +267,71 @@ impl WithPath<Config> {
         Ok((members, exclude))
     }
 
+    // Sets the address of a program in the Anchor.toml, within the selected
+    // profile if it configures the programs of the cluster. The file is
+    // edited as is, so that profiles, unexpanded values, comments and
+    // formatting are preserved.
+    pub fn write_program_address(
+        &self,
+        cluster: &Cluster,
+        name: &str,
+        address: &Pubkey,
+    ) -> Result<()> {
+        let mut doc: toml_edit::Document = fs::read_to_string(self.path())?.parse()?;
+        let cluster = cluster.to_string();
+        let in_profile = self.profile.as_ref().filter(|profile| {
+            doc.get("profile")
+                .and_then(|p| p.get(profile.as_str()))
+                .and_then(|p| p.get("programs"))
+                .and_then(|p| p.get(&cluster))
+                .is_some()
+        });
+
+        let mut table = doc.as_table_mut();
+        if let Some(profile) = in_profile {
+            table = toml_table_mut(toml_table_mut(table, "profile")?, profile)?;
+        }
+        let programs = toml_table_mut(toml_table_mut(table, "programs")?, &cluster)?;
+        let address = address.to_string();
+        match programs.get_mut(name) {
+            Some(toml_edit::Item::Table(deployment)) => {
+                deployment.insert("address", toml_edit::value(address));
+            }
+            Some(toml_edit::Item::Value(toml_edit::Value::InlineTable(deployment))) => {
+                deployment.insert("address", address.into());
+            }
+            _ => {
+                programs.insert(name, toml_edit::value(address));
+            }
+        }
+
+        fs::write(self.path(), doc.to_string())?;
+        Ok(())
+    }
+
+    // Sets the provider's cluster in the Anchor.toml, within the selected
+    // profile if it configures the cluster. Like `write_program_address`, the
+    // rest of the file is preserved as is.
+    pub fn write_provider_cluster(&self, cluster: &str) -> Result<()> {
+        let mut doc: toml_edit::Document = fs::read_to_string(self.path())?.parse()?;
+        let in_profile = self.profile.as_ref().filter(|profile| {
+            doc.get("profile")
+                .and_then(|p| p.get(profile.as_str()))
+                .and_then(|p| p.get("provider"))
+                .and_then(|p| p.get("cluster"))
+                .is_some()
+        });
+
+        let mut table = doc.as_table_mut();
+        if let Some(profile) = in_profile {
+            table = toml_table_mut(toml_table_mut(table, "profile")?, profile)?;
+        }
+        toml_table_mut(table, "provider")?.insert("cluster", toml_edit::value(cluster));
+
+        fs::write(self.path(), doc.to_string())?;
+        Ok(())
+    }
+
     pub fn get_program(&self, name: &str) -> Result<Option<WithPath<Program>>> {
         for program in self.read_all_programs()? {
             let cargo_toml = program.path.join("Cargo.toml");
@@ -249,6 +379,8 @@ pub struct Config {
     pub scripts: ScriptsConfig,
     pub workspace: WorkspaceConfig,
     pub test: Option<Test>,
+    // Name of the profile applied to the config, if any.
+    pub profile: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -282,6 +414,10 @@ pub struct WorkspaceConfig {
     pub exclude: Vec<String>,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     pub types: String,
+    /// Template of `anchor new`, i.e., the template the workspace was
+    /// initialized with.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub template: String,
 }
 
 #[derive(ArgEnum, Clap, Clone, PartialEq, Debug)]
@@ -290,9 +426,16 @@ pub enum BootstrapMode {
     Debian,
 }
 
+#[derive(ArgEnum, Clap, Clone, PartialEq, Debug)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
 #[derive(Debug, Clone)]
 pub struct BuildConfig {
     pub verifiable: bool,
+    pub force: bool,
     pub solana_version: Option<String>,
     pub docker_image: String,
     pub bootstrap: BootstrapMode,
@@ -308,7 +451,11 @@ impl Config {
     }
 
     pub fn discover(cfg_override: &ConfigOverride) -> Result<Option<WithPath<Config>>> {
-        Config::_discover().map(|opt| {
+        let profile = cfg_override
+            .profile
+            .clone()
+            .or_else(|| std::env::var("ANCHOR_PROFILE").ok());
+        Config::_discover(profile.as_deref()).map(|opt| {
             opt.map(|mut cfg| {
                 if let Some(cluster) = cfg_override.cluster.clone() {
                     cfg.provider.cluster = cluster;
@@ -322,7 +469,7 @@ impl Config {
     }
 
     // Climbs each parent directory until we find an Anchor.toml.
-    fn _discover() -> Result<Option<WithPath<Config>>> {
+    fn _discover(profile: Option<&str>) -> Result<Option<WithPath<Config>>> {
         let _cwd = std::env::current_dir()?;
         let mut cwd_opt = Some(_cwd.as_path());
 
@@ -331,7 +478,7 @@ impl Config {
                 let p = f?.path();
                 if let Some(filename) = p.file_name() {
                     if filename.to_str() == Some("Anchor.toml") {
-                        let cfg = Config::from_path(&p)?;
+                        let cfg = Config::from_path(&p, profile)?;
                         return Ok(Some(WithPath::new(cfg, p)));
                     }
                 }
@@ -343,15 +490,87 @@ impl Config {
         Ok(None)
     }
 
-    fn from_path(p: impl AsRef<Path>) -> Result<Self> {
+    fn from_path(p: impl AsRef<Path>, profile: Option<&str>) -> Result<Self> {
         let mut cfg_file = File::open(&p)?;
         let mut cfg_contents = String::new();
         cfg_file.read_to_string(&mut cfg_contents)?;
-        let cfg = cfg_contents.parse()?;
+        let cfg = Config::parse(&cfg_contents, profile)
+            .map_err(|e| anyhow!("{}: {}", p.as_ref().display(), e))?;
 
         Ok(cfg)
     }
 
+    // Parses an Anchor.toml, applying the overrides of the given profile.
+    fn parse(s: &str, profile: Option<&str>) -> Result<Self> {
+        // Deserialize the file as written first, so that all profiles are
+        // validated and errors point to the offending line.
+        let cfg: _Config = toml::from_str(s)
+            .map_err(|e| anyhow::format_err!("Unable to deserialize config: {}", e.to_string()))?;
+        let cfg: _Config = match profile {
+            None => cfg,
+            Some(profile) => {
+                if !cfg
+                    .profile
+                    .as_ref()
+                    .map_or(false, |profiles| profiles.contains_key(profile))
+                {
+                    return Err(anyhow!("Profile {} not found", profile));
+                }
+                let mut value: toml::Value = toml::from_str(s)?;
+                let overrides = value["profile"][profile].clone();
+                merge_toml(&mut value, overrides);
+                value.try_into().map_err(|e| {
+                    anyhow::format_err!("Unable to apply profile {}: {}", profile, e.to_string())
+                })?
+            }
+        };
+
+        let provider = ProviderConfig {
+            cluster: expand_value(s, "cluster", &cfg.provider.cluster)?
+                .parse()
+                .map_err(|e| config_error(s, "cluster", &cfg.provider.cluster, e))?,
+            wallet: expand_value(s, "wallet", &cfg.provider.wallet)?.parse()?,
+        };
+        let registry = match cfg.registry {
+            None => RegistryConfig::default(),
+            Some(registry) => RegistryConfig {
+                url: expand_value(s, "url", &registry.url)?,
+            },
+        };
+        let test = match cfg.test {
+            Some(Test {
+                validator: Some(mut validator),
+                genesis,
+                clone,
+                startup_wait,
+            }) => {
+                validator.url = validator
+                    .url
+                    .map(|url| expand_value(s, "url", &url))
+                    .transpose()?;
+                Some(Test {
+                    validator: Some(validator),
+                    genesis,
+                    clone,
+                    startup_wait,
+                })
+            }
+            test => test,
+        };
+
+        Ok(Config {
+            anchor_version: cfg.anchor_version,
+            solana_version: cfg.solana_version,
+            registry,
+            provider,
+            scripts: cfg.scripts.unwrap_or_else(BTreeMap::new),
+            test,
+            programs: cfg.programs.map_or(Ok(BTreeMap::new()), deser_programs)?,
+            workspace: cfg.workspace.unwrap_or_default(),
+            profile: profile.map(ToString::to_string),
+        })
+    }
+
     pub fn wallet_kp(&self) -> Result<Keypair> {
         solana_sdk::signature::read_keypair_file(&self.provider.wallet.to_string())
             .map_err(|_| anyhow!("Unable to read keypair file"))
@@ -368,6 +587,7 @@ struct _Config {
     workspace: Option<WorkspaceConfig>,
     scripts: Option<ScriptsConfig>,
     test: Option<Test>,
+    profile: Option<BTreeMap<String, _Profile>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -376,6 +596,25 @@ struct Provider {
     wallet: String,
 }
 
+// A `[profile.<name>]` section, overriding the rest of the config when the
+// profile is selected. Tables are merged, so a profile only needs to set the
+// values it changes, e.g., `[profile.devnet.test.validator]`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct _Profile {
+    provider: Option<ProfileProvider>,
+    programs: Option<BTreeMap<String, BTreeMap<String, serde_json::Value>>>,
+    scripts: Option<ScriptsConfig>,
+    test: Option<Test>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileProvider {
+    cluster: Option<String>,
+    wallet: Option<String>,
+}
+
 impl ToString for Config {
     fn to_string(&self) -> String {
         let programs = {
@@ -400,8 +639,11 @@ impl ToString for Config {
                 false => Some(self.scripts.clone()),
             },
             programs,
-            workspace: (!self.workspace.members.is_empty() || !self.workspace.exclude.is_empty())
-                .then(|| self.workspace.clone()),
+            workspace: (!self.workspace.members.is_empty()
+                || !self.workspace.exclude.is_empty()
+                || !self.workspace.template.is_empty())
+            .then(|| self.workspace.clone()),
+            profile: None,
         };
 
         toml::to_string(&cfg).expect("Must be well formed")
@@ -412,21 +654,63 @@ impl FromStr for Config {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let cfg: _Config = toml::from_str(s)
-            .map_err(|e| anyhow::format_err!("Unable to deserialize config: {}", e.to_string()))?;
-        Ok(Config {
-            anchor_version: cfg.anchor_version,
-            solana_version: cfg.solana_version,
-            registry: cfg.registry.unwrap_or_default(),
-            provider: ProviderConfig {
-                cluster: cfg.provider.cluster.parse()?,
-                wallet: shellexpand::tilde(&cfg.provider.wallet).parse()?,
-            },
-            scripts: cfg.scripts.unwrap_or_else(BTreeMap::new),
-            test: cfg.test,
-            programs: cfg.programs.map_or(Ok(BTreeMap::new()), deser_programs)?,
-            workspace: cfg.workspace.unwrap_or_default(),
+        Config::parse(s, None)
+    }
+}
+
+// The table at `key`, inserted if it doesn't exist.
+fn toml_table_mut<'a>(
+    table: &'a mut toml_edit::Table,
+    key: &str,
+) -> Result<&'a mut toml_edit::Table> {
+    table
+        .entry(key)
+        .or_insert_with(|| {
+            // Only written as a header if it ends up with values of its own.
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            toml_edit::Item::Table(table)
         })
+        .as_table_mut()
+        .ok_or_else(|| anyhow!("{} must be a table", key))
+}
+
+// Merges `overrides` into `base`, recursing into tables and replacing all
+// other values.
+fn merge_toml(base: &mut toml::Value, overrides: toml::Value) {
+    match (base, overrides) {
+        (toml::Value::Table(base), toml::Value::Table(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_toml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+// Expands `~` and environment variables, e.g. `${RPC_URL}`, in a value of the
+// config.
+fn expand_value(src: &str, key: &str, value: &str) -> Result<String> {
+    shellexpand::full(value)
+        .map(|v| v.into_owned())
+        .map_err(|e| config_error(src, key, value, e))
+}
+
+// An error for an invalid value, pointing to the line of the source it's set
+// on, if found.
+fn config_error(src: &str, key: &str, value: &str, err: impl std::fmt::Display) -> Error {
+    let line = src.lines().position(|line| {
+        let line = line.trim_start();
+        line.starts_with(key) && line.contains(value)
+    });
+    match line {
+        Some(idx) => anyhow!("Invalid {} at line {}: {}", key, idx + 1, err),
+        None => anyhow!("Invalid {}: {}", key, err),
     }
 }
 
@@ -550,6 +834,18 @@ pub struct Validator {
     // Warp the ledger to WARP_SLOT after starting the validator.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub warp_slot: Option<String>,
+    // Load accounts from JSON files into the validator.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<Vec<AccountEntry>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountEntry {
+    // Base58 pubkey string.
+    pub address: String,
+    // Filepath to the account fixture, either in the format of
+    // `solana account --output json` or an Anchor typed account.
+    pub filename: String,
 }
 
 fn default_ledger_path() -> String {
@@ -564,6 +860,37 @@ fn default_rpc_port() -> u16 {
     8899
 }
 
+// Depth first search appending `program` to `ordered` after all of its
+// dependencies. `path` is the chain of programs currently being visited, used
+// to detect cycles.
+fn visit_program(
+    program: &Path,
+    graph: &BTreeMap<PathBuf, Vec<PathBuf>>,
+    path: &mut Vec<PathBuf>,
+    ordered: &mut Vec<PathBuf>,
+) -> Result<()> {
+    if ordered.iter().any(|p| p == program) {
+        return Ok(());
+    }
+    if path.iter().any(|p| p == program) {
+        return Err(anyhow!(
+            "Cyclic dependency between workspace programs: {}",
+            path.iter()
+                .chain(std::iter::once(&program.to_path_buf()))
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        ));
+    }
+    path.push(program.to_path_buf());
+    for dep in graph.get(program).into_iter().flatten() {
+        visit_program(dep, graph, path, ordered)?;
+    }
+    path.pop();
+    ordered.push(program.to_path_buf());
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub struct Program {
     pub lib_name: String,
@@ -586,9 +913,7 @@ impl Program {
     // Lazily initializes the keypair file with a new key if it doesn't exist.
     pub fn keypair_file(&self) -> Result<WithPath<File>> {
         fs::create_dir_all("target/deploy/")?;
-        let path = std::env::current_dir()
-            .expect("Must have current dir")
-            .join(format!("target/deploy/{}-keypair.json", self.lib_name));
+        let path = self.keypair_path();
         if path.exists() {
             return Ok(WithPath::new(File::open(&path)?, path));
         }
@@ -598,6 +923,12 @@ impl Program {
         Ok(WithPath::new(file, path))
     }
 
+    pub fn keypair_path(&self) -> PathBuf {
+        std::env::current_dir()
+            .expect("Must have current dir")
+            .join(format!("target/deploy/{}-keypair.json", self.lib_name))
+    }
+
     pub fn binary_path(&self) -> PathBuf {
         std::env::current_dir()
             .expect("Must have current dir")
@@ -672,3 +1003,196 @@ impl AnchorPackage {
 }
 
 serum_common::home_path!(WalletPath, ".config/solana/id.json");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A workspace in a fresh temporary directory, with a program for each
+    // entry of `programs`, depending on the programs listed with it.
+    fn workspace(name: &str, programs: &[(&str, &[&str])]) -> WithPath<Config> {
+        let dir = std::env::temp_dir()
+            .join("anchor-config-tests")
+            .join(format!("{}-{}", name, std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        for (program, deps) in programs {
+            let program_dir = dir.join("programs").join(program);
+            fs::create_dir_all(program_dir.join("src")).unwrap();
+            fs::write(program_dir.join("src/lib.rs"), "").unwrap();
+            let deps: String = deps
+                .iter()
+                .map(|dep| format!("{0} = {{ path = \"../{0}\" }}\n", dep))
+                .collect();
+            fs::write(
+                program_dir.join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{}\"\nversion = \"0.1.0\"\n\n[dependencies]\n{}",
+                    program, deps
+                ),
+            )
+            .unwrap();
+        }
+        WithPath::new(Config::default(), dir.join("Anchor.toml"))
+    }
+
+    fn names(paths: &[PathBuf]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn program_list_orders_dependencies_first() {
+        let cfg = workspace(
+            "order",
+            &[("a", &["b", "c"]), ("b", &["c"]), ("c", &[]), ("d", &[])],
+        );
+        let graph = cfg.program_graph().unwrap();
+        assert_eq!(graph.len(), 4);
+        let deps_of = |name: &str| {
+            let (_, deps) = graph.iter().find(|(p, _)| p.ends_with(name)).unwrap();
+            let mut deps = names(deps);
+            deps.sort();
+            deps
+        };
+        assert_eq!(deps_of("a"), vec!["b", "c"]);
+        assert_eq!(deps_of("b"), vec!["c"]);
+        assert!(deps_of("d").is_empty());
+
+        let ordered = names(&cfg.get_program_list().unwrap());
+        let position = |name: &str| ordered.iter().position(|p| p == name).unwrap();
+        assert_eq!(ordered.len(), 4);
+        assert!(position("c") < position("b"));
+        assert!(position("b") < position("a"));
+    }
+
+    #[test]
+    fn program_list_with_dependencies() {
+        let cfg = workspace(
+            "with-dependencies",
+            &[("a", &["b"]), ("b", &["c"]), ("c", &[]), ("d", &[])],
+        );
+        let b = cfg.get_program_path("b").unwrap();
+        assert_eq!(
+            names(&cfg.get_program_list_with_dependencies(&b).unwrap()),
+            vec!["c", "b"]
+        );
+        let d = cfg.get_program_path("d").unwrap();
+        assert_eq!(
+            names(&cfg.get_program_list_with_dependencies(&d).unwrap()),
+            vec!["d"]
+        );
+    }
+
+    #[test]
+    fn program_list_rejects_cycles() {
+        let cfg = workspace("cycle", &[("a", &["b"]), ("b", &["c"]), ("c", &["a"])]);
+        let err = cfg.get_program_list().unwrap_err().to_string();
+        assert!(err.starts_with("Cyclic dependency between workspace programs"));
+        // The cycle is reported from where it was entered back to itself.
+        let cycle: Vec<&str> = err
+            .split(": ")
+            .nth(1)
+            .unwrap()
+            .split(" -> ")
+            .map(|p| p.rsplit('/').next().unwrap())
+            .collect();
+        assert_eq!(cycle.len(), 4);
+        assert_eq!(cycle.first(), cycle.last());
+    }
+
+    const PROFILES: &str = r#"[provider]
+cluster = "localnet"
+wallet = "~/.config/solana/id.json"
+
+[programs.localnet]
+counter = "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS"
+
+[scripts]
+test = "mocha"
+
+[profile.devnet.provider]
+cluster = "devnet"
+
+[profile.devnet.programs.devnet]
+counter = "7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw"
+"#;
+
+    #[test]
+    fn merge_toml_merges_tables_and_replaces_values() {
+        let mut base: toml::Value =
+            toml::from_str("a = 1\nlist = [1, 2]\n[table]\nkept = true\nreplaced = \"old\"\n")
+                .unwrap();
+        let overrides: toml::Value =
+            toml::from_str("list = [3]\nadded = 2\n[table]\nreplaced = \"new\"\n").unwrap();
+        merge_toml(&mut base, overrides);
+        let expected: toml::Value = toml::from_str(
+            "a = 1\nlist = [3]\nadded = 2\n[table]\nkept = true\nreplaced = \"new\"\n",
+        )
+        .unwrap();
+        assert_eq!(base, expected);
+    }
+
+    #[test]
+    fn parse_applies_profile_overrides() {
+        let cfg = Config::parse(PROFILES, None).unwrap();
+        assert_eq!(cfg.provider.cluster, Cluster::Localnet);
+        assert_eq!(cfg.profile, None);
+
+        let cfg = Config::parse(PROFILES, Some("devnet")).unwrap();
+        assert_eq!(cfg.provider.cluster, Cluster::Devnet);
+        assert_eq!(cfg.profile.as_deref(), Some("devnet"));
+        // Settings the profile doesn't override are kept.
+        assert_eq!(cfg.scripts["test"], "mocha");
+        assert!(cfg.programs[&Cluster::Localnet].contains_key("counter"));
+        assert_eq!(
+            cfg.programs[&Cluster::Devnet]["counter"]
+                .address
+                .to_string(),
+            "7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw"
+        );
+
+        let err = Config::parse(PROFILES, Some("mainnet")).unwrap_err();
+        assert_eq!(err.to_string(), "Profile mainnet not found");
+    }
+
+    #[test]
+    fn parse_expands_values() {
+        std::env::set_var("ANCHOR_CONFIG_TEST_RPC_URL", "http://127.0.0.1:9000");
+        let src = PROFILES.replace(
+            "cluster = \"localnet\"",
+            "cluster = \"${ANCHOR_CONFIG_TEST_RPC_URL}\"",
+        );
+        let cfg = Config::parse(&src, None).unwrap();
+        assert_eq!(cfg.provider.cluster.url(), "http://127.0.0.1:9000");
+
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(
+            PathBuf::from(cfg.provider.wallet.to_string()),
+            home.join(".config/solana/id.json")
+        );
+        assert_eq!(
+            expand_value("", "url", "~/x/${ANCHOR_CONFIG_TEST_RPC_URL}").unwrap(),
+            format!("{}/x/http://127.0.0.1:9000", home.display())
+        );
+    }
+
+    #[test]
+    fn parse_errors_point_to_line() {
+        let src = PROFILES.replace(
+            "cluster = \"localnet\"",
+            "cluster = \"${ANCHOR_CONFIG_TEST_UNDEFINED}\"",
+        );
+        let err = Config::parse(&src, None).unwrap_err().to_string();
+        assert!(err.starts_with("Invalid cluster at line 2: "), "{}", err);
+
+        // The line is only given when it's found in the source.
+        let err = config_error(PROFILES, "url", "http://x", "bad url");
+        assert_eq!(err.to_string(), "Invalid url: bad url");
+        let err = config_error(PROFILES, "cluster", "devnet", "bad cluster");
+        assert_eq!(err.to_string(), "Invalid cluster at line 12: bad cluster");
+    }
+}
@@ -0,0 +1,469 @@
+//! In-process deployment of programs with the BPF upgradeable loader.
+//!
+//! Deploying happens in three phases. First, a buffer account is created (or
+//! an existing one is resumed). Then the program binary is written to it in
+//! chunks, one transaction per chunk. Finally, the buffer is either deployed
+//! as a new program or used to upgrade an existing one.
+//!
+//! The buffer keypair is persisted next to the program binary while the
+//! deploy is in progress, so that a failed deploy can be resumed by running
+//! the same command again, only rewriting the chunks that didn't land.
+
+use anyhow::{anyhow, Result};
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcSendTransactionConfig,
+};
+use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
+use solana_sdk::account_utils::StateMut;
+use solana_sdk::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::message::Message;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, write_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone)]
+pub struct DeployConfig {
+    /// Number of threads writing chunks concurrently.
+    pub parallelism: usize,
+    /// Number of times a chunk is retried before giving up.
+    pub max_retries: usize,
+    /// Close all other buffers owned by the authority after deploying.
+    pub close_buffers: bool,
+    /// HTTP headers sent with each request to the cluster.
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Default for DeployConfig {
+    fn default() -> Self {
+        Self {
+            parallelism: 8,
+            max_retries: 5,
+            close_buffers: false,
+            headers: BTreeMap::new(),
+        }
+    }
+}
+
+/// Deploys and upgrades programs, paying with and using `authority` as the
+/// upgrade authority.
+pub struct Deployer {
+    url: String,
+    authority: Keypair,
+    config: DeployConfig,
+}
+
+impl Deployer {
+    pub fn new(url: &str, authority: Keypair, config: DeployConfig) -> Self {
+        Self {
+            url: url.to_string(),
+            authority,
+            config,
+        }
+    }
+
+    /// Deploys the binary at `binary_path` to the address of
+    /// `program_keypair`, or upgrades it if it's already deployed.
+    pub fn deploy(&self, program_keypair: &Keypair, binary_path: &Path) -> Result<()> {
+        let client = self.rpc_client();
+        let program_id = program_keypair.pubkey();
+        let is_deployed = client
+            .get_account_with_commitment(&program_id, CommitmentConfig::confirmed())?
+            .value
+            .is_some();
+        if is_deployed {
+            return self.upgrade(&program_id, binary_path);
+        }
+
+        let program_data = read_binary(binary_path)?;
+        let (buffer, buffer_path) = self.write_buffer(binary_path, &program_data)?;
+
+        println!("Deploying program {}...", program_id);
+        let program_lamports =
+            client.get_minimum_balance_for_rent_exemption(UpgradeableLoaderState::program_len()?)?;
+        let ixs = bpf_loader_upgradeable::deploy_with_max_program_len(
+            &self.authority.pubkey(),
+            &program_id,
+            &buffer,
+            &self.authority.pubkey(),
+            program_lamports,
+            program_data.len() * 2,
+        )?;
+        self.send(&client, &ixs, &[&self.authority, program_keypair])
+            .map_err(|e| anyhow!("Failed to deploy program {}: {}", program_id, e))?;
+
+        self.finish(&buffer_path)
+    }
+
+    /// Upgrades the program at `program_id` to the binary at `binary_path`.
+    pub fn upgrade(&self, program_id: &Pubkey, binary_path: &Path) -> Result<()> {
+        let client = self.rpc_client();
+        let program_data = read_binary(binary_path)?;
+        let (buffer, buffer_path) = self.write_buffer(binary_path, &program_data)?;
+
+        println!("Upgrading program {}...", program_id);
+        let ix = bpf_loader_upgradeable::upgrade(
+            program_id,
+            &buffer,
+            &self.authority.pubkey(),
+            &self.authority.pubkey(),
+        );
+        self.send(&client, &[ix], &[&self.authority])
+            .map_err(|e| anyhow!("Failed to upgrade program {}: {}", program_id, e))?;
+
+        self.finish(&buffer_path)
+    }
+
+    /// Writes the binary at `binary_path` to a buffer without deploying it,
+    /// and transfers the buffer to `buffer_authority`, e.g., a multisig that
+    /// will later upgrade the program with it. Returns the buffer address.
+    pub fn write_buffer_only(
+        &self,
+        binary_path: &Path,
+        buffer_authority: &Pubkey,
+    ) -> Result<Pubkey> {
+        let client = self.rpc_client();
+        let program_data = read_binary(binary_path)?;
+        let (buffer, buffer_path) = self.write_buffer(binary_path, &program_data)?;
+
+        if buffer_authority != &self.authority.pubkey() {
+            let ix = bpf_loader_upgradeable::set_buffer_authority(
+                &buffer,
+                &self.authority.pubkey(),
+                buffer_authority,
+            );
+            self.send(&client, &[ix], &[&self.authority])
+                .map_err(|e| anyhow!("Failed to set buffer authority: {}", e))?;
+        }
+        // The buffer is complete, so there's nothing left to resume.
+        std::fs::remove_file(&buffer_path)?;
+
+        println!("Buffer: {}", buffer);
+        println!("Buffer authority: {}", buffer_authority);
+        Ok(buffer)
+    }
+
+    /// Sets the upgrade authority of `program_id` to `new_authority`, or
+    /// makes the program immutable if `None`.
+    pub fn set_upgrade_authority(
+        &self,
+        program_id: &Pubkey,
+        new_authority: Option<&Pubkey>,
+    ) -> Result<()> {
+        let client = self.rpc_client();
+        let ix = bpf_loader_upgradeable::set_upgrade_authority(
+            program_id,
+            &self.authority.pubkey(),
+            new_authority,
+        );
+        self.send(&client, &[ix], &[&self.authority])
+            .map_err(|e| anyhow!("Failed to set upgrade authority: {}", e))
+    }
+
+    pub fn authority(&self) -> Pubkey {
+        self.authority.pubkey()
+    }
+
+    /// Closes all buffers owned by the authority, returning their lamports to
+    /// it. Returns the closed buffer addresses.
+    pub fn close_buffers(&self) -> Result<Vec<Pubkey>> {
+        let client = self.rpc_client();
+        let authority = self.authority.pubkey();
+        let accounts = client.get_program_accounts_with_config(
+            &bpf_loader_upgradeable::ID,
+            RpcProgramAccountsConfig {
+                filters: Some(vec![RpcFilterType::Memcmp(Memcmp {
+                    offset: 5,
+                    bytes: MemcmpEncodedBytes::Binary(authority.to_string()),
+                    encoding: None,
+                })]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    data_slice: Some(UiDataSliceConfig {
+                        offset: 0,
+                        length: UpgradeableLoaderState::buffer_data_offset()?,
+                    }),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+                with_context: None,
+            },
+        )?;
+
+        let mut closed = vec![];
+        for (address, account) in accounts {
+            // Only buffers, not program data accounts, can match the filter,
+            // but check anyway.
+            if let Ok(UpgradeableLoaderState::Buffer {
+                authority_address: Some(buffer_authority),
+            }) = account.state()
+            {
+                if buffer_authority != authority {
+                    continue;
+                }
+                let ix = bpf_loader_upgradeable::close(&address, &authority, &authority);
+                self.send(&client, &[ix], &[&self.authority])
+                    .map_err(|e| anyhow!("Failed to close buffer {}: {}", address, e))?;
+                println!("Closed buffer {}", address);
+                closed.push(address);
+            }
+        }
+        Ok(closed)
+    }
+
+    // Creates or resumes the buffer for the binary at `binary_path` and
+    // writes `program_data` into it. Returns the buffer address and the path
+    // of its persisted keypair.
+    fn write_buffer(&self, binary_path: &Path, program_data: &[u8]) -> Result<(Pubkey, PathBuf)> {
+        let client = self.rpc_client();
+        let buffer_path = buffer_keypair_path(binary_path);
+        let authority = self.authority.pubkey();
+        let data_offset = UpgradeableLoaderState::buffer_data_offset()?;
+        let buffer_len = UpgradeableLoaderState::buffer_len(program_data.len())?;
+
+        // Resume the buffer from a previous, failed, attempt if possible.
+        let mut written: Option<Vec<u8>> = None;
+        let mut buffer = match buffer_path.exists() {
+            false => None,
+            true => Some(
+                read_keypair_file(&buffer_path)
+                    .map_err(|_| anyhow!("Unable to read {}", buffer_path.display()))?,
+            ),
+        };
+        if let Some(kp) = &buffer {
+            let account = client
+                .get_account_with_commitment(&kp.pubkey(), CommitmentConfig::confirmed())?
+                .value;
+            match account {
+                None => {}
+                Some(account) => {
+                    let is_ours = matches!(
+                        account.state(),
+                        Ok(UpgradeableLoaderState::Buffer {
+                            authority_address: Some(a),
+                        }) if a == authority
+                    );
+                    if is_ours && account.data.len() == buffer_len {
+                        println!("Resuming buffer {}", kp.pubkey());
+                        written = Some(account.data[data_offset..].to_vec());
+                    } else {
+                        if is_ours {
+                            // Left over from a binary of a different size.
+                            let ix =
+                                bpf_loader_upgradeable::close(&kp.pubkey(), &authority, &authority);
+                            self.send(&client, &[ix], &[&self.authority])?;
+                        }
+                        buffer = None;
+                    }
+                }
+            }
+        }
+
+        let buffer = match (buffer, &written) {
+            (Some(kp), Some(_)) => kp,
+            (buffer, _) => {
+                let kp = buffer.unwrap_or_else(Keypair::new);
+                write_keypair_file(&kp, &buffer_path)
+                    .map_err(|_| anyhow!("Unable to write {}", buffer_path.display()))?;
+                let lamports = client.get_minimum_balance_for_rent_exemption(buffer_len)?;
+                let ixs = bpf_loader_upgradeable::create_buffer(
+                    &authority,
+                    &kp.pubkey(),
+                    &authority,
+                    lamports,
+                    program_data.len(),
+                )?;
+                self.send(&client, &ixs, &[&self.authority, &kp])
+                    .map_err(|e| anyhow!("Failed to create buffer {}: {}", kp.pubkey(), e))?;
+                println!("Created buffer {}", kp.pubkey());
+                kp
+            }
+        };
+
+        // Only write the chunks that aren't already on chain.
+        let chunk_size = self.max_chunk_size(&buffer.pubkey());
+        let chunks: Vec<(usize, Vec<u8>)> = program_data
+            .chunks(chunk_size)
+            .enumerate()
+            .map(|(i, chunk)| (i * chunk_size, chunk.to_vec()))
+            .filter(|(offset, chunk)| match &written {
+                None => true,
+                Some(w) => &w[*offset..*offset + chunk.len()] != chunk.as_slice(),
+            })
+            .collect();
+        self.write_chunks(&buffer.pubkey(), chunks)
+            .map_err(|e| anyhow!("{}. Run the command again to resume.", e))?;
+
+        Ok((buffer.pubkey(), buffer_path))
+    }
+
+    // Writes the `(offset, bytes)` chunks to `buffer` using multiple threads.
+    fn write_chunks(&self, buffer: &Pubkey, chunks: Vec<(usize, Vec<u8>)>) -> Result<()> {
+        let total = chunks.len();
+        if total == 0 {
+            return Ok(());
+        }
+        let chunks = Arc::new(chunks);
+        let next = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicUsize::new(0));
+        let failure: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+
+        let threads: Vec<_> = (0..self.config.parallelism.clamp(1, total))
+            .map(|_| {
+                let chunks = chunks.clone();
+                let next = next.clone();
+                let done = done.clone();
+                let failure = failure.clone();
+                let client = self.rpc_client();
+                let authority = Keypair::from_bytes(&self.authority.to_bytes()).unwrap();
+                let buffer = *buffer;
+                let max_retries = self.config.max_retries;
+                std::thread::spawn(move || loop {
+                    let idx = next.fetch_add(1, Ordering::SeqCst);
+                    if idx >= chunks.len() || failure.lock().unwrap().is_some() {
+                        return;
+                    }
+                    let (offset, bytes) = &chunks[idx];
+                    let ix = bpf_loader_upgradeable::write(
+                        &buffer,
+                        &authority.pubkey(),
+                        *offset as u32,
+                        bytes.clone(),
+                    );
+                    let mut attempt = 0;
+                    loop {
+                        match send_instructions(&client, &[ix.clone()], &[&authority]) {
+                            Ok(()) => break,
+                            Err(_) if attempt < max_retries => attempt += 1,
+                            Err(e) => {
+                                *failure.lock().unwrap() = Some(format!(
+                                    "Failed to write chunk at offset {} after {} attempts: {}",
+                                    offset,
+                                    attempt + 1,
+                                    e
+                                ));
+                                return;
+                            }
+                        }
+                    }
+                    let count = done.fetch_add(1, Ordering::SeqCst) + 1;
+                    print!("\rWriting buffer: {}/{} chunks", count, chunks.len());
+                    std::io::stdout().flush().ok();
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread
+                .join()
+                .map_err(|_| anyhow!("Buffer write thread panicked"))?;
+        }
+        println!();
+
+        let failure = failure.lock().unwrap().take();
+        match failure {
+            Some(e) => Err(anyhow!(e)),
+            None => Ok(()),
+        }
+    }
+
+    // Removes the persisted buffer keypair, since the loader drains the
+    // buffer on deploy and upgrade, and optionally closes any other buffers.
+    fn finish(&self, buffer_path: &Path) -> Result<()> {
+        std::fs::remove_file(buffer_path)?;
+        if self.config.close_buffers {
+            self.close_buffers()?;
+        }
+        Ok(())
+    }
+
+    // Largest number of bytes that fits in a single write transaction.
+    fn max_chunk_size(&self, buffer: &Pubkey) -> usize {
+        let ix = bpf_loader_upgradeable::write(buffer, &self.authority.pubkey(), 0, vec![]);
+        let message = Message::new(&[ix], Some(&self.authority.pubkey()));
+        let num_signatures = message.header.num_required_signatures as usize;
+        // Leave room for the bytes' length prefix growing as data is added.
+        PACKET_DATA_SIZE - (1 + num_signatures * 64 + message.serialize().len()) - 4
+    }
+
+    fn send(&self, client: &RpcClient, ixs: &[Instruction], signers: &[&Keypair]) -> Result<()> {
+        let (recent_hash, _fee_calc) = client.get_recent_blockhash()?;
+        let tx = Transaction::new_signed_with_payer(
+            ixs,
+            Some(&self.authority.pubkey()),
+            signers,
+            recent_hash,
+        );
+        client.send_and_confirm_transaction_with_spinner_and_config(
+            &tx,
+            CommitmentConfig::confirmed(),
+            RpcSendTransactionConfig {
+                skip_preflight: true,
+                ..RpcSendTransactionConfig::default()
+            },
+        )?;
+        Ok(())
+    }
+
+    pub fn rpc_client(&self) -> RpcClient {
+        anchor_client::rpc_client(
+            &self.url,
+            &self.config.headers,
+            CommitmentConfig::confirmed(),
+        )
+    }
+}
+
+// Sends a transaction paid for by the first signer, without a spinner, so
+// that it can be used from multiple threads at once.
+fn send_instructions(client: &RpcClient, ixs: &[Instruction], signers: &[&Keypair]) -> Result<()> {
+    let (recent_hash, _fee_calc) = client.get_recent_blockhash()?;
+    let tx =
+        Transaction::new_signed_with_payer(ixs, Some(&signers[0].pubkey()), signers, recent_hash);
+    client.send_and_confirm_transaction(&tx)?;
+    Ok(())
+}
+
+fn read_binary(binary_path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(binary_path)
+        .map_err(|e| anyhow!("Unable to read program {}: {}", binary_path.display(), e))
+}
+
+// Path of the keypair file for the buffer of the binary at `binary_path`,
+// e.g., `target/deploy/my_program-buffer-keypair.json`.
+fn buffer_keypair_path(binary_path: &Path) -> PathBuf {
+    let stem = binary_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    binary_path.with_file_name(format!("{}-buffer-keypair.json", stem))
+}
+
+/// Prints an instruction in a form that can be copied into a proposal for a
+/// multisig, or any other offline signer, to execute. Along with its program,
+/// accounts and data, the instruction itself is printed bincode serialized and
+/// base58 encoded. No transaction message is printed, since it would embed a
+/// recent blockhash that expires long before a proposal is approved.
+pub fn print_instruction(ix: &Instruction) -> Result<()> {
+    println!("Program: {}", ix.program_id);
+    println!("Accounts:");
+    for meta in &ix.accounts {
+        println!(
+            "  {} (signer: {}, writable: {})",
+            meta.pubkey, meta.is_signer, meta.is_writable
+        );
+    }
+    println!("Data (base58): {}", bs58::encode(&ix.data).into_string());
+    println!(
+        "Instruction (base58, bincode serialized): {}",
+        bs58::encode(bincode::serialize(ix)?).into_string()
+    );
+    Ok(())
+}
@@ -0,0 +1,90 @@
+//! Account fixtures loaded into the local validator with `--account`.
+//!
+//! A fixture is either a raw account, in the JSON format of `solana account
+//! --output json` and `anchor account dump`, or an Anchor typed account,
+//! whose data is given as JSON and serialized with the program's IDL.
+
+use crate::coder;
+use anchor_syn::idl::Idl;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+use std::str::FromStr;
+
+/// An account as written by `solana account --output json`, which is what
+/// `solana-test-validator --account` expects.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountFixture {
+    pub pubkey: String,
+    pub account: UiAccount,
+}
+
+impl AccountFixture {
+    pub fn new(pubkey: &Pubkey, account: &Account) -> Self {
+        Self {
+            pubkey: pubkey.to_string(),
+            account: UiAccount::encode(pubkey, account, UiAccountEncoding::Base64, None, None),
+        }
+    }
+}
+
+/// An account of a type defined in a program's IDL, e.g.,
+///
+/// ```json
+/// {
+///   "program": "counter",
+///   "type": "Counter",
+///   "data": { "authority": "...", "count": 5 }
+/// }
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TypedAccountFixture {
+    /// Name of the workspace program defining the account type.
+    pub program: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub data: JsonValue,
+    /// Defaults to the program.
+    pub owner: Option<String>,
+    /// Defaults to the rent exempt minimum.
+    pub lamports: Option<u64>,
+    /// Size of the account, if larger than its serialized data.
+    pub space: Option<usize>,
+}
+
+impl TypedAccountFixture {
+    /// Serializes the account, including its discriminator, with the IDL of
+    /// the program, owned by `program_id` unless the fixture says otherwise.
+    pub fn to_account(&self, idl: &Idl, program_id: Pubkey) -> Result<Account> {
+        let mut data = coder::encode_account(idl, &self.ty, &self.data)?;
+        if let Some(space) = self.space {
+            if space < data.len() {
+                return Err(anyhow!(
+                    "Account {} needs {} bytes, but its space is {}",
+                    self.ty,
+                    data.len(),
+                    space
+                ));
+            }
+            data.resize(space, 0);
+        }
+
+        let owner = match &self.owner {
+            None => program_id,
+            Some(owner) => Pubkey::from_str(owner)?,
+        };
+        Ok(Account {
+            lamports: self
+                .lamports
+                .unwrap_or_else(|| Rent::default().minimum_balance(data.len())),
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        })
+    }
+}
@@ -0,0 +1,629 @@
+//! Compatibility of two versions of a program's IDL, used by `anchor idl diff`.
+//!
+//! A change is breaking if clients built against the old IDL can no longer
+//! talk to the program, e.g., because an instruction they send was removed,
+//! or because data they encode or decode is laid out differently. Changes
+//! clients built against the old IDL can ignore, e.g., a new instruction or a
+//! field appended to an event, are not. Fields may only be appended to a
+//! versioned account whose previous layout is kept, since the accounts
+//! created with that layout are too small for them.
+
+use anchor_syn::idl::{
+    EnumFields, Idl, IdlAccountItem, IdlEvent, IdlField, IdlInstruction, IdlType,
+    IdlTypeDefinition, IdlTypeDefinitionTy,
+};
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdlChange {
+    pub breaking: bool,
+    /// What changed, e.g., `instruction initialize` or `account Counter`.
+    pub item: String,
+    pub description: String,
+}
+
+impl fmt::Display for IdlChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.breaking {
+            true => "breaking",
+            false => "non-breaking",
+        };
+        write!(f, "{}: {}: {}", kind, self.item, self.description)
+    }
+}
+
+/// Changes from `old` to `new`, breaking ones first.
+pub fn diff(old: &Idl, new: &Idl) -> Vec<IdlChange> {
+    let mut changes = Changes::default();
+
+    diff_instructions(
+        &mut changes,
+        "instruction",
+        &old.instructions,
+        &new.instructions,
+    );
+    match (&old.state, &new.state) {
+        (Some(old), Some(new)) => {
+            diff_type_definition(&mut changes, "state", &old.strct, &new.strct);
+            diff_instructions(&mut changes, "state method", &old.methods, &new.methods);
+        }
+        (Some(_), None) => changes.breaking("state", "removed"),
+        (None, Some(_)) => changes.non_breaking("state", "added"),
+        (None, None) => {}
+    }
+    diff_by_name(
+        &mut changes,
+        "account",
+        &old.accounts,
+        &new.accounts,
+        |ty| &ty.name,
+        diff_type_definition,
+    );
+    diff_by_name(
+        &mut changes,
+        "type",
+        &old.types,
+        &new.types,
+        |ty| &ty.name,
+        diff_type_definition,
+    );
+
+    let no_events = vec![];
+    diff_by_name(
+        &mut changes,
+        "event",
+        old.events.as_ref().unwrap_or(&no_events),
+        new.events.as_ref().unwrap_or(&no_events),
+        |e| &e.name,
+        diff_event,
+    );
+
+    let no_errors = vec![];
+    diff_by_name(
+        &mut changes,
+        "error",
+        old.errors.as_ref().unwrap_or(&no_errors),
+        new.errors.as_ref().unwrap_or(&no_errors),
+        |e| &e.name,
+        |changes, item, old, new| {
+            if old.code != new.code {
+                changes.breaking(
+                    item,
+                    format!("code changed from {} to {}", old.code, new.code),
+                );
+            }
+            if old.msg != new.msg {
+                changes.non_breaking(item, "message changed");
+            }
+        },
+    );
+
+    diff_by_name(
+        &mut changes,
+        "constant",
+        &old.constants,
+        &new.constants,
+        |c| &c.name,
+        |changes, item, old, new| {
+            if old.ty != new.ty {
+                changes.breaking(
+                    item,
+                    format!(
+                        "type changed from {} to {}",
+                        type_name(&old.ty),
+                        type_name(&new.ty)
+                    ),
+                );
+            } else if old.value != new.value {
+                changes.non_breaking(
+                    item,
+                    format!("value changed from {} to {}", old.value, new.value),
+                );
+            }
+        },
+    );
+
+    let mut changes = changes.0;
+    // Stable, so changes of the same kind stay in the order of the IDL.
+    changes.sort_by_key(|change| !change.breaking);
+    changes
+}
+
+#[derive(Default)]
+struct Changes(Vec<IdlChange>);
+
+impl Changes {
+    fn breaking(&mut self, item: &str, description: impl Into<String>) {
+        self.push(true, item, description.into());
+    }
+
+    fn non_breaking(&mut self, item: &str, description: impl Into<String>) {
+        self.push(false, item, description.into());
+    }
+
+    fn push(&mut self, breaking: bool, item: &str, description: String) {
+        self.0.push(IdlChange {
+            breaking,
+            item: item.to_string(),
+            description,
+        });
+    }
+}
+
+/// Diffs items matched by name, with removed items breaking and new ones
+/// not.
+fn diff_by_name<T>(
+    changes: &mut Changes,
+    kind: &str,
+    old: &[T],
+    new: &[T],
+    name: impl Fn(&T) -> &String,
+    diff_item: impl Fn(&mut Changes, &str, &T, &T),
+) {
+    for old_item in old {
+        let item = format!("{} {}", kind, name(old_item));
+        match new.iter().find(|new_item| name(new_item) == name(old_item)) {
+            Some(new_item) => diff_item(changes, &item, old_item, new_item),
+            None => changes.breaking(&item, "removed"),
+        }
+    }
+    for new_item in new {
+        if !old.iter().any(|old_item| name(old_item) == name(new_item)) {
+            changes.non_breaking(&format!("{} {}", kind, name(new_item)), "added");
+        }
+    }
+}
+
+fn diff_instructions(
+    changes: &mut Changes,
+    kind: &str,
+    old: &[IdlInstruction],
+    new: &[IdlInstruction],
+) {
+    diff_by_name(changes, kind, old, new, |ix| &ix.name, diff_instruction);
+}
+
+fn diff_instruction(changes: &mut Changes, item: &str, old: &IdlInstruction, new: &IdlInstruction) {
+    // Accounts are passed in order, so any account added, removed or moved
+    // changes which account the program reads at each position.
+    let old_accounts = flatten_accounts(&old.accounts, "");
+    let new_accounts = flatten_accounts(&new.accounts, "");
+    for (i, (old, new)) in old_accounts.iter().zip(&new_accounts).enumerate() {
+        if old.name != new.name {
+            changes.breaking(
+                item,
+                format!("account {} is now {} (position {})", old.name, new.name, i),
+            );
+            continue;
+        }
+        if old.is_mut != new.is_mut {
+            changes.breaking(
+                item,
+                match new.is_mut {
+                    true => format!("account {} is now mutable", new.name),
+                    false => format!("account {} is no longer mutable", new.name),
+                },
+            );
+        }
+        if old.is_signer != new.is_signer {
+            changes.breaking(
+                item,
+                match new.is_signer {
+                    true => format!("account {} is now a signer", new.name),
+                    false => format!("account {} is no longer a signer", new.name),
+                },
+            );
+        }
+    }
+    for old in old_accounts.iter().skip(new_accounts.len()) {
+        changes.breaking(item, format!("account {} removed", old.name));
+    }
+    for new in new_accounts.iter().skip(old_accounts.len()) {
+        changes.breaking(item, format!("account {} added", new.name));
+    }
+
+    // Arguments are encoded in order, so only their names may change freely.
+    diff_fields(
+        changes, item, "argument", &old.args, &new.args, false, false,
+    );
+}
+
+struct FlatAccount {
+    name: String,
+    is_mut: bool,
+    is_signer: bool,
+}
+
+fn flatten_accounts(items: &[IdlAccountItem], prefix: &str) -> Vec<FlatAccount> {
+    let mut accounts = vec![];
+    for item in items {
+        match item {
+            IdlAccountItem::IdlAccount(acc) => accounts.push(FlatAccount {
+                name: format!("{}{}", prefix, acc.name),
+                is_mut: acc.is_mut,
+                is_signer: acc.is_signer,
+            }),
+            IdlAccountItem::IdlAccounts(accs) => accounts.extend(flatten_accounts(
+                &accs.accounts,
+                &format!("{}{}.", prefix, accs.name),
+            )),
+        }
+    }
+    accounts
+}
+
+fn diff_type_definition(
+    changes: &mut Changes,
+    item: &str,
+    old: &IdlTypeDefinition,
+    new: &IdlTypeDefinition,
+) {
+    if old.serialization != new.serialization {
+        changes.breaking(
+            item,
+            format!(
+                "serialization changed from {:?} to {:?}",
+                old.serialization, new.serialization
+            )
+            .to_lowercase(),
+        );
+    }
+    // Fields appended to an account don't fit in the accounts created with
+    // the old layout, unless the program keeps reading them, i.e., the old
+    // layout is kept under `versions`. Types may be followed by other data,
+    // so fields are never appended to them.
+    let old_version = old.version.unwrap_or(1);
+    let appendable = new
+        .versions
+        .iter()
+        .any(|version| version.version == old_version && version.ty == old.ty);
+    // Each version of a versioned account has its own discriminator.
+    if old.version != new.version {
+        let description = format!(
+            "version changed from {} to {}",
+            old_version,
+            new.version.unwrap_or(1)
+        );
+        match appendable {
+            true => changes.non_breaking(item, format!("{}, keeping the old layout", description)),
+            false => changes.breaking(item, description),
+        }
+    }
+    match (&old.ty, &new.ty) {
+        (
+            IdlTypeDefinitionTy::Struct { fields: old },
+            IdlTypeDefinitionTy::Struct { fields: new },
+        ) => diff_fields(changes, item, "field", old, new, appendable, true),
+        (
+            IdlTypeDefinitionTy::Enum { variants: old },
+            IdlTypeDefinitionTy::Enum { variants: new },
+        ) => {
+            // Variants are encoded by their index.
+            for (i, (old, new)) in old.iter().zip(new).enumerate() {
+                if old.name != new.name {
+                    changes.breaking(
+                        item,
+                        format!("variant {} is now {} (index {})", old.name, new.name, i),
+                    );
+                } else if old.fields != new.fields {
+                    let variant = format!("{} variant {}", item, new.name);
+                    match (&old.fields, &new.fields) {
+                        (Some(EnumFields::Named(old)), Some(EnumFields::Named(new))) => {
+                            diff_fields(changes, &variant, "field", old, new, false, true)
+                        }
+                        _ => changes.breaking(&variant, "fields changed"),
+                    }
+                }
+            }
+            for old in old.iter().skip(new.len()) {
+                changes.breaking(item, format!("variant {} removed", old.name));
+            }
+            for new in new.iter().skip(old.len()) {
+                changes.non_breaking(item, format!("variant {} added", new.name));
+            }
+        }
+        (IdlTypeDefinitionTy::Struct { .. }, IdlTypeDefinitionTy::Enum { .. }) => {
+            changes.breaking(item, "changed from a struct to an enum")
+        }
+        (IdlTypeDefinitionTy::Enum { .. }, IdlTypeDefinitionTy::Struct { .. }) => {
+            changes.breaking(item, "changed from an enum to a struct")
+        }
+    }
+}
+
+fn diff_event(changes: &mut Changes, item: &str, old: &IdlEvent, new: &IdlEvent) {
+    let fields = |event: &IdlEvent| -> Vec<IdlField> {
+        event
+            .fields
+            .iter()
+            .map(|f| IdlField {
+                name: f.name.clone(),
+                ty: f.ty.clone(),
+            })
+            .collect()
+    };
+    diff_fields(
+        changes,
+        item,
+        "field",
+        &fields(old),
+        &fields(new),
+        true,
+        true,
+    );
+    for (old, new) in old.fields.iter().zip(&new.fields) {
+        if old.name == new.name && old.index != new.index {
+            changes.non_breaking(item, format!("field {} index changed", new.name));
+        }
+    }
+}
+
+/// Diffs fields encoded in order. If `appendable`, the fields are the end of
+/// the data, so new fields aren't breaking, as clients built against the old
+/// IDL decode its prefix. If `named`, clients see the fields by name, so
+/// renaming them is breaking.
+fn diff_fields(
+    changes: &mut Changes,
+    item: &str,
+    kind: &str,
+    old: &[IdlField],
+    new: &[IdlField],
+    appendable: bool,
+    named: bool,
+) {
+    for (i, (old, new)) in old.iter().zip(new).enumerate() {
+        if old.ty != new.ty {
+            let moved = new.name != old.name;
+            changes.breaking(
+                item,
+                match moved {
+                    true => format!(
+                        "{} {}: {} is now {}: {} (position {})",
+                        kind,
+                        old.name,
+                        type_name(&old.ty),
+                        new.name,
+                        type_name(&new.ty),
+                        i
+                    ),
+                    false => format!(
+                        "{} {} type changed from {} to {}",
+                        kind,
+                        new.name,
+                        type_name(&old.ty),
+                        type_name(&new.ty)
+                    ),
+                },
+            );
+        } else if old.name != new.name {
+            let description = format!("{} {} renamed to {}", kind, old.name, new.name);
+            match named {
+                true => changes.breaking(item, description),
+                false => changes.non_breaking(item, description),
+            }
+        }
+    }
+    for old in old.iter().skip(new.len()) {
+        changes.breaking(item, format!("{} {} removed", kind, old.name));
+    }
+    for new in new.iter().skip(old.len()) {
+        let description = format!("{} {} added", kind, new.name);
+        match appendable {
+            true => changes.non_breaking(item, description),
+            false => changes.breaking(item, description),
+        }
+    }
+}
+
+fn type_name(ty: &IdlType) -> String {
+    match ty {
+        IdlType::Defined(name) => name.clone(),
+        IdlType::Option(ty) => format!("Option<{}>", type_name(ty)),
+        IdlType::Vec(ty) => format!("Vec<{}>", type_name(ty)),
+        IdlType::Array(ty, len) => format!("[{}; {}]", type_name(ty), len),
+        IdlType::RingBuffer(ty, len) => format!("RingBuffer<{}, {}>", type_name(ty), len),
+        IdlType::Slab(ty, len) => format!("Slab<{}, {}>", type_name(ty), len),
+        IdlType::BitMap(len) => format!("BitMap<{}>", len),
+        _ => serde_json::to_value(ty)
+            .ok()
+            .and_then(|v| v.as_str().map(ToString::to_string))
+            .unwrap_or_else(|| format!("{:?}", ty)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value as JsonValue};
+
+    // The fixture IDL, with an error, edited by `edit`.
+    fn idl_with(edit: impl Fn(&mut JsonValue)) -> Idl {
+        let mut idl: JsonValue =
+            serde_json::from_str(include_str!("../tests/fixtures/idl.json")).unwrap();
+        idl["errors"] = json!([{ "code": 6000, "name": "Unauthorized", "msg": "Unauthorized" }]);
+        edit(&mut idl);
+        serde_json::from_value(idl).unwrap()
+    }
+
+    #[test]
+    fn classify_changes() {
+        type Edit = fn(&mut JsonValue);
+        let cases: &[(&str, Edit, &[(bool, &str, &str)])] = &[
+            ("unchanged", |_| {}, &[]),
+            (
+                "reordered fields",
+                |idl| {
+                    let fields = idl["types"][1]["type"]["fields"].as_array_mut().unwrap();
+                    fields.swap(0, 1);
+                },
+                &[
+                    (
+                        true,
+                        "type Inner",
+                        "field flag: bool is now delta: i64 (position 0)",
+                    ),
+                    (
+                        true,
+                        "type Inner",
+                        "field delta: i64 is now flag: bool (position 1)",
+                    ),
+                ],
+            ),
+            (
+                "retyped field",
+                |idl| {
+                    idl["accounts"][0]["type"]["fields"][5]["type"] = json!({ "array": ["u32", 3] })
+                },
+                &[(
+                    true,
+                    "account State",
+                    "field limits type changed from [u16; 3] to [u32; 3]",
+                )],
+            ),
+            (
+                "renamed argument",
+                |idl| idl["instructions"][0]["args"][0]["name"] = json!("lamports"),
+                &[(
+                    false,
+                    "instruction initialize",
+                    "argument amount renamed to lamports",
+                )],
+            ),
+            (
+                "removed instruction",
+                |idl| {
+                    idl["instructions"].as_array_mut().unwrap().remove(1);
+                },
+                &[(true, "instruction setSide", "removed")],
+            ),
+            (
+                "changed mut and signer flags",
+                |idl| {
+                    let accounts = &mut idl["instructions"][0]["accounts"];
+                    accounts[0]["isMut"] = json!(false);
+                    accounts[1]["accounts"][0]["isSigner"] = json!(false);
+                    accounts[1]["accounts"][1]["accounts"][0]["isSigner"] = json!(true);
+                },
+                &[
+                    (
+                        true,
+                        "instruction initialize",
+                        "account state is no longer mutable",
+                    ),
+                    (
+                        true,
+                        "instruction initialize",
+                        "account auth.authority is no longer a signer",
+                    ),
+                    (
+                        true,
+                        "instruction initialize",
+                        "account auth.nested.vault is now a signer",
+                    ),
+                ],
+            ),
+            (
+                "changed error code",
+                |idl| idl["errors"][0]["code"] = json!(6001),
+                &[(true, "error Unauthorized", "code changed from 6000 to 6001")],
+            ),
+            (
+                "changed error message",
+                |idl| idl["errors"][0]["msg"] = json!("Not the authority"),
+                &[(false, "error Unauthorized", "message changed")],
+            ),
+            (
+                "appended account field",
+                |idl| {
+                    let fields = idl["accounts"][0]["type"]["fields"].as_array_mut().unwrap();
+                    fields.push(json!({ "name": "bump", "type": "u8" }));
+                },
+                &[(true, "account State", "field bump added")],
+            ),
+            (
+                "appended versioned account field",
+                |idl| {
+                    let account = &mut idl["accounts"][0];
+                    let layout = account["type"].clone();
+                    account["versions"] =
+                        json!([{ "version": 1, "name": "StateV1", "type": layout }]);
+                    account["version"] = json!(2);
+                    let fields = account["type"]["fields"].as_array_mut().unwrap();
+                    fields.push(json!({ "name": "bump", "type": "u8" }));
+                },
+                &[
+                    (
+                        false,
+                        "account State",
+                        "version changed from 1 to 2, keeping the old layout",
+                    ),
+                    (false, "account State", "field bump added"),
+                ],
+            ),
+            (
+                "appended versioned account field without the old layout",
+                |idl| {
+                    let account = &mut idl["accounts"][0];
+                    account["version"] = json!(2);
+                    let fields = account["type"]["fields"].as_array_mut().unwrap();
+                    fields.push(json!({ "name": "bump", "type": "u8" }));
+                },
+                &[
+                    (true, "account State", "version changed from 1 to 2"),
+                    (true, "account State", "field bump added"),
+                ],
+            ),
+            (
+                "appended type field",
+                |idl| {
+                    let fields = idl["types"][1]["type"]["fields"].as_array_mut().unwrap();
+                    fields.push(json!({ "name": "bump", "type": "u8" }));
+                },
+                &[(true, "type Inner", "field bump added")],
+            ),
+            (
+                "added enum variant",
+                |idl| {
+                    let variants = idl["types"][2]["type"]["variants"].as_array_mut().unwrap();
+                    variants.push(json!({ "name": "Neither" }));
+                },
+                &[(false, "type Side", "variant Neither added")],
+            ),
+            (
+                "reordered enum variants",
+                |idl| {
+                    let variants = idl["types"][2]["type"]["variants"].as_array_mut().unwrap();
+                    variants.swap(0, 1);
+                },
+                &[
+                    (true, "type Side", "variant Bid is now Ask (index 0)"),
+                    (true, "type Side", "variant Ask is now Bid (index 1)"),
+                ],
+            ),
+            (
+                "added instruction, breaking changes first",
+                |idl| {
+                    let instructions = idl["instructions"].as_array_mut().unwrap();
+                    instructions.insert(0, json!({ "name": "close", "accounts": [], "args": [] }));
+                    idl["accounts"][0]["type"]["fields"][1]["name"] = json!("settings");
+                },
+                &[
+                    (true, "account State", "field config renamed to settings"),
+                    (false, "instruction close", "added"),
+                ],
+            ),
+        ];
+
+        let old = idl_with(|_| {});
+        for (name, edit, expected) in cases {
+            let changes = diff(&old, &idl_with(edit));
+            let changes: Vec<(bool, &str, &str)> = changes
+                .iter()
+                .map(|c| (c.breaking, c.item.as_str(), c.description.as_str()))
+                .collect();
+            assert_eq!(changes, expected.to_vec(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Instructions built from the IDL of a program, with their accounts and
+//! arguments given as JSON, e.g., by `anchor shell --rust` and `anchor invoke`.
+//!
+//! Accounts are given as an object keyed by the IDL's account names, with
+//! nested objects for composite accounts. PDAs with seeds in the IDL and
+//! well known programs and sysvars can be omitted.
+
+use crate::coder;
+use anchor_syn::idl::{Idl, IdlAccountItem, IdlInstruction, IdlPda, IdlSeed, IdlType};
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value as JsonValue};
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::{system_program, sysvar};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Address of the account named `name`, if it's a well known program or
+/// sysvar.
+pub fn well_known_account(name: &str) -> Option<Pubkey> {
+    match name {
+        "systemProgram" => Some(system_program::ID),
+        "tokenProgram" => Pubkey::from_str("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA").ok(),
+        "associatedTokenProgram" => {
+            Pubkey::from_str("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL").ok()
+        }
+        "rent" => Some(sysvar::rent::ID),
+        "clock" => Some(sysvar::clock::ID),
+        _ => None,
+    }
+}
+
+pub fn build_instruction(
+    program_id: &Pubkey,
+    idl: &Idl,
+    ix: &IdlInstruction,
+    accounts: &JsonValue,
+    args: &JsonValue,
+) -> Result<Instruction> {
+    let data = coder::encode_instruction(idl, ix, args)?;
+    let mut metas = vec![];
+    resolve_accounts(
+        program_id,
+        idl,
+        &ix.accounts,
+        accounts,
+        args,
+        "",
+        &mut metas,
+    )?;
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: metas,
+        data,
+    })
+}
+
+/// Names of the accounts of an instruction, in order, with the accounts of
+/// composite accounts named `<composite>.<account>`.
+pub fn account_names(items: &[IdlAccountItem]) -> Vec<String> {
+    let mut names = vec![];
+    for item in items {
+        match item {
+            IdlAccountItem::IdlAccount(acc) => names.push(acc.name.clone()),
+            IdlAccountItem::IdlAccounts(accs) => names.extend(
+                account_names(&accs.accounts)
+                    .into_iter()
+                    .map(|name| format!("{}.{}", accs.name, name)),
+            ),
+        }
+    }
+    names
+}
+
+/// The signers of a transaction with `ix` paid for by `payer`, i.e., the
+/// payer and the keypairs of the instruction's other signers.
+pub fn signers<'a>(
+    ix: &Instruction,
+    payer: &'a Keypair,
+    keypairs: &'a [Keypair],
+) -> Result<Vec<&'a dyn Signer>> {
+    let mut signers: Vec<&dyn Signer> = vec![payer];
+    for meta in ix.accounts.iter().filter(|meta| meta.is_signer) {
+        if signers.iter().any(|s| s.pubkey() == meta.pubkey) {
+            continue;
+        }
+        let keypair = keypairs
+            .iter()
+            .find(|k| k.pubkey() == meta.pubkey)
+            .ok_or_else(|| anyhow!("Missing the keypair of signer {}", meta.pubkey))?;
+        signers.push(keypair);
+    }
+    Ok(signers)
+}
+
+/// Appends the metas of `items`, in order, to `metas`.
+fn resolve_accounts(
+    program_id: &Pubkey,
+    idl: &Idl,
+    items: &[IdlAccountItem],
+    given: &JsonValue,
+    args: &JsonValue,
+    prefix: &str,
+    metas: &mut Vec<AccountMeta>,
+) -> Result<()> {
+    let empty = Map::new();
+    let given = match given {
+        JsonValue::Null => &empty,
+        JsonValue::Object(given) => given,
+        _ => return Err(anyhow!("Expected an object of accounts, found {}", given)),
+    };
+    let name = |item: &IdlAccountItem| match item {
+        IdlAccountItem::IdlAccount(acc) => acc.name.clone(),
+        IdlAccountItem::IdlAccounts(accs) => accs.name.clone(),
+    };
+    if let Some(key) = given
+        .keys()
+        .find(|key| !items.iter().any(|i| &name(i) == *key))
+    {
+        return Err(anyhow!("Unknown account {}{}", prefix, key));
+    }
+
+    // PDAs are derived last, as their seeds may refer to any other account.
+    let mut addresses = HashMap::new();
+    for item in items {
+        if let IdlAccountItem::IdlAccount(acc) = item {
+            let address = match given.get(&acc.name) {
+                Some(value) => Some(
+                    parse_pubkey(value)
+                        .map_err(|e| anyhow!("Invalid account {}{}: {}", prefix, acc.name, e))?,
+                ),
+                None if acc.pda.is_none() => well_known_account(&acc.name),
+                None => None,
+            };
+            if let Some(address) = address {
+                addresses.insert(acc.name.clone(), address);
+            }
+        }
+    }
+    for item in items {
+        if let IdlAccountItem::IdlAccount(acc) = item {
+            if let (Some(pda), false) = (&acc.pda, addresses.contains_key(&acc.name)) {
+                let (address, _bump) = find_pda(program_id, idl, pda, &addresses, args)
+                    .map_err(|e| anyhow!("Unable to derive {}{}: {}", prefix, acc.name, e))?;
+                addresses.insert(acc.name.clone(), address);
+            }
+        }
+    }
+
+    for item in items {
+        match item {
+            IdlAccountItem::IdlAccount(acc) => {
+                let address = *addresses
+                    .get(&acc.name)
+                    .ok_or_else(|| anyhow!("Missing account {}{}", prefix, acc.name))?;
+                metas.push(match acc.is_mut {
+                    true => AccountMeta::new(address, acc.is_signer),
+                    false => AccountMeta::new_readonly(address, acc.is_signer),
+                });
+            }
+            IdlAccountItem::IdlAccounts(accs) => resolve_accounts(
+                program_id,
+                idl,
+                &accs.accounts,
+                given.get(&accs.name).unwrap_or(&JsonValue::Null),
+                args,
+                &format!("{}{}.", prefix, accs.name),
+                metas,
+            )?,
+        }
+    }
+    Ok(())
+}
+
+/// Derives the address and bump of the account at `path` of an instruction,
+/// e.g., `vault` or `composite.vault`, from its IDL seeds. Only the accounts
+/// its seeds refer to are needed.
+pub fn find_account_pda(
+    program_id: &Pubkey,
+    idl: &Idl,
+    ix: &IdlInstruction,
+    path: &str,
+    accounts: &JsonValue,
+    args: &JsonValue,
+) -> Result<(Pubkey, u8)> {
+    let mut items = &ix.accounts;
+    let mut given = accounts;
+    let mut names = path.split('.').peekable();
+    while let Some(name) = names.next() {
+        let item = items
+            .iter()
+            .find(|item| match item {
+                IdlAccountItem::IdlAccount(acc) => acc.name == name,
+                IdlAccountItem::IdlAccounts(accs) => accs.name == name,
+            })
+            .ok_or_else(|| anyhow!("Account {} not found in {}", path, ix.name))?;
+        match (item, names.peek()) {
+            (IdlAccountItem::IdlAccounts(accs), Some(_)) => {
+                items = &accs.accounts;
+                given = given.get(&accs.name).unwrap_or(&JsonValue::Null);
+            }
+            (IdlAccountItem::IdlAccount(acc), None) => {
+                let pda = acc
+                    .pda
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Account {} has no seeds in the IDL", path))?;
+                let mut addresses = HashMap::new();
+                for item in items {
+                    if let IdlAccountItem::IdlAccount(acc) = item {
+                        let address = match given.get(&acc.name) {
+                            Some(value) => Some(parse_pubkey(value)?),
+                            None => well_known_account(&acc.name),
+                        };
+                        if let Some(address) = address {
+                            addresses.insert(acc.name.clone(), address);
+                        }
+                    }
+                }
+                return find_pda(program_id, idl, pda, &addresses, args);
+            }
+            _ => break,
+        }
+    }
+    Err(anyhow!("Account {} not found in {}", path, ix.name))
+}
+
+/// Derives the address and bump of a PDA from its IDL seeds, given the
+/// addresses of the other accounts of its accounts struct and the
+/// instruction's arguments.
+pub fn find_pda(
+    program_id: &Pubkey,
+    idl: &Idl,
+    pda: &IdlPda,
+    accounts: &HashMap<String, Pubkey>,
+    args: &JsonValue,
+) -> Result<(Pubkey, u8)> {
+    let seeds = pda
+        .seeds
+        .iter()
+        .map(|seed| seed_bytes(idl, seed, accounts, args))
+        .collect::<Result<Vec<_>>>()?;
+    let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+    Ok(Pubkey::find_program_address(&seeds, program_id))
+}
+
+fn seed_bytes(
+    idl: &Idl,
+    seed: &IdlSeed,
+    accounts: &HashMap<String, Pubkey>,
+    args: &JsonValue,
+) -> Result<Vec<u8>> {
+    match seed {
+        IdlSeed::Const { value } => Ok(value.clone()),
+        IdlSeed::Account { path } => accounts
+            .get(path)
+            .map(|address| address.to_bytes().to_vec())
+            .ok_or_else(|| anyhow!("Missing account {}", path)),
+        IdlSeed::Arg { ty, path } => {
+            let value = args
+                .get(path)
+                .ok_or_else(|| anyhow!("Missing argument {}", path))?;
+            // Strings are used as bytes, without borsh's length prefix.
+            if let (IdlType::String, Some(s)) = (ty, value.as_str()) {
+                return Ok(s.as_bytes().to_vec());
+            }
+            let mut bytes = vec![];
+            coder::encode(idl, ty, value, &mut bytes)?;
+            Ok(bytes)
+        }
+    }
+}
+
+fn parse_pubkey(value: &JsonValue) -> Result<Pubkey> {
+    let s = value
+        .as_str()
+        .ok_or_else(|| anyhow!("Expected a public key, found {}", value))?;
+    Pubkey::from_str(s).map_err(|e| anyhow!("{}: {}", s, e))
+}
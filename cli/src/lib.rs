@@ -1,25 +1,33 @@
 use crate::config::{
-    AnchorPackage, BootstrapMode, BuildConfig, Config, ConfigOverride, Manifest, ProgramDeployment,
-    ProgramWorkspace, Test, WithPath,
+    AccountEntry, AnchorPackage, BootstrapMode, BuildConfig, Config, ConfigOverride, Manifest,
+    OutputFormat, Program, ProgramDeployment, ProgramWorkspace, RegistryConfig, Test, WithPath,
 };
-use anchor_client::Cluster;
+use crate::deploy::{print_instruction, DeployConfig, Deployer};
+use crate::fixture::{AccountFixture, TypedAccountFixture};
+use crate::logs::{LogDecoder, LogProgram, LogStream};
+use crate::shell::Shell;
+use crate::template::ProjectTemplate;
+use crate::tx::RawTransaction;
+use anchor_client::{Cluster, ClusterEndpoint, ClustersConfig};
 use anchor_lang::idl::{IdlAccount, IdlInstruction};
 use anchor_lang::{AccountDeserialize, AnchorDeserialize, AnchorSerialize};
-use anchor_syn::idl::Idl;
+use anchor_registry::{ApiError, PackageManifest, PackageVersions, IDL_FILE, WORKSPACE_FILE};
+use anchor_syn::idl::{Idl, IdlType};
 use anyhow::{anyhow, Context, Result};
 use clap::Clap;
 use flate2::read::GzDecoder;
 use flate2::read::ZlibDecoder;
 use flate2::write::{GzEncoder, ZlibEncoder};
 use flate2::Compression;
-use heck::SnakeCase;
+use heck::MixedCase;
 use rand::rngs::OsRng;
-use reqwest::blocking::multipart::{Form, Part};
 use reqwest::blocking::Client;
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
-use solana_client::rpc_client::RpcClient;
+use serde_json::Value as JsonValue;
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
 use solana_client::rpc_config::RpcSendTransactionConfig;
+use solana_client::rpc_response::{Response, RpcLogsResponse};
 use solana_program::instruction::{AccountMeta, Instruction};
 use solana_sdk::account_utils::StateMut;
 use solana_sdk::bpf_loader;
@@ -28,21 +36,32 @@ use solana_sdk::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Keypair;
+use solana_sdk::signature::Signature;
 use solana_sdk::signature::Signer;
 use solana_sdk::sysvar;
 use solana_sdk::transaction::Transaction;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::ffi::OsString;
 use std::fs::{self, File};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::process::{Child, Stdio};
+use std::str::FromStr;
 use std::string::ToString;
 use tar::Archive;
 
+pub mod coder;
 pub mod config;
+pub mod deploy;
+pub mod fixture;
+pub mod idl_diff;
+pub mod instruction;
+pub mod logs;
+pub mod shell;
 pub mod template;
+pub mod tx;
 
 // Version of the docker image.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -64,6 +83,10 @@ pub enum Command {
         name: String,
         #[clap(short, long)]
         javascript: bool,
+        /// Template of the workspace: basic, rust-tests, multiple-programs,
+        /// zero-copy, token-program, or the path of a template directory.
+        #[clap(short, long, default_value = "basic")]
+        template: ProjectTemplate,
     },
     /// Builds the workspace.
     Build {
@@ -76,6 +99,10 @@ pub enum Command {
         /// True if the build artifact needs to be deterministic and verifiable.
         #[clap(short, long)]
         verifiable: bool,
+        /// Rebuild all programs, even if they're unchanged since the last
+        /// build.
+        #[clap(long)]
+        force: bool,
         #[clap(short, long)]
         program_name: Option<String>,
         /// Version of the Solana toolchain to use. For --verifiable builds
@@ -136,6 +163,14 @@ pub enum Command {
         /// verifiable builds. Only works for debian-based images.
         #[clap(arg_enum, short, long, default_value = "none")]
         bootstrap: BootstrapMode,
+        /// Format of the verification report.
+        #[clap(arg_enum, short, long, default_value = "text")]
+        output: OutputFormat,
+        /// Verify against the workspace published to the registry as
+        /// `<name>@<version>`, or `<name>` for the latest version, rather
+        /// than the local one.
+        #[clap(long)]
+        from_registry: Option<String>,
         /// Arguments to pass to the underlying `cargo build-bpf` command.
         #[clap(
             required = false,
@@ -163,6 +198,18 @@ pub enum Command {
         /// to be able to check the transactions.
         #[clap(long)]
         detach: bool,
+        /// Only run the test files matching this glob, e.g.,
+        /// `tests/token*.ts`.
+        #[clap(long)]
+        file: Option<String>,
+        /// Only run the tests whose name matches this pattern. Passed to the
+        /// test runner as `--grep`.
+        #[clap(long)]
+        grep: Option<String>,
+        /// Number of local validators to start, sharding the test files
+        /// across them.
+        #[clap(long, default_value = "1")]
+        parallel: usize,
         #[clap(multiple_values = true)]
         args: Vec<String>,
         /// Arguments to pass to the underlying `cargo build-bpf` command.
@@ -175,7 +222,12 @@ pub enum Command {
         cargo_args: Vec<String>,
     },
     /// Creates a new program.
-    New { name: String },
+    New {
+        name: String,
+        /// Template of the program, defaults to the workspace's template.
+        #[clap(short, long)]
+        template: Option<ProjectTemplate>,
+    },
     /// Commands for interacting with interface definitions.
     Idl {
         #[clap(subcommand)]
@@ -185,9 +237,33 @@ pub enum Command {
     Deploy {
         #[clap(short, long)]
         program_name: Option<String>,
+        /// Closes all other buffer accounts owned by the upgrade authority
+        /// once deployed, reclaiming their rent.
+        #[clap(long)]
+        close_buffers: bool,
+        /// Only writes the program to a buffer account, without deploying it,
+        /// and prints the instruction upgrading the program with it, e.g.,
+        /// to be proposed to a multisig upgrade authority.
+        #[clap(long)]
+        buffer_only: bool,
+        /// Authority to transfer the buffer to. Only used with
+        /// `--buffer-only`. Defaults to the program's upgrade authority.
+        #[clap(long)]
+        buffer_authority: Option<Pubkey>,
+    },
+    /// Runs the deploy migration script, or the pending Rust migrations of
+    /// the `migrations` crate.
+    Migrate {
+        /// Only run the Rust migrations up to and including this one.
+        #[clap(long)]
+        to: Option<u32>,
+        /// Print the pending Rust migrations without running them.
+        #[clap(long)]
+        dry_run: bool,
+        /// Create a `migrations` crate for Rust migrations.
+        #[clap(long)]
+        init: bool,
     },
-    /// Runs the deploy migration script.
-    Migrate,
     /// Deploys, initializes an IDL, and migrates all in one command.
     /// Upgrades a single program. The configured wallet must be the upgrade
     /// authority.
@@ -197,6 +273,19 @@ pub enum Command {
         program_id: Pubkey,
         /// Filepath to the new program binary.
         program_filepath: String,
+        /// Closes all other buffer accounts owned by the upgrade authority
+        /// once upgraded, reclaiming their rent.
+        #[clap(long)]
+        close_buffers: bool,
+        /// Only writes the program to a buffer account, without upgrading it,
+        /// and prints the instruction upgrading the program with it, e.g.,
+        /// to be proposed to a multisig upgrade authority.
+        #[clap(long)]
+        buffer_only: bool,
+        /// Authority to transfer the buffer to. Only used with
+        /// `--buffer-only`. Defaults to the program's upgrade authority.
+        #[clap(long)]
+        buffer_authority: Option<Pubkey>,
     },
     #[cfg(feature = "dev")]
     /// Runs an airdrop loop, continuously funding the configured wallet.
@@ -204,6 +293,56 @@ pub enum Command {
         #[clap(short, long)]
         url: Option<String>,
     },
+    /// Prints the dependency graph of the workspace's programs, in the order
+    /// they're built and deployed.
+    Graph,
+    /// Commands for inspecting and managing deployed programs.
+    Program {
+        #[clap(subcommand)]
+        subcmd: ProgramCommand,
+    },
+    /// Fetches an account and prints it as JSON, decoded with the IDL of its
+    /// program.
+    Account {
+        #[clap(subcommand)]
+        subcmd: Option<AccountCommand>,
+        /// Type of the account, as `<program>.<AccountType>`, e.g.,
+        /// `counter.Counter`. If omitted, the type is detected from the
+        /// account's discriminator.
+        account_type: Option<String>,
+        /// Address of the account.
+        address: Option<String>,
+    },
+    /// Sends an instruction of a program, encoded with its IDL.
+    Invoke {
+        /// Name of the program in Anchor.toml, or its address, to use its
+        /// IDL on chain.
+        program: String,
+        /// Name of the instruction, e.g., `initialize_mint`.
+        instruction: String,
+        /// Argument of the instruction, as `name=value`. Values are JSON,
+        /// e.g., `--arg 'config={"fee":5}'`, or strings otherwise.
+        #[clap(long = "arg", multiple_occurrences = true, number_of_values = 1)]
+        args: Vec<String>,
+        /// Account of the instruction, as `name=pubkey`, or
+        /// `composite.name=pubkey` for a composite's account. PDAs with
+        /// seeds in the IDL and well known programs and sysvars can be
+        /// omitted.
+        #[clap(long = "account", multiple_occurrences = true, number_of_values = 1)]
+        accounts: Vec<String>,
+        /// Keypair file of a signer other than the wallet.
+        #[clap(long = "signer", multiple_occurrences = true, number_of_values = 1)]
+        signers: Vec<String>,
+        /// Simulate the transaction and print its decoded logs, instead of
+        /// sending it.
+        #[clap(long)]
+        simulate: bool,
+    },
+    /// Commands for inspecting transactions.
+    Tx {
+        #[clap(subcommand)]
+        subcmd: TxCommand,
+    },
     /// Cluster commands.
     Cluster {
         #[clap(subcommand)]
@@ -211,7 +350,11 @@ pub enum Command {
     },
     /// Starts a node shell with an Anchor client setup according to the local
     /// config.
-    Shell,
+    Shell {
+        /// Start the Rust shell instead, which needs no node toolchain.
+        #[clap(long)]
+        rust: bool,
+    },
     /// Runs the script defined by the current workspace's Anchor.toml.
     Run {
         /// The name of the script to run.
@@ -222,6 +365,16 @@ pub enum Command {
         /// API access token.
         token: String,
     },
+    /// Downloads the workspace of a published program, e.g.,
+    /// `anchor fetch my_program@0.1.0`, along with its IDL.
+    Fetch {
+        /// `<name>@<version>`, or `<name>` for the latest version.
+        package: String,
+        /// Directory to unpack the workspace into. Defaults to
+        /// `<name>-<version>`.
+        #[clap(short, long)]
+        out: Option<String>,
+    },
     /// Publishes a verified build to the Anchor registry.
     Publish {
         /// The name of the program to publish.
@@ -240,6 +393,16 @@ pub enum Command {
         #[clap(subcommand)]
         subcmd: KeysCommand,
     },
+    /// Streams the logs of transactions invoking the workspace's programs,
+    /// decoded with their IDLs.
+    Logs {
+        /// Only stream the logs of this program, given by name or address.
+        #[clap(short, long)]
+        program: Option<String>,
+        /// Print each transaction as a line of JSON.
+        #[clap(long)]
+        json: bool,
+    },
     /// Localnet commands.
     Localnet {
         /// Flag to skip building the program in the workspace,
@@ -263,7 +426,67 @@ pub enum Command {
 
 #[derive(Debug, Clap)]
 pub enum KeysCommand {
+    /// Lists the program ids of the workspace's programs.
     List,
+    /// Updates the `declare_id!` of each program, and its address in the
+    /// Anchor.toml section of the configured cluster, to match the program's
+    /// keypair in `target/deploy`.
+    Sync {
+        /// Only sync this program.
+        #[clap(short, long)]
+        program_name: Option<String>,
+    },
+    /// Generates a new keypair, and so a new program id, for a program.
+    New {
+        program_name: String,
+        /// Overwrite the program's existing keypair.
+        #[clap(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Clap)]
+pub enum ProgramCommand {
+    /// Shows the on chain state of a program or buffer account.
+    Show { program_id: Pubkey },
+    /// Sets the upgrade authority of a program. If the configured wallet
+    /// isn't the current authority, prints the instruction for the current
+    /// authority to execute instead.
+    SetUpgradeAuthority {
+        program_id: Pubkey,
+        /// New upgrade authority of the program.
+        #[clap(long)]
+        new_upgrade_authority: Option<Pubkey>,
+        /// Makes the program immutable, removing the upgrade authority.
+        #[clap(long = "final")]
+        make_final: bool,
+    },
+}
+
+#[derive(Debug, Clap)]
+pub enum AccountCommand {
+    /// Writes an account of the configured cluster to a JSON file, which can
+    /// be loaded into the local validator with `[[test.validator.account]]`.
+    Dump {
+        address: Pubkey,
+        /// Output file for the account. Defaults to stdout.
+        #[clap(short, long)]
+        out: Option<String>,
+    },
+}
+
+#[derive(Debug, Clap)]
+pub enum TxCommand {
+    /// Decodes the instructions of a transaction with the IDLs of their
+    /// programs, along with its events and errors if it was executed.
+    Decode {
+        /// Signature of the transaction to fetch, or the transaction itself,
+        /// serialized and encoded in base64 or base58.
+        transaction: String,
+        /// Print the decoded transaction as JSON.
+        #[clap(long)]
+        json: bool,
+    },
 }
 
 #[derive(Debug, Clap)]
@@ -339,22 +562,59 @@ pub enum IdlCommand {
         #[clap(short, long)]
         out: Option<String>,
     },
+    /// Lists the changes between two versions of an IDL, and exits with an
+    /// error if any of them breaks clients of the old version.
+    Diff {
+        /// The old IDL, as a file or the address of a program whose IDL is
+        /// fetched from the cluster.
+        old: String,
+        /// The new IDL, as a file or the address of a program whose IDL is
+        /// fetched from the cluster.
+        new: String,
+        /// Print the changes as JSON.
+        #[clap(long)]
+        json: bool,
+    },
 }
 
 #[derive(Debug, Clap)]
 pub enum ClusterCommand {
-    /// Prints common cluster urls.
+    /// Prints common cluster urls and the named clusters.
     List,
+    /// Saves a cluster under a name, which can be used wherever a cluster is
+    /// expected, e.g., `--provider.cluster <name>`.
+    Add {
+        name: String,
+        /// RPC url of the cluster.
+        url: String,
+        /// Websocket url of the cluster.
+        ws_url: String,
+        /// Commitment to use with the cluster, e.g., `confirmed`.
+        #[clap(long)]
+        commitment: Option<String>,
+        /// HTTP header to send with each request, as `<name>: <value>`.
+        #[clap(long = "header", multiple_occurrences = true)]
+        headers: Vec<String>,
+    },
+    /// Removes a named cluster.
+    Remove { name: String },
+    /// Sets the cluster of the workspace's Anchor.toml.
+    Use { cluster: String },
 }
 
 pub fn entry(opts: Opts) -> Result<()> {
     match opts.command {
-        Command::Init { name, javascript } => init(&opts.cfg_override, name, javascript),
-        Command::New { name } => new(&opts.cfg_override, name),
+        Command::Init {
+            name,
+            javascript,
+            template,
+        } => init(&opts.cfg_override, name, javascript, template),
+        Command::New { name, template } => new(&opts.cfg_override, name, template),
         Command::Build {
             idl,
             idl_ts,
             verifiable,
+            force,
             program_name,
             solana_version,
             docker_image,
@@ -365,6 +625,7 @@ pub fn entry(opts: Opts) -> Result<()> {
             idl,
             idl_ts,
             verifiable,
+            force,
             program_name,
             solana_version,
             docker_image,
@@ -379,6 +640,8 @@ pub fn entry(opts: Opts) -> Result<()> {
             solana_version,
             docker_image,
             bootstrap,
+            output,
+            from_registry,
             cargo_args,
         } => verify(
             &opts.cfg_override,
@@ -387,9 +650,22 @@ pub fn entry(opts: Opts) -> Result<()> {
             solana_version,
             docker_image,
             bootstrap,
+            output,
+            from_registry,
             cargo_args,
         ),
-        Command::Deploy { program_name } => deploy(&opts.cfg_override, program_name),
+        Command::Deploy {
+            program_name,
+            close_buffers,
+            buffer_only,
+            buffer_authority,
+        } => deploy(
+            &opts.cfg_override,
+            program_name,
+            close_buffers,
+            buffer_only,
+            buffer_authority,
+        ),
         Command::Expand {
             program_name,
             cargo_args,
@@ -397,14 +673,64 @@ pub fn entry(opts: Opts) -> Result<()> {
         Command::Upgrade {
             program_id,
             program_filepath,
-        } => upgrade(&opts.cfg_override, program_id, program_filepath),
+            close_buffers,
+            buffer_only,
+            buffer_authority,
+        } => upgrade(
+            &opts.cfg_override,
+            program_id,
+            program_filepath,
+            close_buffers,
+            buffer_only,
+            buffer_authority,
+        ),
+        Command::Program { subcmd } => program(&opts.cfg_override, subcmd),
+        Command::Account {
+            subcmd,
+            account_type,
+            address,
+        } => match subcmd {
+            Some(subcmd) => account(&opts.cfg_override, subcmd),
+            // With a single argument, it's the address.
+            None => match (account_type, address) {
+                (Some(account_type), Some(address)) => {
+                    account_show(&opts.cfg_override, Some(account_type), address.parse()?)
+                }
+                (Some(address), None) => account_show(&opts.cfg_override, None, address.parse()?),
+                _ => Err(anyhow!("Missing the address of the account")),
+            },
+        },
+        Command::Invoke {
+            program,
+            instruction,
+            args,
+            accounts,
+            signers,
+            simulate,
+        } => invoke(
+            &opts.cfg_override,
+            program,
+            instruction,
+            args,
+            accounts,
+            signers,
+            simulate,
+        ),
+        Command::Tx { subcmd } => tx(&opts.cfg_override, subcmd),
+        Command::Graph => graph(&opts.cfg_override),
         Command::Idl { subcmd } => idl(&opts.cfg_override, subcmd),
-        Command::Migrate => migrate(&opts.cfg_override),
+        Command::Migrate { to, dry_run, init } => match init {
+            true => migrate_init(&opts.cfg_override),
+            false => migrate(&opts.cfg_override, to, dry_run),
+        },
         Command::Test {
             skip_deploy,
             skip_local_validator,
             skip_build,
             detach,
+            file,
+            grep,
+            parallel,
             args,
             cargo_args,
         } => test(
@@ -413,20 +739,27 @@ pub fn entry(opts: Opts) -> Result<()> {
             skip_local_validator,
             skip_build,
             detach,
+            TestSelection {
+                file,
+                grep,
+                parallel,
+            },
             args,
             cargo_args,
         ),
         #[cfg(feature = "dev")]
         Command::Airdrop => airdrop(cfg_override),
-        Command::Cluster { subcmd } => cluster(subcmd),
-        Command::Shell => shell(&opts.cfg_override),
+        Command::Cluster { subcmd } => cluster(&opts.cfg_override, subcmd),
+        Command::Shell { rust } => shell(&opts.cfg_override, rust),
         Command::Run { script } => run(&opts.cfg_override, script),
         Command::Login { token } => login(&opts.cfg_override, token),
         Command::Publish {
             program,
             cargo_args,
         } => publish(&opts.cfg_override, program, cargo_args),
+        Command::Fetch { package, out } => fetch(&opts.cfg_override, package, out),
         Command::Keys { subcmd } => keys(&opts.cfg_override, subcmd),
+        Command::Logs { program, json } => logs(&opts.cfg_override, program, json),
         Command::Localnet {
             skip_build,
             skip_deploy,
@@ -435,7 +768,12 @@ pub fn entry(opts: Opts) -> Result<()> {
     }
 }
 
-fn init(cfg_override: &ConfigOverride, name: String, javascript: bool) -> Result<()> {
+fn init(
+    cfg_override: &ConfigOverride,
+    name: String,
+    javascript: bool,
+    template: ProjectTemplate,
+) -> Result<()> {
     if Config::discover(cfg_override)?.is_some() {
         return Err(anyhow!("Workspace already initialized"));
     }
@@ -461,75 +799,47 @@ fn init(cfg_override: &ConfigOverride, name: String, javascript: bool) -> Result
         ));
     }
 
+    let workspace = template.render_workspace(&name, javascript)?;
+
     fs::create_dir(name.clone())?;
     std::env::set_current_dir(&name)?;
-    fs::create_dir("app")?;
+    if !matches!(template, ProjectTemplate::Custom(_)) {
+        fs::create_dir("app")?;
+    }
 
-    let mut cfg = Config::default();
-    cfg.scripts.insert(
-        "test".to_owned(),
-        if javascript {
-            "yarn run mocha -t 1000000 tests/"
-        } else {
-            "yarn run ts-mocha -p ./tsconfig.json -t 1000000 tests/**/*.ts"
+    // Custom templates may bring their own Anchor.toml.
+    if !workspace.contains("Anchor.toml") {
+        let mut cfg = Config::default();
+        if let Some(test_script) = &workspace.test_script {
+            cfg.scripts.insert("test".to_owned(), test_script.clone());
         }
-        .to_owned(),
-    );
-    let mut localnet = BTreeMap::new();
-    localnet.insert(
-        name.to_snake_case(),
-        ProgramDeployment {
-            address: template::default_program_id(),
-            path: None,
-            idl: None,
-        },
-    );
-    cfg.programs.insert(Cluster::Localnet, localnet);
-    let toml = cfg.to_string();
-    let mut file = File::create("Anchor.toml")?;
-    file.write_all(toml.as_bytes())?;
-
-    // Build virtual manifest.
-    let mut virt_manifest = File::create("Cargo.toml")?;
-    virt_manifest.write_all(template::virtual_manifest().as_bytes())?;
-
-    // Initialize .gitignore file
-    let mut virt_manifest = File::create(".gitignore")?;
-    virt_manifest.write_all(template::git_ignore().as_bytes())?;
-
-    // Build the program.
-    fs::create_dir("programs")?;
-
-    new_program(&name)?;
-
-    // Build the test suite.
-    fs::create_dir("tests")?;
-    // Build the migrations directory.
-    fs::create_dir("migrations")?;
-
-    if javascript {
-        // Build javascript config
-        let mut package_json = File::create("package.json")?;
-        package_json.write_all(template::package_json().as_bytes())?;
-
-        let mut mocha = File::create(&format!("tests/{}.js", name))?;
-        mocha.write_all(template::mocha(&name).as_bytes())?;
-
-        let mut deploy = File::create("migrations/deploy.js")?;
-        deploy.write_all(template::deploy_script().as_bytes())?;
-    } else {
-        // Build typescript config
-        let mut ts_config = File::create("tsconfig.json")?;
-        ts_config.write_all(template::ts_config().as_bytes())?;
-
-        let mut ts_package_json = File::create("package.json")?;
-        ts_package_json.write_all(template::ts_package_json().as_bytes())?;
+        cfg.programs.insert(
+            Cluster::Localnet,
+            workspace
+                .programs
+                .iter()
+                .map(|(name, address)| {
+                    let deployment = ProgramDeployment {
+                        address: *address,
+                        path: None,
+                        idl: None,
+                    };
+                    (name.clone(), deployment)
+                })
+                .collect(),
+        );
+        // Recorded for `anchor new`.
+        if template != ProjectTemplate::Basic {
+            cfg.workspace.template = template.to_string();
+        }
+        fs::write("Anchor.toml", cfg.to_string())?;
+    }
 
-        let mut deploy = File::create("migrations/deploy.ts")?;
-        deploy.write_all(template::ts_deploy_script().as_bytes())?;
+    write_template_files(&workspace.files)?;
 
-        let mut mocha = File::create(&format!("tests/{}.ts", name))?;
-        mocha.write_all(template::ts_mocha(&name).as_bytes())?;
+    if !workspace.contains("package.json") {
+        println!("{} initialized", name);
+        return Ok(());
     }
 
     // Install node modules.
@@ -554,7 +864,11 @@ fn init(cfg_override: &ConfigOverride, name: String, javascript: bool) -> Result
 }
 
 // Creates a new program crate in the `programs/<name>` directory.
-fn new(cfg_override: &ConfigOverride, name: String) -> Result<()> {
+fn new(
+    cfg_override: &ConfigOverride,
+    name: String,
+    template: Option<ProjectTemplate>,
+) -> Result<()> {
     with_workspace(cfg_override, |cfg| {
         match cfg.path().parent() {
             None => {
@@ -562,7 +876,13 @@ fn new(cfg_override: &ConfigOverride, name: String) -> Result<()> {
             }
             Some(parent) => {
                 std::env::set_current_dir(&parent)?;
-                new_program(&name)?;
+                // Relative template paths are relative to the workspace root.
+                let template = match template {
+                    Some(template) => template,
+                    None if cfg.workspace.template.is_empty() => ProjectTemplate::Basic,
+                    None => cfg.workspace.template.parse()?,
+                };
+                new_program(&name, &template)?;
                 println!("Created new program.");
             }
         };
@@ -571,15 +891,21 @@ fn new(cfg_override: &ConfigOverride, name: String) -> Result<()> {
 }
 
 // Creates a new program crate in the current directory with `name`.
-fn new_program(name: &str) -> Result<()> {
-    fs::create_dir(&format!("programs/{}", name))?;
-    fs::create_dir(&format!("programs/{}/src/", name))?;
-    let mut cargo_toml = File::create(&format!("programs/{}/Cargo.toml", name))?;
-    cargo_toml.write_all(template::cargo_toml(name).as_bytes())?;
-    let mut xargo_toml = File::create(&format!("programs/{}/Xargo.toml", name))?;
-    xargo_toml.write_all(template::xargo_toml().as_bytes())?;
-    let mut lib_rs = File::create(&format!("programs/{}/src/lib.rs", name))?;
-    lib_rs.write_all(template::lib_rs(name).as_bytes())?;
+fn new_program(name: &str, template: &ProjectTemplate) -> Result<()> {
+    let program_dir = Path::new("programs").join(name);
+    if program_dir.exists() {
+        return Err(anyhow!("{} already exists", program_dir.display()));
+    }
+    write_template_files(&template.render_program(name)?)
+}
+
+fn write_template_files(files: &[(PathBuf, Vec<u8>)]) -> Result<()> {
+    for (path, contents) in files {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+    }
     Ok(())
 }
 
@@ -685,6 +1011,7 @@ pub fn build(
     idl: Option<String>,
     idl_ts: Option<String>,
     verifiable: bool,
+    force: bool,
     program_name: Option<String>,
     solana_version: Option<String>,
     docker_image: Option<String>,
@@ -693,14 +1020,10 @@ pub fn build(
     stderr: Option<File>, // Used for the package registry server.
     cargo_args: Vec<String>,
 ) -> Result<()> {
-    // Change to the workspace member directory, if needed.
-    if let Some(program_name) = program_name.as_ref() {
-        cd_member(cfg_override, program_name)?;
-    }
-
     let cfg = Config::discover(cfg_override)?.expect("Not in workspace.");
     let build_config = BuildConfig {
         verifiable,
+        force,
         solana_version: solana_version.or_else(|| cfg.solana_version.clone()),
         docker_image: docker_image.unwrap_or_else(|| cfg.docker()),
         bootstrap,
@@ -726,10 +1049,26 @@ pub fn build(
     };
 
     match cargo {
+        // A single program was requested. Build it along with the workspace
+        // programs it depends on.
+        _ if program_name.is_some() => build_all(
+            &cfg,
+            cfg.path(),
+            cfg.get_program_list_with_dependencies(
+                &cfg.get_program_path(program_name.as_ref().unwrap())?,
+            )?,
+            idl_out,
+            idl_ts_out,
+            &build_config,
+            stdout,
+            stderr,
+            cargo_args,
+        )?,
         // No Cargo.toml so build the entire workspace.
         None => build_all(
             &cfg,
             cfg.path(),
+            cfg.get_program_list()?,
             idl_out,
             idl_ts_out,
             &build_config,
@@ -741,6 +1080,7 @@ pub fn build(
         Some(cargo) if cargo.path().parent() == cfg.path().parent() => build_all(
             &cfg,
             cfg.path(),
+            cfg.get_program_list()?,
             idl_out,
             idl_ts_out,
             &build_config,
@@ -770,6 +1110,7 @@ pub fn build(
 fn build_all(
     cfg: &WithPath<Config>,
     cfg_path: &Path,
+    program_paths: Vec<PathBuf>,
     idl_out: Option<PathBuf>,
     idl_ts_out: Option<PathBuf>,
     build_config: &BuildConfig,
@@ -781,7 +1122,7 @@ fn build_all(
     let r = match cfg_path.parent() {
         None => Err(anyhow!("Invalid Anchor.toml at {}", cfg_path.display())),
         Some(_parent) => {
-            for p in cfg.get_program_list()? {
+            for p in program_paths {
                 build_cwd(
                     cfg,
                     p.join("Cargo.toml"),
@@ -816,8 +1157,16 @@ fn build_cwd(
         None => return Err(anyhow!("Unable to find parent")),
         Some(p) => std::env::set_current_dir(&p)?,
     };
+    check_program_id(cfg, &cargo_toml)?;
     match build_config.verifiable {
-        false => _build_cwd(cfg, idl_out, idl_ts_out, cargo_args),
+        false => _build_cwd(
+            cfg,
+            cargo_toml,
+            idl_out,
+            idl_ts_out,
+            build_config.force,
+            cargo_args,
+        ),
         true => build_cwd_verifiable(cfg, cargo_toml, build_config, stdout, stderr, cargo_args),
     }
 }
@@ -1119,23 +1468,60 @@ fn docker_exec(container_name: &str, args: &[&str]) -> Result<()> {
 
 fn _build_cwd(
     cfg: &WithPath<Config>,
+    cargo_toml: PathBuf,
     idl_out: Option<PathBuf>,
     idl_ts_out: Option<PathBuf>,
+    force: bool,
     cargo_args: Vec<String>,
 ) -> Result<()> {
-    let exit = std::process::Command::new("cargo")
-        .arg("build-bpf")
-        .args(cargo_args)
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .output()
-        .map_err(|e| anyhow::format_err!("{}", e.to_string()))?;
-    if !exit.status.success() {
-        std::process::exit(exit.status.code().unwrap_or(1));
+    let workspace_dir = cfg.path().parent().expect("Invalid Anchor.toml");
+    let lib_name = Manifest::from_path(&cargo_toml)?.lib_name()?;
+    let cache_dir = workspace_dir.join("target/.anchor-cache").join(&lib_name);
+    let binary = workspace_dir
+        .join("target/deploy")
+        .join(&lib_name)
+        .with_extension("so");
+
+    // Skip the build if nothing changed since the last one.
+    let fingerprint = build_fingerprint(
+        workspace_dir,
+        &cargo_toml,
+        &cargo_args,
+        &toolchain_version(),
+    )?;
+    if !force && is_build_fresh(&cache_dir, &binary, &fingerprint) {
+        println!("{} is up to date", lib_name);
+    } else {
+        // Invalidate the cache first, so that an interrupted build is never
+        // mistaken for a fresh one.
+        if cache_dir.exists() {
+            fs::remove_dir_all(&cache_dir)?;
+        }
+
+        let exit = std::process::Command::new("cargo")
+            .arg("build-bpf")
+            .args(&cargo_args)
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| anyhow::format_err!("{}", e.to_string()))?;
+        if !exit.status.success() {
+            std::process::exit(exit.status.code().unwrap_or(1));
+        }
+
+        // Always assume idl is located at src/lib.rs.
+        fs::create_dir_all(&cache_dir)?;
+        if let Some(idl) = extract_idl("src/lib.rs")? {
+            write_idl(&idl, OutFile::File(cache_dir.join("idl.json")))?;
+            fs::write(cache_dir.join("idl.ts"), template::idl_ts(&idl)?)?;
+        }
+        fs::write(cache_dir.join("fingerprint"), &fingerprint)?;
     }
 
-    // Always assume idl is located at src/lib.rs.
-    if let Some(idl) = extract_idl("src/lib.rs")? {
+    // Copy the IDL out of the cache.
+    let cached_idl = cache_dir.join("idl.json");
+    if cached_idl.exists() {
+        let idl: Idl = serde_json::from_slice(&fs::read(&cached_idl)?)?;
         // JSON out path.
         let out = match idl_out {
             None => PathBuf::from(".").join(&idl.name).with_extension("json"),
@@ -1148,15 +1534,14 @@ fn _build_cwd(
         };
 
         // Write out the JSON file.
-        write_idl(&idl, OutFile::File(out))?;
+        fs::copy(&cached_idl, out)?;
         // Write out the TypeScript type.
-        fs::write(&ts_out, template::idl_ts(&idl)?)?;
+        fs::copy(cache_dir.join("idl.ts"), &ts_out)?;
         // Copy out the TypeScript type.
-        let cfg_parent = cfg.path().parent().expect("Invalid Anchor.toml");
         if !&cfg.workspace.types.is_empty() {
             fs::copy(
                 &ts_out,
-                cfg_parent
+                workspace_dir
                     .join(&cfg.workspace.types)
                     .join(&idl.name)
                     .with_extension("ts"),
@@ -1167,6 +1552,80 @@ fn _build_cwd(
     Ok(())
 }
 
+// Whether the binary and the cache of the last build are for `fingerprint`.
+fn is_build_fresh(cache_dir: &Path, binary: &Path, fingerprint: &str) -> bool {
+    binary.exists()
+        && fs::read_to_string(cache_dir.join("fingerprint"))
+            .map(|f| f == fingerprint)
+            .unwrap_or(false)
+}
+
+// Identifies the toolchain programs are built with: the version of
+// `cargo build-bpf`, which is the one of the Solana tools and of the Rust
+// compiler they bundle, and the `RUSTFLAGS` it's invoked with.
+fn toolchain_version() -> String {
+    let version = std::process::Command::new("cargo")
+        .args(&["build-bpf", "--version"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+        .unwrap_or_default();
+    let rustflags = std::env::var("RUSTFLAGS").unwrap_or_default();
+    format!("{}\nRUSTFLAGS={}", version.trim(), rustflags)
+}
+
+// Hashes everything a program's build depends on: the sources of the program
+// and of its path dependencies, the workspace's Cargo.toml and Cargo.lock,
+// the arguments (e.g. features) passed to `cargo build-bpf`, the toolchain,
+// and the CLI version, which determines the generated IDL.
+fn build_fingerprint(
+    workspace_dir: &Path,
+    cargo_toml: &Path,
+    cargo_args: &[String],
+    toolchain: &str,
+) -> Result<String> {
+    let mut hasher = solana_sdk::hash::Hasher::default();
+    hasher.hash(VERSION.as_bytes());
+    hasher.hashv(&[toolchain.as_bytes(), &[0]]);
+    for arg in cargo_args {
+        hasher.hashv(&[arg.as_bytes(), &[0]]);
+    }
+    for file in &["Cargo.toml", "Cargo.lock"] {
+        let path = workspace_dir.join(file);
+        if path.exists() {
+            hasher.hash(&fs::read(path)?);
+        }
+    }
+
+    let program_dir = cargo_toml
+        .parent()
+        .ok_or_else(|| anyhow!("Unable to find parent"))?
+        .canonicalize()?;
+    let mut visited = BTreeSet::new();
+    let mut stack = vec![program_dir];
+    while let Some(dir) = stack.pop() {
+        if !visited.insert(dir.clone()) {
+            continue;
+        }
+        let entries = walkdir::WalkDir::new(&dir)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            .into_iter()
+            .filter_entry(|e| e.depth() == 0 || !is_hidden(e));
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type().is_file() {
+                let path = entry.path().to_string_lossy();
+                hasher.hashv(&[path.as_bytes(), &[0], &fs::read(entry.path())?]);
+            }
+        }
+        if let Ok(manifest) = Manifest::from_path(dir.join("Cargo.toml")) {
+            stack.extend(manifest.path_dependencies(&dir));
+        }
+    }
+
+    Ok(hasher.result().to_string())
+}
+
+#[allow(clippy::too_many_arguments)]
 fn verify(
     cfg_override: &ConfigOverride,
     program_id: Pubkey,
@@ -1174,30 +1633,67 @@ fn verify(
     solana_version: Option<String>,
     docker_image: Option<String>,
     bootstrap: BootstrapMode,
+    output: OutputFormat,
+    from_registry: Option<String>,
     cargo_args: Vec<String>,
 ) -> Result<()> {
+    // Switch to the published workspace, if needed.
+    let registry_manifest = match from_registry {
+        None => None,
+        Some(package) => {
+            let registry = registry_url(cfg_override)?;
+            let manifest = fetch_package_manifest(&registry, &package)?;
+            if manifest.address != program_id.to_string() {
+                return Err(anyhow!(
+                    "{}@{} was published for {}",
+                    manifest.name,
+                    manifest.version,
+                    manifest.address
+                ));
+            }
+            let dir = std::env::temp_dir()
+                .join("anchor-registry")
+                .join(format!("{}-{}", manifest.name, manifest.version));
+            if dir.exists() {
+                fs::remove_dir_all(&dir)?;
+            }
+            fetch_package_workspace(&registry, &manifest, &dir)?;
+            std::env::set_current_dir(&dir)?;
+            Some(manifest)
+        }
+    };
+    let program_name = program_name.or_else(|| registry_manifest.as_ref().map(|m| m.name.clone()));
+
     // Change to the workspace member directory, if needed.
     if let Some(program_name) = program_name.as_ref() {
         cd_member(cfg_override, program_name)?;
     }
 
-    // Proceed with the command.
+    // Proceed with the command. A published build is reproduced with the
+    // same toolchain.
     let cfg = Config::discover(cfg_override)?.expect("Not in workspace.");
     let cargo = Manifest::discover()?.ok_or_else(|| anyhow!("Cargo.toml not found"))?;
+    let solana_version = solana_version
+        .or_else(|| registry_manifest.as_ref()?.solana_version.clone())
+        .or_else(|| cfg.solana_version.clone());
+    let docker_image = docker_image
+        .or_else(|| registry_manifest.as_ref().map(|m| m.docker_image.clone()))
+        .unwrap_or_else(|| cfg.docker());
 
     // Build the program we want to verify.
     let cur_dir = std::env::current_dir()?;
     build(
         cfg_override,
-        None,                                                  // idl
-        None,                                                  // idl ts
-        true,                                                  // verifiable
-        None,                                                  // program name
-        solana_version.or_else(|| cfg.solana_version.clone()), // solana version
-        docker_image,                                          // docker image
-        bootstrap,                                             // bootstrap docker image
-        None,                                                  // stdout
-        None,                                                  // stderr
+        None,                       // idl
+        None,                       // idl ts
+        true,                       // verifiable
+        false,                      // force
+        None,                       // program name
+        solana_version.clone(),     // solana version
+        Some(docker_image.clone()), // docker image
+        bootstrap,                  // bootstrap docker image
+        None,                       // stdout
+        None,                       // stderr
         cargo_args,
     )?;
     std::env::set_current_dir(&cur_dir)?;
@@ -1211,29 +1707,127 @@ fn verify(
         .join("target/verifiable/")
         .join(format!("{}.so", binary_name));
 
-    let url = cluster_url(&cfg);
-    let bin_ver = verify_bin(program_id, &bin_path, &url)?;
-    if !bin_ver.is_verified {
-        println!("Error: Binaries don't match");
-        std::process::exit(1);
-    }
+    let client = cluster_client(&cfg);
+    let bin_ver = verify_bin(&client, program_id, &bin_path)?;
+
+    // Verify IDL (only if it's not a buffer account). A program without an
+    // IDL account, or with one that can't be read, still gets a report.
+    let (idl_verified, idl_error) = match extract_idl("src/lib.rs")? {
+        Some(local_idl) if bin_ver.state != BinVerificationState::Buffer => {
+            match fetch_idl(cfg_override, program_id) {
+                Ok(deployed_idl) => (Some(local_idl == deployed_idl), None),
+                Err(e) => (None, Some(e.to_string())),
+            }
+        }
+        _ => (None, None),
+    };
+
+    let (slot, slot_is_estimate, upgrade_authority) = match &bin_ver.state {
+        BinVerificationState::Buffer => (None, false, None),
+        BinVerificationState::ProgramData {
+            slot,
+            upgrade_authority_address,
+        } if bin_ver.owner == bpf_loader_upgradeable::id() => (
+            Some(*slot),
+            false,
+            upgrade_authority_address.map(|a| a.to_string()),
+        ),
+        BinVerificationState::ProgramData { .. } => {
+            (fetch_first_slot(&client, &program_id)?, true, None)
+        }
+    };
+    let registry_verified = registry_manifest
+        .as_ref()
+        .map(|manifest| manifest.binary_hash == bin_ver.local_hash);
+    let report = VerificationReport {
+        program_id: program_id.to_string(),
+        is_verified: bin_ver.is_verified
+            && idl_verified != Some(false)
+            && registry_verified != Some(false),
+        binary_verified: bin_ver.is_verified,
+        idl_verified,
+        idl_error,
+        registry_verified,
+        deployed_hash: bin_ver.deployed_hash,
+        local_hash: bin_ver.local_hash,
+        docker_image,
+        solana_version,
+        git_commit: git_commit(),
+        slot,
+        slot_is_estimate,
+        upgrade_authority,
+    };
 
-    // Verify IDL (only if it's not a buffer account).
-    if let Some(local_idl) = extract_idl("src/lib.rs")? {
-        if bin_ver.state != BinVerificationState::Buffer {
-            let deployed_idl = fetch_idl(cfg_override, program_id)?;
-            if local_idl != deployed_idl {
+    match output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Text => {
+            if !report.binary_verified {
+                println!("Error: Binaries don't match");
+            } else if report.idl_verified == Some(false) {
                 println!("Error: IDLs don't match");
-                std::process::exit(1);
+            } else if report.registry_verified == Some(false) {
+                println!("Error: Binary doesn't match the build published to the registry");
+            } else {
+                println!("{} is verified.", program_id);
+            }
+            if let Some(e) = &report.idl_error {
+                println!("Warning: Unable to fetch the deployed IDL: {}", e);
             }
         }
     }
-
-    println!("{} is verified.", program_id);
+    if !report.is_verified {
+        std::process::exit(1);
+    }
 
     Ok(())
 }
 
+/// Machine readable result of `anchor verify`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationReport {
+    pub program_id: String,
+    /// Whether both the binary and, if any, the IDL match.
+    pub is_verified: bool,
+    pub binary_verified: bool,
+    /// `None` if there's no IDL to compare, e.g., for buffer accounts.
+    pub idl_verified: Option<bool>,
+    /// Why the deployed IDL couldn't be compared, e.g., if the program has
+    /// no IDL account.
+    pub idl_error: Option<String>,
+    /// Whether the local binary matches the hash published to the registry,
+    /// with `--from-registry`.
+    pub registry_verified: Option<bool>,
+    /// Hex encoded sha256 of the deployed binary, without trailing zeros.
+    pub deployed_hash: String,
+    /// Hex encoded sha256 of the local binary, without trailing zeros.
+    pub local_hash: String,
+    pub docker_image: String,
+    pub solana_version: Option<String>,
+    /// Commit of the workspace the local binary was built from.
+    pub git_commit: Option<String>,
+    /// Slot the program was last deployed in. `None` for buffers.
+    pub slot: Option<u64>,
+    /// Whether `slot` is a best effort estimate, for programs of the
+    /// non-upgradeable loaders, which don't record it. It's then the slot of
+    /// the oldest transaction referencing the program that the RPC node
+    /// returned, or `None` if the program's history is too long to search.
+    pub slot_is_estimate: bool,
+    pub upgrade_authority: Option<String>,
+}
+
+// Current commit of the git repository in the working directory, if any.
+fn git_commit() -> Option<String> {
+    let output = std::process::Command::new("git")
+        .args(&["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+}
+
 fn cd_member(cfg_override: &ConfigOverride, program_name: &str) -> Result<()> {
     // Change directories to the given `program_name`, if given.
     let cfg = Config::discover(cfg_override)?.expect("Not in workspace.");
@@ -1255,71 +1849,18 @@ fn cd_member(cfg_override: &ConfigOverride, program_name: &str) -> Result<()> {
     return Err(anyhow!("{} is not part of the workspace", program_name,));
 }
 
-pub fn verify_bin(program_id: Pubkey, bin_path: &Path, cluster: &str) -> Result<BinVerification> {
-    let client = RpcClient::new(cluster.to_string());
-
+pub fn verify_bin(
+    client: &RpcClient,
+    program_id: Pubkey,
+    bin_path: &Path,
+) -> Result<BinVerification> {
     // Get the deployed build artifacts.
-    let (deployed_bin, state) = {
-        let account = client
-            .get_account_with_commitment(&program_id, CommitmentConfig::default())?
-            .value
-            .map_or(Err(anyhow!("Account not found")), Ok)?;
-        if account.owner == bpf_loader::id() || account.owner == bpf_loader_deprecated::id() {
-            let bin = account.data.to_vec();
-            let state = BinVerificationState::ProgramData {
-                slot: 0, // Need to look through the transaction history.
-                upgrade_authority_address: None,
-            };
-            (bin, state)
-        } else if account.owner == bpf_loader_upgradeable::id() {
-            match account.state()? {
-                UpgradeableLoaderState::Program {
-                    programdata_address,
-                } => {
-                    let account = client
-                        .get_account_with_commitment(
-                            &programdata_address,
-                            CommitmentConfig::default(),
-                        )?
-                        .value
-                        .map_or(Err(anyhow!("Account not found")), Ok)?;
-                    let bin = account.data
-                        [UpgradeableLoaderState::programdata_data_offset().unwrap_or(0)..]
-                        .to_vec();
-
-                    if let UpgradeableLoaderState::ProgramData {
-                        slot,
-                        upgrade_authority_address,
-                    } = account.state()?
-                    {
-                        let state = BinVerificationState::ProgramData {
-                            slot,
-                            upgrade_authority_address,
-                        };
-                        (bin, state)
-                    } else {
-                        return Err(anyhow!("Expected program data"));
-                    }
-                }
-                UpgradeableLoaderState::Buffer { .. } => {
-                    let offset = UpgradeableLoaderState::buffer_data_offset().unwrap_or(0);
-                    (
-                        account.data[offset..].to_vec(),
-                        BinVerificationState::Buffer,
-                    )
-                }
-                _ => {
-                    return Err(anyhow!(
-                        "Invalid program id, not a buffer or program account"
-                    ))
-                }
-            }
-        } else {
-            return Err(anyhow!(
-                "Invalid program id, not owned by any loader program"
-            ));
-        }
-    };
+    let DeployedBin {
+        bin: deployed_bin,
+        state,
+        owner,
+        ..
+    } = fetch_deployed_bin(client, program_id)?;
     let mut local_bin = {
         let mut f = File::open(bin_path)?;
         let mut contents = vec![];
@@ -1336,13 +1877,34 @@ pub fn verify_bin(program_id: Pubkey, bin_path: &Path, cluster: &str) -> Result<
     // Finally, check the bytes.
     let is_verified = local_bin == deployed_bin;
 
-    Ok(BinVerification { state, is_verified })
+    Ok(BinVerification {
+        state,
+        owner,
+        is_verified,
+        deployed_hash: bin_hash(&deployed_bin),
+        local_hash: bin_hash(&local_bin),
+    })
+}
+
+// Hex encoded sha256 of a program binary, ignoring the zero padding of
+// deployed programs.
+fn bin_hash(bin: &[u8]) -> String {
+    let len = bin.iter().rposition(|b| *b != 0).map_or(0, |idx| idx + 1);
+    solana_sdk::hash::hash(&bin[..len])
+        .to_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
 }
 
 #[derive(PartialEq)]
 pub struct BinVerification {
     pub state: BinVerificationState,
+    /// The loader owning the program.
+    pub owner: Pubkey,
     pub is_verified: bool,
+    pub deployed_hash: String,
+    pub local_hash: String,
 }
 
 #[derive(PartialEq)]
@@ -1354,27 +1916,142 @@ pub enum BinVerificationState {
     },
 }
 
-// Fetches an IDL for the given program_id.
-fn fetch_idl(cfg_override: &ConfigOverride, idl_addr: Pubkey) -> Result<Idl> {
-    let cfg = Config::discover(cfg_override)?.expect("Inside a workspace");
-    let url = cluster_url(&cfg);
-    let client = RpcClient::new(url);
-
-    let mut account = client
-        .get_account_with_commitment(&idl_addr, CommitmentConfig::processed())?
-        .value
-        .map_or(Err(anyhow!("Account not found")), Ok)?;
+pub struct DeployedBin {
+    pub bin: Vec<u8>,
+    pub state: BinVerificationState,
+    /// The loader owning the program.
+    pub owner: Pubkey,
+    /// Only set for programs owned by the upgradeable loader.
+    pub programdata_address: Option<Pubkey>,
+    /// Balance of the program account, plus its program data account.
+    pub lamports: u64,
+}
 
-    if account.executable {
-        let idl_addr = IdlAccount::address(&idl_addr);
-        account = client
-            .get_account_with_commitment(&idl_addr, CommitmentConfig::processed())?
-            .value
-            .map_or(Err(anyhow!("Account not found")), Ok)?;
+// Maximum number of pages of signatures searched by `fetch_first_slot`.
+const FIRST_SLOT_MAX_PAGES: usize = 10;
+// Number of signatures per page returned by `getSignaturesForAddress`.
+const SIGNATURES_PAGE_SIZE: usize = 1000;
+
+// Non-upgradeable programs can't be redeployed, so the slot they were
+// deployed in is the slot of the oldest transaction referencing them. This is
+// best effort: `None` if the program has more history than is searched, and
+// too recent if the RPC node doesn't retain the program's full history.
+fn fetch_first_slot(client: &RpcClient, address: &Pubkey) -> Result<Option<u64>> {
+    let mut before = None;
+    let mut first_slot = None;
+    for _ in 0..FIRST_SLOT_MAX_PAGES {
+        let signatures = client.get_signatures_for_address_with_config(
+            address,
+            GetConfirmedSignaturesForAddress2Config {
+                before,
+                ..GetConfirmedSignaturesForAddress2Config::default()
+            },
+        )?;
+        if let Some(oldest) = signatures.last() {
+            first_slot = Some(oldest.slot);
+            before = Some(oldest.signature.parse()?);
+        }
+        if signatures.len() < SIGNATURES_PAGE_SIZE {
+            return Ok(first_slot);
+        }
     }
+    Ok(None)
+}
 
-    // Cut off account discriminator.
-    let mut d: &[u8] = &account.data[8..];
+// Fetches the deployed binary of a program, or buffer, from any of the
+// loaders.
+pub fn fetch_deployed_bin(client: &RpcClient, program_id: Pubkey) -> Result<DeployedBin> {
+    let account = client
+        .get_account_with_commitment(&program_id, CommitmentConfig::default())?
+        .value
+        .map_or(Err(anyhow!("Account not found")), Ok)?;
+    if account.owner == bpf_loader::id() || account.owner == bpf_loader_deprecated::id() {
+        let bin = account.data.to_vec();
+        let state = BinVerificationState::ProgramData {
+            slot: 0, // Need to look through the transaction history.
+            upgrade_authority_address: None,
+        };
+        Ok(DeployedBin {
+            bin,
+            state,
+            owner: account.owner,
+            programdata_address: None,
+            lamports: account.lamports,
+        })
+    } else if account.owner == bpf_loader_upgradeable::id() {
+        match account.state()? {
+            UpgradeableLoaderState::Program {
+                programdata_address,
+            } => {
+                let programdata_account = client
+                    .get_account_with_commitment(&programdata_address, CommitmentConfig::default())?
+                    .value
+                    .map_or(Err(anyhow!("Account not found")), Ok)?;
+                let bin = programdata_account.data
+                    [UpgradeableLoaderState::programdata_data_offset().unwrap_or(0)..]
+                    .to_vec();
+
+                if let UpgradeableLoaderState::ProgramData {
+                    slot,
+                    upgrade_authority_address,
+                } = programdata_account.state()?
+                {
+                    let state = BinVerificationState::ProgramData {
+                        slot,
+                        upgrade_authority_address,
+                    };
+                    Ok(DeployedBin {
+                        bin,
+                        state,
+                        owner: account.owner,
+                        programdata_address: Some(programdata_address),
+                        lamports: account.lamports + programdata_account.lamports,
+                    })
+                } else {
+                    Err(anyhow!("Expected program data"))
+                }
+            }
+            UpgradeableLoaderState::Buffer { .. } => {
+                let offset = UpgradeableLoaderState::buffer_data_offset().unwrap_or(0);
+                Ok(DeployedBin {
+                    bin: account.data[offset..].to_vec(),
+                    state: BinVerificationState::Buffer,
+                    owner: account.owner,
+                    programdata_address: None,
+                    lamports: account.lamports,
+                })
+            }
+            _ => Err(anyhow!(
+                "Invalid program id, not a buffer or program account"
+            )),
+        }
+    } else {
+        Err(anyhow!(
+            "Invalid program id, not owned by any loader program"
+        ))
+    }
+}
+
+// Fetches an IDL for the given program_id.
+fn fetch_idl(cfg_override: &ConfigOverride, idl_addr: Pubkey) -> Result<Idl> {
+    let cfg = Config::discover(cfg_override)?.expect("Inside a workspace");
+    let client = cluster_client(&cfg);
+
+    let mut account = client
+        .get_account_with_commitment(&idl_addr, CommitmentConfig::processed())?
+        .value
+        .map_or(Err(anyhow!("Account not found")), Ok)?;
+
+    if account.executable {
+        let idl_addr = IdlAccount::address(&idl_addr);
+        account = client
+            .get_account_with_commitment(&idl_addr, CommitmentConfig::processed())?
+            .value
+            .map_or(Err(anyhow!("Account not found")), Ok)?;
+    }
+
+    // Cut off account discriminator.
+    let mut d: &[u8] = &account.data[8..];
     let idl_account: IdlAccount = AnchorDeserialize::deserialize(&mut d)?;
 
     let mut z = ZlibDecoder::new(&idl_account.data[..]);
@@ -1417,6 +2094,7 @@ fn idl(cfg_override: &ConfigOverride, subcmd: IdlCommand) -> Result<()> {
         IdlCommand::Authority { program_id } => idl_authority(cfg_override, program_id),
         IdlCommand::Parse { file, out, out_ts } => idl_parse(file, out, out_ts),
         IdlCommand::Fetch { address, out } => idl_fetch(cfg_override, address, out),
+        IdlCommand::Diff { old, new, json } => idl_diff(cfg_override, old, new, json),
     }
 }
 
@@ -1458,8 +2136,7 @@ fn idl_set_buffer(cfg_override: &ConfigOverride, program_id: Pubkey, buffer: Pub
     with_workspace(cfg_override, |cfg| {
         let keypair = solana_sdk::signature::read_keypair_file(&cfg.provider.wallet.to_string())
             .map_err(|_| anyhow!("Unable to read keypair file"))?;
-        let url = cluster_url(cfg);
-        let client = RpcClient::new(url);
+        let client = cluster_client(cfg);
 
         // Instruction to set the buffer onto the IdlAccount.
         let set_buffer_ix = {
@@ -1511,8 +2188,7 @@ fn idl_upgrade(
 
 fn idl_authority(cfg_override: &ConfigOverride, program_id: Pubkey) -> Result<()> {
     with_workspace(cfg_override, |cfg| {
-        let url = cluster_url(cfg);
-        let client = RpcClient::new(url);
+        let client = cluster_client(cfg);
         let idl_address = {
             let account = client
                 .get_account_with_commitment(&program_id, CommitmentConfig::processed())?
@@ -1549,8 +2225,7 @@ fn idl_set_authority(
         };
         let keypair = solana_sdk::signature::read_keypair_file(&cfg.provider.wallet.to_string())
             .map_err(|_| anyhow!("Unable to read keypair file"))?;
-        let url = cluster_url(cfg);
-        let client = RpcClient::new(url);
+        let client = cluster_client(cfg);
 
         // Instruction data.
         let data =
@@ -1620,8 +2295,7 @@ fn idl_write(cfg: &Config, program_id: &Pubkey, idl: &Idl, idl_address: Pubkey)
     // Misc.
     let keypair = solana_sdk::signature::read_keypair_file(&cfg.provider.wallet.to_string())
         .map_err(|_| anyhow!("Unable to read keypair file"))?;
-    let url = cluster_url(cfg);
-    let client = RpcClient::new(url);
+    let client = cluster_client(cfg);
 
     // Serialize and compress the idl.
     let idl_data = {
@@ -1699,6 +2373,40 @@ fn idl_fetch(cfg_override: &ConfigOverride, address: Pubkey, out: Option<String>
     write_idl(&idl, out)
 }
 
+fn idl_diff(cfg_override: &ConfigOverride, old: String, new: String, json: bool) -> Result<()> {
+    let read_idl = |source: &str| -> Result<Idl> {
+        match (Path::new(source).exists(), Pubkey::from_str(source)) {
+            (false, Ok(address)) => fetch_idl(cfg_override, address),
+            _ => {
+                let bytes = fs::read(source).map_err(|e| anyhow!("{}: {}", source, e))?;
+                serde_json::from_slice(&bytes).map_err(|e| anyhow!("{}: {}", source, e))
+            }
+        }
+    };
+    let changes = idl_diff::diff(&read_idl(&old)?, &read_idl(&new)?);
+    let breaking = changes.iter().filter(|change| change.breaking).count();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&changes)?);
+    } else if changes.is_empty() {
+        println!("No changes.");
+    } else {
+        for change in &changes {
+            println!("{}", change);
+        }
+        println!(
+            "\n{} breaking, {} non-breaking change(s).",
+            breaking,
+            changes.len() - breaking
+        );
+    }
+    if breaking > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
 fn write_idl(idl: &Idl, out: OutFile) -> Result<()> {
     let idl_json = serde_json::to_string_pretty(idl)?;
     match out {
@@ -1715,16 +2423,86 @@ enum OutFile {
 }
 
 // Builds, deploys, and tests all workspace programs in a single command.
+// Which tests `anchor test` runs, and across how many validators.
+struct TestSelection {
+    file: Option<String>,
+    grep: Option<String>,
+    parallel: usize,
+}
+
+#[allow(clippy::too_many_arguments)]
 fn test(
     cfg_override: &ConfigOverride,
     skip_deploy: bool,
     skip_local_validator: bool,
     skip_build: bool,
     detach: bool,
+    selection: TestSelection,
     extra_args: Vec<String>,
     cargo_args: Vec<String>,
 ) -> Result<()> {
     with_workspace(cfg_override, |cfg| {
+        let is_localnet = cfg.provider.cluster == Cluster::Localnet;
+        if selection.parallel == 0 {
+            return Err(anyhow!("--parallel must be at least 1"));
+        }
+        if selection.parallel > 1 && (!is_localnet || skip_local_validator) {
+            return Err(anyhow!(
+                "--parallel requires the localnet cluster and a local validator"
+            ));
+        }
+
+        // Split the test command into the test files it runs, and the rest of
+        // its arguments.
+        let cmd = cfg
+            .scripts
+            .get("test")
+            .expect("Not able to find command for `test`")
+            .clone();
+        let mut args: Vec<String> = cmd.split(' ').map(ToString::to_string).collect();
+        let program = args.remove(0);
+        let shards = match selection.file.is_some() || selection.parallel > 1 {
+            // Run the command as is.
+            false => vec![args],
+            true => {
+                let (patterns, args): (Vec<String>, Vec<String>) =
+                    args.into_iter().partition(|arg| is_test_files_arg(arg));
+                let patterns = match &selection.file {
+                    Some(file) => vec![file.clone()],
+                    None if patterns.is_empty() => {
+                        return Err(anyhow!(
+                            "Unable to find the test files in the test script: {}",
+                            cmd
+                        ))
+                    }
+                    None => patterns,
+                };
+                let files = expand_test_files(&patterns)?;
+                if files.is_empty() {
+                    return Err(anyhow!("No test files match {}", patterns.join(" ")));
+                }
+                shard_test_files(files, selection.parallel)
+                    .into_iter()
+                    .map(|files| {
+                        let mut args = args.clone();
+                        args.extend(files);
+                        args
+                    })
+                    .collect::<Vec<_>>()
+            }
+        };
+        let shards: Vec<Vec<String>> = shards
+            .into_iter()
+            .map(|mut args| {
+                if let Some(grep) = &selection.grep {
+                    args.push("--grep".to_string());
+                    args.push(grep.clone());
+                }
+                args.extend(extra_args.iter().cloned());
+                args
+            })
+            .collect();
+
         // Build if needed.
         if !skip_build {
             build(
@@ -1732,6 +2510,7 @@ fn test(
                 None,
                 None,
                 false,
+                false,
                 None,
                 None,
                 None,
@@ -1748,22 +2527,21 @@ fn test(
         // 2. The cluster is localnet, but we're not booting a local validator.
         //
         // In either case, skip the deploy if the user specifies.
-        let is_localnet = cfg.provider.cluster == Cluster::Localnet;
         if (!is_localnet || skip_local_validator) && !skip_deploy {
-            deploy(cfg_override, None)?;
+            deploy(cfg_override, None, false, false, None)?;
         }
-        // Start local test validator, if needed.
-        let mut validator_handle = None;
+        // Start a local test validator for each shard, if needed.
+        let mut validator_handles = vec![];
         if is_localnet && (!skip_local_validator) {
             let flags = match skip_deploy {
                 true => None,
                 false => Some(validator_flags(cfg)?),
             };
-            validator_handle = Some(start_test_validator(cfg, flags, true)?);
+            for instance in 0..shards.len() {
+                validator_handles.push(start_test_validator(cfg, flags.clone(), true, instance)?);
+            }
         }
 
-        let url = cluster_url(cfg);
-
         let node_options = format!(
             "{} {}",
             match std::env::var_os("NODE_OPTIONS") {
@@ -1775,32 +2553,64 @@ fn test(
             get_node_dns_option()?,
         );
 
-        // Setup log reader.
-        let log_streams = stream_logs(cfg, &url);
-
-        // Run the tests.
-        let test_result: Result<_> = {
-            let cmd = cfg
-                .scripts
-                .get("test")
-                .expect("Not able to find command for `test`")
-                .clone();
-            let mut args: Vec<&str> = cmd
-                .split(' ')
-                .chain(extra_args.iter().map(|arg| arg.as_str()))
-                .collect();
-            let program = args.remove(0);
+        // Setup log readers.
+        let mut log_streams = vec![];
+        for instance in 0..shards.len() {
+            let ws_url = cluster_ws_url(cfg, instance)?;
+            log_streams.push(stream_logs(cfg, &ws_url, instance, is_localnet));
+        }
 
-            std::process::Command::new(program)
-                .args(args)
-                .env("ANCHOR_PROVIDER_URL", url)
-                .env("ANCHOR_WALLET", cfg.provider.wallet.to_string())
-                .env("NODE_OPTIONS", node_options)
-                .stdout(Stdio::inherit())
-                .stderr(Stdio::inherit())
-                .output()
-                .map_err(anyhow::Error::from)
-                .context(cmd)
+        // Run the tests, each shard against its own validator.
+        let test_result: Result<Vec<_>> = {
+            let children = shards
+                .iter()
+                .enumerate()
+                .map(|(instance, args)| {
+                    let url = match instance {
+                        0 => cluster_url(cfg),
+                        _ => test_validator_rpc_url(cfg, instance),
+                    };
+                    // Don't interleave the output of parallel runs.
+                    let (stdout, stderr) = match shards.len() {
+                        1 => (Stdio::inherit(), Stdio::inherit()),
+                        _ => (Stdio::piped(), Stdio::piped()),
+                    };
+                    std::process::Command::new(&program)
+                        .args(args)
+                        .env("ANCHOR_PROVIDER_URL", url)
+                        .env("ANCHOR_WALLET", cfg.provider.wallet.to_string())
+                        .env("NODE_OPTIONS", &node_options)
+                        .stdout(stdout)
+                        .stderr(stderr)
+                        .spawn()
+                        .map_err(anyhow::Error::from)
+                        .context(cmd.clone())
+                })
+                .collect::<Result<Vec<_>>>();
+            children.and_then(|children| {
+                // Wait on each shard from its own thread, so that the pipes of
+                // every shard are drained while it runs, rather than filling
+                // up and blocking the shard until the previous ones exit.
+                let waits: Vec<_> = children
+                    .into_iter()
+                    .map(|child| std::thread::spawn(move || child.wait_with_output()))
+                    .collect();
+                waits
+                    .into_iter()
+                    .enumerate()
+                    .map(|(instance, wait)| -> Result<_> {
+                        let output = wait
+                            .join()
+                            .map_err(|_| anyhow!("Unable to wait on test shard {}", instance))??;
+                        if shards.len() > 1 {
+                            println!("Test shard {}:", instance);
+                            std::io::stdout().write_all(&output.stdout)?;
+                            std::io::stderr().write_all(&output.stderr)?;
+                        }
+                        Ok(output)
+                    })
+                    .collect()
+            })
         };
 
         // Keep validator running if needed.
@@ -1810,21 +2620,19 @@ fn test(
         }
 
         // Check all errors and shut down.
-        if let Some(mut child) = validator_handle {
+        for mut child in validator_handles {
             if let Err(err) = child.kill() {
                 println!("Failed to kill subprocess {}: {}", child.id(), err);
             }
         }
-        for mut child in log_streams? {
-            if let Err(err) = child.kill() {
-                println!("Failed to kill subprocess {}: {}", child.id(), err);
-            }
+        for log_stream in log_streams {
+            log_stream?.stop();
         }
 
         // Must exist *after* shutting down the validator and log streams.
         match test_result {
-            Ok(exit) => {
-                if !exit.status.success() {
+            Ok(outputs) => {
+                if let Some(exit) = outputs.iter().find(|exit| !exit.status.success()) {
                     std::process::exit(exit.status.code().unwrap());
                 }
             }
@@ -1837,6 +2645,42 @@ fn test(
     })
 }
 
+// Whether an argument of the test script selects test files, i.e., is a glob
+// pattern, e.g., `tests/**/*.ts`, or a directory.
+fn is_test_files_arg(arg: &str) -> bool {
+    arg.contains(|c| matches!(c, '*' | '?' | '[')) || Path::new(arg).is_dir()
+}
+
+// Splits `files` round robin into `parallel` shards, or fewer if there are
+// fewer files, so that no shard is empty.
+fn shard_test_files(files: Vec<String>, parallel: usize) -> Vec<Vec<String>> {
+    let mut shards = vec![vec![]; parallel.min(files.len())];
+    for (idx, file) in files.into_iter().enumerate() {
+        let len = shards.len();
+        shards[idx % len].push(file);
+    }
+    shards
+}
+
+// Expands glob patterns and directories into the sorted test files they
+// select.
+fn expand_test_files(patterns: &[String]) -> Result<Vec<String>> {
+    let mut files = BTreeSet::new();
+    for pattern in patterns {
+        let pattern = match Path::new(pattern).is_dir() {
+            true => format!("{}/*.[jt]s", pattern.trim_end_matches('/')),
+            false => pattern.clone(),
+        };
+        for path in glob::glob(&pattern)? {
+            let path = path?;
+            if path.is_file() {
+                files.insert(path.display().to_string());
+            }
+        }
+    }
+    Ok(files.into_iter().collect())
+}
+
 // Returns the solana-test-validator flags. This will embed the workspace
 // programs in the genesis block so we don't have to deploy every time. It also
 // allows control of other solana-test-validator features.
@@ -1893,7 +2737,7 @@ fn validator_flags(cfg: &WithPath<Config>) -> Result<Vec<String>> {
         }
         if let Some(validator) = &test.validator {
             for (key, value) in serde_json::to_value(validator)?.as_object().unwrap() {
-                if key == "ledger" {
+                if key == "ledger" || key == "account" {
                     continue;
                 };
                 flags.push(format!("--{}", key.replace('_', "-")));
@@ -1903,61 +2747,369 @@ fn validator_flags(cfg: &WithPath<Config>) -> Result<Vec<String>> {
                     flags.push(value.to_string());
                 }
             }
+            for entry in validator.account.iter().flatten() {
+                flags.push("--account".to_string());
+                flags.push(entry.address.clone());
+                flags.push(account_fixture_path(cfg, entry)?);
+            }
         }
     }
 
     Ok(flags)
 }
 
-fn stream_logs(config: &WithPath<Config>, rpc_url: &str) -> Result<Vec<std::process::Child>> {
+// Path to the account fixture of an `[[test.validator.account]]` entry, in the
+// format expected by solana-test-validator. Typed fixtures are serialized with
+// the IDL of their program into `.anchor/fixtures` first.
+fn account_fixture_path(cfg: &WithPath<Config>, entry: &AccountEntry) -> Result<String> {
+    let contents = fs::read_to_string(&entry.filename)
+        .with_context(|| format!("Unable to read account fixture {}", entry.filename))?;
+    let fixture: serde_json::Value = serde_json::from_str(&contents)?;
+    if fixture.get("account").is_some() {
+        return Ok(entry.filename.clone());
+    }
+
+    let typed: TypedAccountFixture = serde_json::from_value(fixture)
+        .with_context(|| format!("Invalid account fixture {}", entry.filename))?;
+    let program = cfg
+        .read_all_programs()?
+        .into_iter()
+        .find(|p| p.lib_name == typed.program)
+        .ok_or_else(|| anyhow!("Program {} not found in workspace", typed.program))?;
+    let idl = program
+        .idl
+        .as_ref()
+        .ok_or_else(|| anyhow!("Program {} has no IDL", typed.program))?;
+    let program_id = match cfg
+        .programs
+        .get(&Cluster::Localnet)
+        .and_then(|m| m.get(&program.lib_name))
+    {
+        Some(deployment) => deployment.address,
+        None => program.pubkey()?,
+    };
+    let account = typed
+        .to_account(idl, program_id)
+        .with_context(|| format!("Invalid account fixture {}", entry.filename))?;
+
+    let address: Pubkey = entry.address.parse()?;
+    let fixtures_dir = Path::new(".anchor/fixtures");
+    fs::create_dir_all(fixtures_dir)?;
+    let path = fixtures_dir.join(&entry.address).with_extension("json");
+    fs::write(
+        &path,
+        serde_json::to_string_pretty(&AccountFixture::new(&address, &account))?,
+    )?;
+    Ok(path.display().to_string())
+}
+
+// Streams the decoded logs of the workspace's programs, run by the
+// `instance`th test validator or by the cluster at `ws_url`, to a file per
+// program.
+fn stream_logs(
+    config: &WithPath<Config>,
+    ws_url: &str,
+    instance: usize,
+    all_logs: bool,
+) -> Result<LogStream> {
     let program_logs_dir = ".anchor/program-logs";
-    if Path::new(program_logs_dir).exists() {
+    if instance == 0 && Path::new(program_logs_dir).exists() {
         fs::remove_dir_all(program_logs_dir)?;
     }
     fs::create_dir_all(program_logs_dir)?;
-    let mut handles = vec![];
-    for program in config.read_all_programs()? {
-        let mut file = File::open(&format!("target/idl/{}.json", program.lib_name))?;
-        let mut contents = vec![];
-        file.read_to_end(&mut contents)?;
-        let idl: Idl = serde_json::from_slice(&contents)?;
-        let metadata = idl
-            .metadata
-            .ok_or_else(|| anyhow!("Program address not found."))?;
-        let metadata: IdlTestMetadata = serde_json::from_value(metadata)?;
-
-        let log_file = File::create(format!(
-            "{}/{}.{}.log",
-            program_logs_dir, metadata.address, program.lib_name,
-        ))?;
-        let stdio = std::process::Stdio::from(log_file);
-        let child = std::process::Command::new("solana")
-            .arg("logs")
-            .arg(metadata.address)
-            .arg("--url")
-            .arg(rpc_url)
-            .stdout(stdio)
-            .spawn()?;
-        handles.push(child);
+    let suffix = match instance {
+        0 => "log".to_string(),
+        _ => format!("{}.log", instance),
+    };
+    let programs = log_programs(config)?;
+    let files = programs
+        .iter()
+        .map(|program| {
+            let file = match &program.name {
+                Some(name) => format!(
+                    "{}/{}.{}.{}",
+                    program_logs_dir, program.address, name, suffix
+                ),
+                None => format!("{}/{}.{}", program_logs_dir, program.address, suffix),
+            };
+            (program.address.to_string(), PathBuf::from(file))
+        })
+        .collect();
+    LogStream::start(ws_url, LogDecoder::new(programs), files, all_logs)
+}
+
+// The programs whose logs are decoded: the workspace's programs, with their
+// IDLs, and the genesis programs of the test validator.
+fn log_programs(cfg: &WithPath<Config>) -> Result<Vec<LogProgram>> {
+    let deployments = cfg.programs.get(&cfg.provider.cluster);
+    let mut programs = vec![];
+    for program in cfg.read_all_programs()? {
+        let address = match deployments.and_then(|m| m.get(&program.lib_name)) {
+            Some(deployment) => deployment.address,
+            None => program.pubkey()?,
+        };
+        programs.push(LogProgram {
+            address,
+            name: Some(program.lib_name),
+            idl: program.idl,
+        });
     }
-    if let Some(test) = config.test.as_ref() {
-        if let Some(genesis) = &test.genesis {
-            for entry in genesis {
-                let log_file = File::create(format!("{}/{}.log", program_logs_dir, entry.address))?;
-                let stdio = std::process::Stdio::from(log_file);
-                let child = std::process::Command::new("solana")
-                    .arg("logs")
-                    .arg(entry.address.clone())
-                    .arg("--url")
-                    .arg(rpc_url)
-                    .stdout(stdio)
-                    .spawn()?;
-                handles.push(child);
+    if let Some(genesis) = cfg.test.as_ref().and_then(|test| test.genesis.as_ref()) {
+        for entry in genesis {
+            programs.push(LogProgram {
+                address: entry.address.parse()?,
+                name: None,
+                idl: None,
+            });
+        }
+    }
+    Ok(programs)
+}
+
+fn tx(cfg_override: &ConfigOverride, subcmd: TxCommand) -> Result<()> {
+    match subcmd {
+        TxCommand::Decode { transaction, json } => tx_decode(cfg_override, transaction, json),
+    }
+}
+
+fn tx_decode(cfg_override: &ConfigOverride, transaction: String, json: bool) -> Result<()> {
+    with_workspace(cfg_override, |cfg| {
+        let raw = match Signature::from_str(&transaction) {
+            Ok(signature) => {
+                let client = cluster_client_with_commitment(cfg, CommitmentConfig::confirmed());
+                RawTransaction::fetch(&client, &signature)?
+            }
+            Err(_) => RawTransaction::parse(&transaction)?,
+        };
+
+        // Workspace programs first, then programs with an IDL in Anchor.toml,
+        // then the IDLs on chain of any other program invoked.
+        let mut programs = log_programs(cfg)?;
+        for program in shell_programs(cfg)? {
+            match programs
+                .iter_mut()
+                .find(|p| p.address == program.program_id)
+            {
+                Some(p) if p.idl.is_none() => p.idl = Some(program.idl),
+                Some(_) => {}
+                None => programs.push(LogProgram {
+                    address: program.program_id,
+                    name: Some(program.name),
+                    idl: Some(program.idl),
+                }),
+            }
+        }
+        let message = &raw.transaction.message;
+        let invoked: BTreeSet<Pubkey> = message
+            .instructions
+            .iter()
+            .chain(raw.inner_instructions.iter().flat_map(|(_, ixs)| ixs))
+            .map(|ix| message.account_keys[ix.program_id_index as usize])
+            .collect();
+        for address in invoked {
+            if programs
+                .iter()
+                .any(|p| p.address == address && p.idl.is_some())
+            {
+                continue;
+            }
+            if let Ok(idl) = fetch_idl(cfg_override, address) {
+                programs.retain(|p| p.address != address);
+                programs.push(LogProgram {
+                    address,
+                    name: None,
+                    idl: Some(idl),
+                });
+            }
+        }
+
+        let decoded = tx::decode(&LogDecoder::new(programs), &raw);
+        match json {
+            true => println!("{}", serde_json::to_string_pretty(&decoded)?),
+            false => print!("{}", decoded),
+        }
+        Ok(())
+    })
+}
+
+fn invoke(
+    cfg_override: &ConfigOverride,
+    program: String,
+    ix_name: String,
+    args: Vec<String>,
+    accounts: Vec<String>,
+    signers: Vec<String>,
+    simulate: bool,
+) -> Result<()> {
+    with_workspace(cfg_override, |cfg| {
+        let (program_id, idl) = match Pubkey::from_str(&program) {
+            Ok(program_id) => (program_id, fetch_idl(cfg_override, program_id)?),
+            Err(_) => shell_programs(cfg)?
+                .into_iter()
+                .find(|p| p.name == program)
+                .map(|p| (p.program_id, p.idl))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Program {} with an IDL not found in [programs.{}]",
+                        program,
+                        cfg.provider.cluster
+                    )
+                })?,
+        };
+        let ix = idl
+            .instructions
+            .iter()
+            .find(|ix| ix.name == ix_name.to_mixed_case())
+            .ok_or_else(|| anyhow!("Instruction {} not found in {}", ix_name, idl.name))?;
+
+        // Argument values are JSON, except for strings and public keys,
+        // which are never quoted.
+        let mut ix_args = serde_json::Map::new();
+        for arg in &args {
+            let (name, value) = arg
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected --arg name=value, found {}", arg))?;
+            let name = name.to_mixed_case();
+            let ty = ix.args.iter().find(|a| a.name == name).map(|a| &a.ty);
+            let value = match ty {
+                Some(IdlType::String) | Some(IdlType::PublicKey) => {
+                    JsonValue::String(value.to_string())
+                }
+                _ => parse_json_arg(value),
+            };
+            ix_args.insert(name, value);
+        }
+        let mut ix_accounts = JsonValue::Object(serde_json::Map::new());
+        for account in &accounts {
+            let (path, address) = account
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected --account name=pubkey, found {}", account))?;
+            let mut node = &mut ix_accounts;
+            for name in path.split('.') {
+                node = node
+                    .as_object_mut()
+                    .ok_or_else(|| anyhow!("Account {} is both an account and a composite", path))?
+                    .entry(name.to_mixed_case())
+                    .or_insert_with(|| JsonValue::Object(serde_json::Map::new()));
             }
+            *node = JsonValue::String(address.to_string());
         }
+        let keypairs = signers
+            .iter()
+            .map(|path| {
+                solana_sdk::signature::read_keypair_file(&*shellexpand::tilde(path))
+                    .map_err(|_| anyhow!("Unable to read keypair file {}", path))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let payer = solana_sdk::signature::read_keypair_file(&cfg.provider.wallet.to_string())
+            .map_err(|_| anyhow!("Unable to read keypair file"))?;
+
+        let ix = instruction::build_instruction(
+            &program_id,
+            &idl,
+            ix,
+            &ix_accounts,
+            &JsonValue::Object(ix_args),
+        )?;
+        let signers = instruction::signers(&ix, &payer, &keypairs)?;
+        let client = cluster_client_with_commitment(cfg, CommitmentConfig::confirmed());
+        let (recent_hash, _fee_calc) = client.get_recent_blockhash()?;
+        let tx =
+            Transaction::new_signed_with_payer(&[ix], Some(&payer.pubkey()), &signers, recent_hash);
+
+        if simulate {
+            let response = client.simulate_transaction(&tx)?;
+            let mut programs = log_programs(cfg)?;
+            if !programs.iter().any(|p| p.address == program_id) {
+                programs.push(LogProgram {
+                    address: program_id,
+                    name: Some(idl.name.clone()),
+                    idl: Some(idl.clone()),
+                });
+            }
+            let logs = Response {
+                context: response.context,
+                value: RpcLogsResponse {
+                    signature: tx.signatures[0].to_string(),
+                    err: response.value.err,
+                    logs: response.value.logs.unwrap_or_default(),
+                },
+            };
+            print!("{}", LogDecoder::new(programs).decode(&logs));
+            return Ok(());
+        }
+
+        let signature = client.send_and_confirm_transaction_with_spinner(&tx)?;
+        println!("{}", signature);
+        Ok(())
+    })
+}
+
+// Parses an argument as JSON, or as a string. Integers too wide for JSON
+// numbers stay strings, which the coder parses as is.
+fn parse_json_arg(value: &str) -> JsonValue {
+    match serde_json::from_str::<JsonValue>(value) {
+        Ok(JsonValue::Number(n)) if n.is_f64() && !value.contains(&['.', 'e', 'E'][..]) => {
+            JsonValue::String(value.to_string())
+        }
+        Ok(value) => value,
+        Err(_) => JsonValue::String(value.to_string()),
     }
+}
 
-    Ok(handles)
+fn logs(cfg_override: &ConfigOverride, program: Option<String>, json: bool) -> Result<()> {
+    with_workspace(cfg_override, |cfg| {
+        let programs = log_programs(cfg)?;
+        let program = match program {
+            None => None,
+            Some(program) => Some(
+                match programs
+                    .iter()
+                    .find(|p| p.name.as_deref() == Some(program.as_str()))
+                {
+                    Some(p) => p.address,
+                    None => program
+                        .parse()
+                        .map_err(|_| anyhow!("Program {} not found in workspace", program))?,
+                },
+            ),
+        };
+        // As when streaming the logs during tests, the logs of every
+        // transaction are only subscribed to on a local validator, since RPC
+        // nodes of public clusters commonly reject or throttle it.
+        let subscribed: Option<Vec<Pubkey>> = match program {
+            Some(program) => Some(vec![program]),
+            None if cfg.provider.cluster == Cluster::Localnet => None,
+            None if programs.is_empty() => return Err(anyhow!("No programs in workspace")),
+            None => Some(programs.iter().map(|p| p.address).collect()),
+        };
+        let ws_url = cluster_ws_url(cfg, 0)?;
+        let decoder = LogDecoder::new(programs);
+        let (_subscriptions, receiver) = logs::subscribe(&ws_url, subscribed.as_deref())?;
+        eprintln!("Streaming logs from {}. Press Ctrl + C to quit.", ws_url);
+        for (subscription, response) in receiver {
+            let tx = decoder.decode(&response);
+            let print = match (program, &subscription) {
+                // Subscribing to a single program already filters the
+                // transactions.
+                (Some(_), _) => true,
+                (None, None) => decoder.is_known(&tx),
+                // A transaction invoking several programs is received once
+                // per subscription, so it's only printed for the first one.
+                (None, Some(subscription)) => {
+                    decoder.first_known(&tx) == Some(subscription.as_str())
+                }
+            };
+            if !print {
+                continue;
+            }
+            match json {
+                true => println!("{}", serde_json::to_string(&tx)?),
+                false => println!("{}", tx),
+            }
+        }
+        Ok(())
+    })
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -1965,13 +3117,17 @@ pub struct IdlTestMetadata {
     address: String,
 }
 
+// Starts the `instance`th test validator. Instances other than the first one
+// use their own ledger and ports, so that they can run in parallel.
 fn start_test_validator(
     cfg: &Config,
     flags: Option<Vec<String>>,
     test_log_stdout: bool,
+    instance: usize,
 ) -> Result<Child> {
     //
-    let (test_ledger_directory, test_ledger_log_filename) = test_validator_file_paths(cfg);
+    let (test_ledger_directory, test_ledger_log_filename) =
+        test_validator_file_paths(cfg, instance);
 
     // Start a validator for testing.
     let (test_validator_stdout, test_validator_stderr) = match test_log_stdout {
@@ -1986,14 +3142,19 @@ fn start_test_validator(
         false => (Stdio::inherit(), Stdio::inherit()),
     };
 
-    let rpc_url = test_validator_rpc_url(cfg);
+    let rpc_url = test_validator_rpc_url(cfg, instance);
+
+    let mut flags = flags.unwrap_or_default();
+    if instance > 0 {
+        flags = isolate_validator_flags(cfg, flags, instance);
+    }
 
     let mut validator_handle = std::process::Command::new("solana-test-validator")
         .arg("--ledger")
         .arg(test_ledger_directory)
         .arg("--mint")
         .arg(cfg.wallet_kp()?.pubkey().to_string())
-        .args(flags.unwrap_or_default())
+        .args(flags)
         .stdout(test_validator_stdout)
         .stderr(test_validator_stderr)
         .spawn()
@@ -2026,27 +3187,81 @@ fn start_test_validator(
     Ok(validator_handle)
 }
 
-// Return the URL that solana-test-validator should be running on given the
-// configuration
-fn test_validator_rpc_url(cfg: &Config) -> String {
+// Return the URL that the `instance`th solana-test-validator should be
+// running on given the configuration
+fn test_validator_rpc_url(cfg: &Config, instance: usize) -> String {
+    let (rpc_port, _) = test_validator_ports(cfg, instance);
     match &cfg.test.as_ref() {
         Some(Test {
             validator: Some(validator),
             ..
-        }) => format!("http://{}:{}", validator.bind_address, validator.rpc_port),
-        _ => "http://localhost:8899".to_string(),
+        }) => format!("http://{}:{}", validator.bind_address, rpc_port),
+        _ => format!("http://localhost:{}", rpc_port),
+    }
+}
+
+// RPC and faucet ports of the `instance`th solana-test-validator. The RPC
+// websocket uses the port after the RPC port, so instances are two apart.
+fn test_validator_ports(cfg: &Config, instance: usize) -> (u16, u16) {
+    let (rpc_port, faucet_port) = match &cfg.test.as_ref() {
+        Some(Test {
+            validator: Some(validator),
+            ..
+        }) => (validator.rpc_port, validator.faucet_port.unwrap_or(9900)),
+        _ => (8899, 9900),
+    };
+    (
+        rpc_port + 2 * instance as u16,
+        faucet_port + instance as u16,
+    )
+}
+
+// Replaces the port flags of a test validator with the ports of the
+// `instance`th one.
+fn isolate_validator_flags(cfg: &Config, flags: Vec<String>, instance: usize) -> Vec<String> {
+    let port_flags = ["--rpc-port", "--faucet-port", "--gossip-port"];
+    let mut isolated = vec![];
+    let mut flags = flags.into_iter();
+    while let Some(flag) = flags.next() {
+        if port_flags.contains(&flag.as_str()) {
+            // Skip the flag's value too.
+            flags.next();
+        } else {
+            isolated.push(flag);
+        }
+    }
+
+    let (rpc_port, faucet_port) = test_validator_ports(cfg, instance);
+    isolated.push("--rpc-port".to_string());
+    isolated.push(rpc_port.to_string());
+    isolated.push("--faucet-port".to_string());
+    isolated.push(faucet_port.to_string());
+    // Without a configured gossip port, each validator picks a free one.
+    if let Some(gossip_port) = cfg
+        .test
+        .as_ref()
+        .and_then(|test| test.validator.as_ref())
+        .and_then(|validator| validator.gossip_port)
+    {
+        isolated.push("--gossip-port".to_string());
+        isolated.push((gossip_port + instance as u16).to_string());
     }
+    isolated
 }
 
-// Setup and return paths to the solana-test-validator ledger directory and log
-// files given the configuration
-fn test_validator_file_paths(cfg: &Config) -> (String, String) {
+// Setup and return paths to the `instance`th solana-test-validator ledger
+// directory and log files given the configuration
+fn test_validator_file_paths(cfg: &Config, instance: usize) -> (String, String) {
     let ledger_directory = match &cfg.test.as_ref() {
         Some(Test {
             validator: Some(validator),
             ..
-        }) => &validator.ledger,
-        _ => ".anchor/test-ledger",
+        }) => validator.ledger.clone(),
+        _ => ".anchor/test-ledger".to_string(),
+    };
+    let ledger_directory = match instance {
+        0 => ledger_directory,
+        _ => format!("{}-{}", ledger_directory, instance),
     };
 
     if !Path::new(&ledger_directory).is_relative() {
@@ -2067,60 +3282,101 @@ fn test_validator_file_paths(cfg: &Config) -> (String, String) {
     )
 }
 
+// The websocket URL of the cluster, i.e., of the `instance`th test validator
+// on localnet.
+fn cluster_ws_url(cfg: &Config, instance: usize) -> Result<String> {
+    match cfg.provider.cluster {
+        Cluster::Localnet => test_validator_ws_url(cfg, instance),
+        _ => Ok(cfg.provider.cluster.ws_url().to_string()),
+    }
+}
+
+// The websocket URL of the `instance`th solana-test-validator, on the port
+// after its RPC port.
+fn test_validator_ws_url(cfg: &Config, instance: usize) -> Result<String> {
+    let cluster: Cluster = test_validator_rpc_url(cfg, instance).parse()?;
+    Ok(cluster.ws_url().to_string())
+}
+
+// An RPC client of the cluster, with the commitment configured for it, if
+// any.
+fn cluster_client(cfg: &Config) -> RpcClient {
+    let commitment = cfg.provider.cluster.commitment().unwrap_or_default();
+    cluster_client_with_commitment(cfg, commitment)
+}
+
+// An RPC client of the cluster, sending the HTTP headers configured for it,
+// if any.
+fn cluster_client_with_commitment(cfg: &Config, commitment: CommitmentConfig) -> RpcClient {
+    anchor_client::rpc_client(&cluster_url(cfg), &cluster_headers(cfg), commitment)
+}
+
+fn cluster_headers(cfg: &Config) -> BTreeMap<String, String> {
+    cfg.provider.cluster.headers().cloned().unwrap_or_default()
+}
+
 fn cluster_url(cfg: &Config) -> String {
     let is_localnet = cfg.provider.cluster == Cluster::Localnet;
     match is_localnet {
         // Cluster is Localnet, assume the intent is to use the configuration
         // for solana-test-validator
-        true => test_validator_rpc_url(cfg),
+        true => test_validator_rpc_url(cfg, 0),
         false => cfg.provider.cluster.url().to_string(),
     }
 }
 
-fn deploy(cfg_override: &ConfigOverride, program_str: Option<String>) -> Result<()> {
+fn deploy(
+    cfg_override: &ConfigOverride,
+    program_str: Option<String>,
+    close_buffers: bool,
+    buffer_only: bool,
+    buffer_authority: Option<Pubkey>,
+) -> Result<()> {
     with_workspace(cfg_override, |cfg| {
         let url = cluster_url(cfg);
         let keypair = cfg.provider.wallet.to_string();
+        let deployer = Deployer::new(
+            &url,
+            solana_sdk::signature::read_keypair_file(&keypair)
+                .map_err(|_| anyhow!("Unable to read keypair file"))?,
+            DeployConfig {
+                close_buffers,
+                headers: cluster_headers(cfg),
+                ..DeployConfig::default()
+            },
+        );
+
+        // A single program, along with the workspace programs it depends on.
+        let program_paths = match &program_str {
+            None => None,
+            Some(single_prog_str) => Some(
+                cfg.get_program_list_with_dependencies(&cfg.get_program_path(single_prog_str)?)?,
+            ),
+        };
 
         // Deploy the programs.
         println!("Deploying workspace: {}", url);
         println!("Upgrade authority: {}", keypair);
 
         for mut program in cfg.read_all_programs()? {
-            if let Some(single_prog_str) = &program_str {
-                let program_name = program.path.file_name().unwrap().to_str().unwrap();
-                if single_prog_str.as_str() != program_name {
+            if let Some(program_paths) = &program_paths {
+                if !program_paths.contains(&program.path) {
                     continue;
                 }
             }
-            let binary_path = program.binary_path().display().to_string();
+            let binary_path = program.binary_path();
 
             println!(
                 "Deploying program {:?}...",
                 program.path.file_name().unwrap().to_str().unwrap()
             );
-            println!("Program path: {}...", binary_path);
-
-            let file = program.keypair_file()?;
+            println!("Program path: {}...", binary_path.display());
 
             // Send deploy transactions.
-            let exit = std::process::Command::new("solana")
-                .arg("program")
-                .arg("deploy")
-                .arg("--url")
-                .arg(&url)
-                .arg("--keypair")
-                .arg(&keypair)
-                .arg("--program-id")
-                .arg(file.path().display().to_string())
-                .arg(&binary_path)
-                .stdout(Stdio::inherit())
-                .stderr(Stdio::inherit())
-                .output()
-                .expect("Must deploy");
-            if !exit.status.success() {
-                println!("There was a problem deploying: {:?}.", exit);
-                std::process::exit(exit.status.code().unwrap_or(1));
+            if buffer_only {
+                write_upgrade_buffer(&deployer, program.pubkey()?, &binary_path, buffer_authority)?;
+            } else {
+                deployer.deploy(&program.keypair()?, &binary_path)?;
             }
 
             let program_pubkey = program.pubkey()?;
@@ -2148,35 +3404,268 @@ fn upgrade(
     cfg_override: &ConfigOverride,
     program_id: Pubkey,
     program_filepath: String,
+    close_buffers: bool,
+    buffer_only: bool,
+    buffer_authority: Option<Pubkey>,
 ) -> Result<()> {
     let path: PathBuf = program_filepath.parse().unwrap();
-    let program_filepath = path.canonicalize()?.display().to_string();
+    let program_filepath = path.canonicalize()?;
 
     with_workspace(cfg_override, |cfg| {
         let url = cluster_url(cfg);
-        let exit = std::process::Command::new("solana")
-            .arg("program")
-            .arg("deploy")
-            .arg("--url")
-            .arg(url)
-            .arg("--keypair")
-            .arg(&cfg.provider.wallet.to_string())
-            .arg("--program-id")
-            .arg(program_id.to_string())
-            .arg(&program_filepath)
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .output()
-            .expect("Must deploy");
-        if !exit.status.success() {
-            println!("There was a problem deploying: {:?}.", exit);
-            std::process::exit(exit.status.code().unwrap_or(1));
+        let keypair = solana_sdk::signature::read_keypair_file(&cfg.provider.wallet.to_string())
+            .map_err(|_| anyhow!("Unable to read keypair file"))?;
+        let deployer = Deployer::new(
+            &url,
+            keypair,
+            DeployConfig {
+                close_buffers,
+                headers: cluster_headers(cfg),
+                ..DeployConfig::default()
+            },
+        );
+        match buffer_only {
+            true => {
+                write_upgrade_buffer(&deployer, program_id, &program_filepath, buffer_authority)
+            }
+            false => deployer.upgrade(&program_id, &program_filepath),
+        }
+    })
+}
+
+// Writes the binary to a buffer owned by `buffer_authority`, defaulting to the
+// program's upgrade authority, and prints the instruction to upgrade the
+// program with it.
+fn write_upgrade_buffer(
+    deployer: &Deployer,
+    program_id: Pubkey,
+    binary_path: &Path,
+    buffer_authority: Option<Pubkey>,
+) -> Result<()> {
+    let client = deployer.rpc_client();
+    let upgrade_authority = match fetch_deployed_bin(&client, program_id)?.state {
+        BinVerificationState::ProgramData {
+            upgrade_authority_address: Some(authority),
+            ..
+        } => authority,
+        _ => return Err(anyhow!("{} is not an upgradeable program", program_id)),
+    };
+    let buffer_authority = buffer_authority.unwrap_or(upgrade_authority);
+    let buffer = deployer.write_buffer_only(binary_path, &buffer_authority)?;
+
+    println!("Upgrade instruction:");
+    print_instruction(&bpf_loader_upgradeable::upgrade(
+        &program_id,
+        &buffer,
+        &upgrade_authority,
+        &upgrade_authority,
+    ))
+}
+
+fn graph(cfg_override: &ConfigOverride) -> Result<()> {
+    with_workspace(cfg_override, |cfg| {
+        let graph = cfg.program_graph()?;
+        let lib_name = |path: &Path| -> Result<String> {
+            Manifest::from_path(path.join("Cargo.toml"))?.lib_name()
+        };
+        for program in cfg.get_program_list()? {
+            println!("{}", lib_name(&program)?);
+            for dep in graph.get(&program).into_iter().flatten() {
+                println!("  -> {}", lib_name(dep)?);
+            }
         }
         Ok(())
     })
 }
 
-fn create_idl_account(
+fn account(cfg_override: &ConfigOverride, cmd: AccountCommand) -> Result<()> {
+    match cmd {
+        AccountCommand::Dump { address, out } => account_dump(cfg_override, address, out),
+    }
+}
+
+fn account_show(
+    cfg_override: &ConfigOverride,
+    account_type: Option<String>,
+    address: Pubkey,
+) -> Result<()> {
+    let cfg = Config::discover(cfg_override)?.expect("Not in workspace.");
+    let client = cluster_client(&cfg);
+    let account = client
+        .get_account(&address)
+        .map_err(|_| anyhow!("Account {} not found", address))?;
+
+    // Find the IDL defining the account type.
+    let (program, ty, idl) = match account_type {
+        Some(account_type) => {
+            let (program, ty) = account_type
+                .split_once('.')
+                .ok_or_else(|| anyhow!("Expected <program>.<AccountType>"))?;
+            let idl = cfg
+                .read_all_programs()?
+                .into_iter()
+                .find(|p| p.lib_name == program)
+                .ok_or_else(|| anyhow!("Program {} not found in workspace", program))?
+                .idl
+                .ok_or_else(|| anyhow!("Program {} has no IDL", program))?;
+            (program.to_string(), ty.to_string(), idl)
+        }
+        None => {
+            // Look in the workspace IDLs first, then the owner's IDL on chain.
+            let mut idls: Vec<(String, Idl)> = cfg
+                .read_all_programs()?
+                .into_iter()
+                .filter_map(|p| p.idl.map(|idl| (p.lib_name, idl)))
+                .collect();
+            if let Ok(idl) = fetch_idl(cfg_override, account.owner) {
+                idls.push((idl.name.clone(), idl));
+            }
+            idls.into_iter()
+                .find_map(|(program, idl)| {
+                    let ty = coder::find_account(&idl, &account.data)?.name.clone();
+                    Some((program, ty, idl))
+                })
+                .ok_or_else(|| anyhow!("Unable to detect the type of account {}", address))?
+        }
+    };
+
+    let data = coder::decode_account(&idl, &ty, &account.data)?;
+    let fixture = TypedAccountFixture {
+        program,
+        ty,
+        data,
+        owner: Some(account.owner.to_string()),
+        lamports: Some(account.lamports),
+        space: Some(account.data.len()),
+    };
+    println!("{}", serde_json::to_string_pretty(&fixture)?);
+    Ok(())
+}
+
+fn account_dump(cfg_override: &ConfigOverride, address: Pubkey, out: Option<String>) -> Result<()> {
+    let cfg = Config::discover(cfg_override)?.expect("Not in workspace.");
+    let client = cluster_client(&cfg);
+    let account = client
+        .get_account(&address)
+        .map_err(|_| anyhow!("Account {} not found", address))?;
+    let fixture = serde_json::to_string_pretty(&AccountFixture::new(&address, &account))?;
+    match out {
+        None => println!("{}", fixture),
+        Some(out) => fs::write(out, fixture)?,
+    }
+    Ok(())
+}
+
+fn program(cfg_override: &ConfigOverride, cmd: ProgramCommand) -> Result<()> {
+    match cmd {
+        ProgramCommand::Show { program_id } => program_show(cfg_override, program_id),
+        ProgramCommand::SetUpgradeAuthority {
+            program_id,
+            new_upgrade_authority,
+            make_final,
+        } => program_set_upgrade_authority(
+            cfg_override,
+            program_id,
+            new_upgrade_authority,
+            make_final,
+        ),
+    }
+}
+
+fn program_show(cfg_override: &ConfigOverride, program_id: Pubkey) -> Result<()> {
+    with_workspace(cfg_override, |cfg| {
+        let client = cluster_client(cfg);
+        let deployed = fetch_deployed_bin(&client, program_id)?;
+        match deployed.state {
+            BinVerificationState::Buffer => {
+                println!("Buffer Address: {}", program_id);
+            }
+            BinVerificationState::ProgramData {
+                slot,
+                upgrade_authority_address,
+            } => {
+                println!("Program Id: {}", program_id);
+                println!("Owner: {}", deployed.owner);
+                if let Some(programdata_address) = deployed.programdata_address {
+                    println!("ProgramData Address: {}", programdata_address);
+                    println!(
+                        "Authority: {}",
+                        upgrade_authority_address
+                            .map(|a| a.to_string())
+                            .unwrap_or_else(|| "none".to_string())
+                    );
+                    println!("Last Deployed In Slot: {}", slot);
+                }
+            }
+        }
+        println!("Data Length: {} bytes", deployed.bin.len());
+        println!(
+            "Balance: {} SOL",
+            solana_sdk::native_token::lamports_to_sol(deployed.lamports)
+        );
+        Ok(())
+    })
+}
+
+fn program_set_upgrade_authority(
+    cfg_override: &ConfigOverride,
+    program_id: Pubkey,
+    new_upgrade_authority: Option<Pubkey>,
+    make_final: bool,
+) -> Result<()> {
+    let new_upgrade_authority = match (new_upgrade_authority, make_final) {
+        (Some(authority), false) => Some(authority),
+        (None, true) => None,
+        _ => {
+            return Err(anyhow!(
+                "Exactly one of --new-upgrade-authority or --final must be given"
+            ))
+        }
+    };
+    with_workspace(cfg_override, |cfg| {
+        let url = cluster_url(cfg);
+        let client = cluster_client(cfg);
+        let current_authority = match fetch_deployed_bin(&client, program_id)?.state {
+            BinVerificationState::ProgramData {
+                upgrade_authority_address: Some(authority),
+                ..
+            } => authority,
+            _ => return Err(anyhow!("{} is not an upgradeable program", program_id)),
+        };
+
+        let keypair = solana_sdk::signature::read_keypair_file(&cfg.provider.wallet.to_string())
+            .map_err(|_| anyhow!("Unable to read keypair file"))?;
+        let deployer = Deployer::new(
+            &url,
+            keypair,
+            DeployConfig {
+                headers: cluster_headers(cfg),
+                ..DeployConfig::default()
+            },
+        );
+        if deployer.authority() != current_authority {
+            println!(
+                "The configured wallet is not the upgrade authority, {}.",
+                current_authority
+            );
+            println!("Set upgrade authority instruction:");
+            return print_instruction(&bpf_loader_upgradeable::set_upgrade_authority(
+                &program_id,
+                &current_authority,
+                new_upgrade_authority.as_ref(),
+            ));
+        }
+
+        deployer.set_upgrade_authority(&program_id, new_upgrade_authority.as_ref())?;
+        match new_upgrade_authority {
+            Some(authority) => println!("Upgrade authority: {}", authority),
+            None => println!("Upgrade authority: none"),
+        }
+        Ok(())
+    })
+}
+
+fn create_idl_account(
     cfg: &Config,
     keypair_path: &str,
     program_id: &Pubkey,
@@ -2186,8 +3675,7 @@ fn create_idl_account(
     let idl_address = IdlAccount::address(program_id);
     let keypair = solana_sdk::signature::read_keypair_file(keypair_path)
         .map_err(|_| anyhow!("Unable to read keypair file"))?;
-    let url = cluster_url(cfg);
-    let client = RpcClient::new(url);
+    let client = cluster_client(cfg);
     let idl_data = serialize_idl(idl)?;
 
     // Run `Create instruction.
@@ -2240,8 +3728,7 @@ fn create_idl_buffer(
 ) -> Result<Pubkey> {
     let keypair = solana_sdk::signature::read_keypair_file(keypair_path)
         .map_err(|_| anyhow!("Unable to read keypair file"))?;
-    let url = cluster_url(cfg);
-    let client = RpcClient::new(url);
+    let client = cluster_client(cfg);
 
     let buffer = Keypair::generate(&mut OsRng);
 
@@ -2310,8 +3797,17 @@ fn serialize_idl_ix(ix_inner: anchor_lang::idl::IdlInstruction) -> Result<Vec<u8
     Ok(data)
 }
 
-fn migrate(cfg_override: &ConfigOverride) -> Result<()> {
+fn migrate(cfg_override: &ConfigOverride, to: Option<u32>, dry_run: bool) -> Result<()> {
     with_workspace(cfg_override, |cfg| {
+        if Path::new("migrations/Cargo.toml").exists() {
+            return migrate_rust(cfg, to, dry_run);
+        }
+        if to.is_some() || dry_run {
+            return Err(anyhow!(
+                "--to and --dry-run are only supported by Rust migrations, see `anchor migrate --init`"
+            ));
+        }
+
         println!("Running migration deploy script");
 
         let url = cluster_url(cfg);
@@ -2359,6 +3855,51 @@ fn migrate(cfg_override: &ConfigOverride) -> Result<()> {
     })
 }
 
+// Runs the migrations binary, which records the migrations it runs in
+// `.anchor/migrations.json`.
+fn migrate_rust(cfg: &WithPath<Config>, to: Option<u32>, dry_run: bool) -> Result<()> {
+    fs::create_dir_all(".anchor")?;
+    let record = std::env::current_dir()?.join(".anchor/migrations.json");
+    let mut cmd = std::process::Command::new("cargo");
+    cmd.args(&["run", "--manifest-path", "migrations/Cargo.toml"])
+        .env("ANCHOR_PROVIDER_URL", cluster_url(cfg))
+        .env("ANCHOR_PROVIDER_WS_URL", cluster_ws_url(cfg, 0)?)
+        .env("ANCHOR_WALLET", cfg.provider.wallet.to_string())
+        .env(
+            "ANCHOR_MIGRATIONS_CLUSTER",
+            cfg.provider.cluster.to_string(),
+        )
+        .env("ANCHOR_MIGRATIONS_FILE", record)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit());
+    if let Some(to) = to {
+        cmd.env("ANCHOR_MIGRATE_TO", to.to_string());
+    }
+    if dry_run {
+        cmd.env("ANCHOR_MIGRATE_DRY_RUN", "1");
+    }
+
+    let exit = cmd.output()?;
+    if !exit.status.success() {
+        println!("Migrations failed.");
+        std::process::exit(exit.status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+fn migrate_init(cfg_override: &ConfigOverride) -> Result<()> {
+    with_workspace(cfg_override, |_cfg| {
+        if Path::new("migrations/Cargo.toml").exists() {
+            return Err(anyhow!("migrations/Cargo.toml already exists"));
+        }
+        fs::create_dir_all("migrations/src")?;
+        fs::write("migrations/Cargo.toml", template::migrations_cargo_toml())?;
+        fs::write("migrations/src/main.rs", template::migrations_main_rs())?;
+        println!("Created Rust migrations in migrations/src/main.rs");
+        Ok(())
+    })
+}
+
 fn set_workspace_dir_or_exit() {
     let d = match Config::discover(&ConfigOverride::default()) {
         Err(err) => {
@@ -2411,64 +3952,111 @@ fn airdrop(cfg_override: &ConfigOverride) -> Result<()> {
     }
 }
 
-fn cluster(_cmd: ClusterCommand) -> Result<()> {
+fn cluster(cfg_override: &ConfigOverride, cmd: ClusterCommand) -> Result<()> {
+    match cmd {
+        ClusterCommand::List => cluster_list(),
+        ClusterCommand::Add {
+            name,
+            url,
+            ws_url,
+            commitment,
+            headers,
+        } => cluster_add(name, url, ws_url, commitment, headers),
+        ClusterCommand::Remove { name } => cluster_remove(name),
+        ClusterCommand::Use { cluster } => cluster_use(cfg_override, cluster),
+    }
+}
+
+fn cluster_list() -> Result<()> {
     println!("Cluster Endpoints:\n");
     println!("* Mainnet - https://solana-api.projectserum.com");
     println!("* Mainnet - https://api.mainnet-beta.solana.com");
     println!("* Devnet  - https://api.devnet.solana.com");
     println!("* Testnet - https://api.testnet.solana.com");
+
+    let config = ClustersConfig::load()?;
+    if !config.clusters.is_empty() {
+        println!("\nNamed Clusters:\n");
+        for (name, endpoint) in &config.clusters {
+            println!("* {} - {} ({})", name, endpoint.url, endpoint.ws_url);
+            if let Some(commitment) = &endpoint.commitment {
+                println!("    commitment: {}", commitment);
+            }
+            // Header values are often API keys, so only their names are shown.
+            for header in endpoint.headers.keys() {
+                println!("    header: {}", header);
+            }
+        }
+    }
     Ok(())
 }
 
-fn shell(cfg_override: &ConfigOverride) -> Result<()> {
-    with_workspace(cfg_override, |cfg| {
-        let programs = {
-            // Create idl map from all workspace programs.
-            let mut idls: HashMap<String, Idl> = cfg
-                .read_all_programs()?
-                .iter()
-                .filter(|program| program.idl.is_some())
-                .map(|program| {
-                    (
-                        program.idl.as_ref().unwrap().name.clone(),
-                        program.idl.clone().unwrap(),
-                    )
-                })
-                .collect();
-            // Insert all manually specified idls into the idl map.
-            if let Some(programs) = cfg.programs.get(&cfg.provider.cluster) {
-                let _ = programs
-                    .iter()
-                    .map(|(name, pd)| {
-                        if let Some(idl_fp) = &pd.idl {
-                            let file_str =
-                                fs::read_to_string(idl_fp).expect("Unable to read IDL file");
-                            let idl = serde_json::from_str(&file_str).expect("Idl not readable");
-                            idls.insert(name.clone(), idl);
-                        }
-                    })
-                    .collect::<Vec<_>>();
-            }
+fn cluster_add(
+    name: String,
+    url: String,
+    ws_url: String,
+    commitment: Option<String>,
+    headers: Vec<String>,
+) -> Result<()> {
+    let headers = headers
+        .iter()
+        .map(|header| {
+            let (key, value) = header
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid header {}, expected <name>: <value>", header))?;
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect::<Result<_>>()?;
+    let mut config = ClustersConfig::load()?;
+    config.add(
+        &name,
+        ClusterEndpoint {
+            url,
+            ws_url,
+            commitment,
+            headers,
+        },
+    )?;
+    config.save()?;
+    println!("Added cluster {}", name);
+    Ok(())
+}
 
-            // Finalize program list with all programs with IDLs.
-            match cfg.programs.get(&cfg.provider.cluster) {
-                None => Vec::new(),
-                Some(programs) => programs
-                    .iter()
-                    .filter_map(|(name, program_deployment)| {
-                        Some(ProgramWorkspace {
-                            name: name.to_string(),
-                            program_id: program_deployment.address,
-                            idl: match idls.get(name) {
-                                None => return None,
-                                Some(idl) => idl.clone(),
-                            },
-                        })
-                    })
-                    .collect::<Vec<ProgramWorkspace>>(),
-            }
-        };
+fn cluster_remove(name: String) -> Result<()> {
+    let mut config = ClustersConfig::load()?;
+    if config.clusters.remove(&name).is_none() {
+        return Err(anyhow!("Cluster {} not found", name));
+    }
+    config.save()?;
+    println!("Removed cluster {}", name);
+    Ok(())
+}
+
+fn cluster_use(cfg_override: &ConfigOverride, cluster: String) -> Result<()> {
+    let cfg = Config::discover(cfg_override)?.expect("Not in workspace.");
+    // Make sure the cluster resolves before writing it.
+    cluster.parse::<Cluster>()?;
+    cfg.write_provider_cluster(&cluster)?;
+    println!("Using cluster {}", cluster);
+    Ok(())
+}
+
+fn shell(cfg_override: &ConfigOverride, rust: bool) -> Result<()> {
+    with_workspace(cfg_override, |cfg| {
+        let programs = shell_programs(cfg)?;
         let url = cluster_url(cfg);
+        if rust {
+            let payer = solana_sdk::signature::read_keypair_file(&cfg.provider.wallet.to_string())
+                .map_err(|_| anyhow!("Unable to read keypair file"))?;
+            let history = cfg
+                .path()
+                .parent()
+                .expect("Invalid Anchor.toml")
+                .join(".anchor");
+            fs::create_dir_all(&history)?;
+            let client = cluster_client_with_commitment(cfg, CommitmentConfig::confirmed());
+            return Shell::new(programs, &url, client, payer).run(&history.join("shell_history"));
+        }
         let js_code = template::node_shell(&url, &cfg.provider.wallet.to_string(), programs)?;
         let mut child = std::process::Command::new("node")
             .args(&["-e", &js_code, "-i", "--experimental-repl-await"])
@@ -2485,6 +4073,55 @@ fn shell(cfg_override: &ConfigOverride) -> Result<()> {
     })
 }
 
+// The workspace programs with IDLs, and the programs of the cluster's
+// `[programs]` section with an `idl` file.
+fn shell_programs(cfg: &Config) -> Result<Vec<ProgramWorkspace>> {
+    // Create idl map from all workspace programs.
+    let mut idls: HashMap<String, Idl> = cfg
+        .read_all_programs()?
+        .iter()
+        .filter(|program| program.idl.is_some())
+        .map(|program| {
+            (
+                program.idl.as_ref().unwrap().name.clone(),
+                program.idl.clone().unwrap(),
+            )
+        })
+        .collect();
+    // Insert all manually specified idls into the idl map.
+    if let Some(programs) = cfg.programs.get(&cfg.provider.cluster) {
+        let _ = programs
+            .iter()
+            .map(|(name, pd)| {
+                if let Some(idl_fp) = &pd.idl {
+                    let file_str = fs::read_to_string(idl_fp).expect("Unable to read IDL file");
+                    let idl = serde_json::from_str(&file_str).expect("Idl not readable");
+                    idls.insert(name.clone(), idl);
+                }
+            })
+            .collect::<Vec<_>>();
+    }
+
+    // Finalize program list with all programs with IDLs.
+    let programs = match cfg.programs.get(&cfg.provider.cluster) {
+        None => Vec::new(),
+        Some(programs) => programs
+            .iter()
+            .filter_map(|(name, program_deployment)| {
+                Some(ProgramWorkspace {
+                    name: name.to_string(),
+                    program_id: program_deployment.address,
+                    idl: match idls.get(name) {
+                        None => return None,
+                        Some(idl) => idl.clone(),
+                    },
+                })
+            })
+            .collect::<Vec<ProgramWorkspace>>(),
+    };
+    Ok(programs)
+}
+
 fn run(cfg_override: &ConfigOverride, script: String) -> Result<()> {
     with_workspace(cfg_override, |cfg| {
         let url = cluster_url(cfg);
@@ -2555,11 +4192,13 @@ fn publish(
     }
 
     let anchor_package = AnchorPackage::from(program_name.clone(), &cfg)?;
-    let anchor_package_bytes = serde_json::to_vec(&anchor_package)?;
+    let version = Manifest::from_path(program.path().join("Cargo.toml"))?.version();
+    anchor_registry::validate_version(&version)?;
 
     // Set directory to top of the workspace.
     let workspace_dir = cfg.path().parent().unwrap();
     std::env::set_current_dir(workspace_dir)?;
+    let git_commit = git_commit();
 
     // Create the workspace tarball.
     let dot_anchor = workspace_dir.join(".anchor");
@@ -2642,7 +4281,8 @@ fn publish(
         None,
         None,
         true,
-        Some(program_name),
+        false,
+        Some(program_name.clone()),
         None,
         None,
         BootstrapMode::None,
@@ -2653,34 +4293,137 @@ fn publish(
 
     // Success. Now we can finally upload to the server without worrying
     // about a build failure.
+    let binary = fs::read(
+        ws_dir
+            .join("target/verifiable")
+            .join(format!("{}.so", program_name)),
+    )?;
+    let idl_path = ws_dir
+        .join("target/idl")
+        .join(format!("{}.json", program_name));
+    let idl = match idl_path.exists() {
+        true => Some(fs::read(&idl_path)?),
+        false => None,
+    };
+    let manifest = PackageManifest {
+        name: program_name.clone(),
+        version: version.clone(),
+        address: anchor_package.address,
+        binary_hash: bin_hash(&binary),
+        docker_image: cfg.docker(),
+        solana_version: cfg.solana_version.clone(),
+        git_commit,
+        idl: idl.is_some(),
+    };
 
-    // Upload the tarball to the server.
+    // Upload the files, then the manifest, which publishes the version.
+    let registry = &cfg.registry.url;
     let token = registry_api_token(cfg_override)?;
-    let form = Form::new()
-        .part("manifest", Part::bytes(anchor_package_bytes))
-        .part("workspace", {
-            let file = File::open(&tarball_filename)?;
-            Part::reader(file)
-        });
     let client = Client::new();
-    let resp = client
-        .post(&format!("{}/api/v0/build", cfg.registry.url))
-        .bearer_auth(token)
-        .multipart(form)
-        .send()?;
-
-    if resp.status() == 200 {
-        println!("Build triggered");
-    } else {
-        println!(
-            "{:?}",
-            resp.text().unwrap_or_else(|_| "Server error".to_string())
-        );
+    let upload = |url: String, body: Vec<u8>| -> Result<()> {
+        registry_response(client.put(&url).bearer_auth(&token).body(body).send()?)?;
+        Ok(())
+    };
+    upload(
+        anchor_registry::file_url(registry, &program_name, &version, WORKSPACE_FILE),
+        fs::read(&tarball_filename)?,
+    )?;
+    if let Some(idl) = idl {
+        upload(
+            anchor_registry::file_url(registry, &program_name, &version, IDL_FILE),
+            idl,
+        )?;
     }
+    upload(
+        anchor_registry::version_url(registry, &program_name, &version),
+        serde_json::to_vec(&manifest)?,
+    )?;
+    println!("Published {}@{} to {}", program_name, version, registry);
 
     Ok(())
 }
 
+fn fetch(cfg_override: &ConfigOverride, package: String, out: Option<String>) -> Result<()> {
+    let registry = registry_url(cfg_override)?;
+    let manifest = fetch_package_manifest(&registry, &package)?;
+    let out = out.unwrap_or_else(|| format!("{}-{}", manifest.name, manifest.version));
+    if Path::new(&out).exists() {
+        return Err(anyhow!("{} already exists", out));
+    }
+    fetch_package_workspace(&registry, &manifest, Path::new(&out))?;
+    println!(
+        "Fetched {}@{} into {}",
+        manifest.name, manifest.version, out
+    );
+    println!("{}", serde_json::to_string_pretty(&manifest)?);
+    Ok(())
+}
+
+// The registry of the workspace, or the default one outside of a workspace.
+fn registry_url(cfg_override: &ConfigOverride) -> Result<String> {
+    Ok(match Config::discover(cfg_override)? {
+        Some(cfg) => cfg.registry.url.clone(),
+        None => RegistryConfig::default().url,
+    })
+}
+
+// Fetches the manifest of a `<name>@<version>` package id, or of the latest
+// version given only `<name>`.
+fn fetch_package_manifest(registry: &str, package: &str) -> Result<PackageManifest> {
+    let (name, version) = anchor_registry::parse_package_id(package)?;
+    let client = Client::new();
+    let version = match version {
+        Some(version) => version,
+        None => {
+            let url = anchor_registry::package_url(registry, &name);
+            let versions: PackageVersions =
+                serde_json::from_slice(&registry_response(client.get(&url).send()?)?.bytes()?)?;
+            versions
+                .latest()
+                .cloned()
+                .ok_or_else(|| anyhow!("{} has no published versions", name))?
+        }
+    };
+    let url = anchor_registry::version_url(registry, &name, &version);
+    let manifest = registry_response(client.get(&url).send()?)?.bytes()?;
+    Ok(serde_json::from_slice(&manifest)?)
+}
+
+// Downloads the workspace of a published version and unpacks it into `dir`,
+// along with its IDL.
+fn fetch_package_workspace(registry: &str, manifest: &PackageManifest, dir: &Path) -> Result<()> {
+    let client = Client::new();
+    let url = |file| anchor_registry::file_url(registry, &manifest.name, &manifest.version, file);
+
+    let workspace = registry_response(client.get(&url(WORKSPACE_FILE)).send()?)?.bytes()?;
+    fs::create_dir_all(dir)?;
+    Archive::new(GzDecoder::new(&workspace[..])).unpack(dir)?;
+
+    if manifest.idl {
+        let idl = registry_response(client.get(&url(IDL_FILE)).send()?)?.bytes()?;
+        let idl_dir = dir.join("target/idl");
+        fs::create_dir_all(&idl_dir)?;
+        fs::write(idl_dir.join(format!("{}.json", manifest.name)), idl)?;
+    }
+    Ok(())
+}
+
+// Turns error responses of the registry into errors.
+fn registry_response(resp: reqwest::blocking::Response) -> Result<reqwest::blocking::Response> {
+    if resp.status().is_success() {
+        return Ok(resp);
+    }
+    let status = resp.status();
+    let error = match resp
+        .bytes()
+        .map(|body| serde_json::from_slice::<ApiError>(&body))
+    {
+        Ok(Ok(ApiError { error })) => error,
+        _ => "Server error".to_string(),
+    };
+    Err(anyhow!("Registry request failed ({}): {}", status, error))
+}
+
 // Unpacks the tarball into the current directory.
 fn unpack_archive(tar_path: impl AsRef<Path>) -> Result<()> {
     let tar = GzDecoder::new(std::fs::File::open(tar_path)?);
@@ -2713,6 +4456,11 @@ fn registry_api_token(_cfg_override: &ConfigOverride) -> Result<String> {
 fn keys(cfg_override: &ConfigOverride, cmd: KeysCommand) -> Result<()> {
     match cmd {
         KeysCommand::List => keys_list(cfg_override),
+        KeysCommand::Sync { program_name } => keys_sync(cfg_override, program_name),
+        KeysCommand::New {
+            program_name,
+            force,
+        } => keys_new(cfg_override, program_name, force),
     }
 }
 
@@ -2725,6 +4473,179 @@ fn keys_list(cfg_override: &ConfigOverride) -> Result<()> {
     Ok(())
 }
 
+fn keys_sync(cfg_override: &ConfigOverride, program_name: Option<String>) -> Result<()> {
+    with_workspace(cfg_override, |cfg| {
+        let cluster = &cfg.provider.cluster;
+
+        let programs: Vec<Program> = cfg
+            .read_all_programs()?
+            .into_iter()
+            .filter(|p| program_name.is_none() || program_name.as_ref() == Some(&p.lib_name))
+            .collect();
+        if programs.is_empty() {
+            return Err(anyhow!("Program not found in workspace"));
+        }
+
+        for program in programs {
+            let address = program.pubkey()?;
+
+            // Sync the program's declare_id!.
+            let lib_rs = program.path.join("src/lib.rs");
+            let mut src = fs::read_to_string(&lib_rs)?;
+            match find_declare_id(&src) {
+                None => println!("{}: declare_id! not found, skipping", program.lib_name),
+                Some((range, declared)) => {
+                    if declared != address.to_string() {
+                        src.replace_range(range, &address.to_string());
+                        fs::write(&lib_rs, src)?;
+                        println!("{}: updated declare_id! to {}", program.lib_name, address);
+                    }
+                }
+            }
+
+            // Sync the program's address in Anchor.toml.
+            let configured = cfg
+                .programs
+                .get(cluster)
+                .and_then(|programs| programs.get(&program.lib_name))
+                .map(|deployment| deployment.address);
+            if configured != Some(address) {
+                cfg.write_program_address(cluster, &program.lib_name, &address)?;
+                println!(
+                    "{}: updated programs.{} address to {}",
+                    program.lib_name, cluster, address
+                );
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn keys_new(cfg_override: &ConfigOverride, program_name: String, force: bool) -> Result<()> {
+    with_workspace(cfg_override, |cfg| {
+        let program = cfg
+            .read_all_programs()?
+            .into_iter()
+            .find(|p| p.lib_name == program_name)
+            .ok_or_else(|| anyhow!("Program {} not found in workspace", program_name))?;
+
+        let keypair_path = program.keypair_path();
+        if keypair_path.exists() {
+            if !force {
+                return Err(anyhow!(
+                    "{} already exists. Use --force to replace it, changing the program id",
+                    keypair_path.display()
+                ));
+            }
+            fs::remove_file(&keypair_path)?;
+        }
+
+        println!("{}: {}", program.lib_name, program.pubkey()?);
+        println!("Run `anchor keys sync` to use the new program id in the workspace.");
+
+        Ok(())
+    })
+}
+
+// Finds the program id declared with `declare_id!` in the given source,
+// returning it along with its byte range. Comments, and string and character
+// literals, are skipped, so that only an actual invocation is matched.
+fn find_declare_id(src: &str) -> Option<(std::ops::Range<usize>, String)> {
+    let mut i = 0;
+    while i < src.len() {
+        let rest = &src[i..];
+        if rest.starts_with("//") {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if rest.starts_with("/*") {
+            i += rest.find("*/").map_or(rest.len(), |end| end + 2);
+        } else if rest.starts_with('"') {
+            i += 1 + string_literal_len(&rest[1..]);
+        } else if rest.starts_with("'\"'") {
+            i += 3;
+        } else if rest.starts_with("declare_id!")
+            && !src[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_')
+        {
+            let args = &rest["declare_id!".len()..];
+            let open = args
+                .trim_start()
+                .strip_prefix('(')
+                .map(str::trim_start)
+                .and_then(|args| args.strip_prefix('"'))
+                .map(|id| src.len() - id.len());
+            if let Some(open) = open {
+                let close = open + src[open..].find('"')?;
+                return Some((open..close, src[open..close].to_string()));
+            }
+            i += "declare_id!".len();
+        } else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    None
+}
+
+// Length of a string literal's contents, including its closing quote.
+fn string_literal_len(src: &str) -> usize {
+    let mut escaped = false;
+    for (i, c) in src.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return i + 1,
+            _ => {}
+        }
+    }
+    src.len()
+}
+
+// Warns if the program id in a program's `declare_id!` doesn't match the
+// program's keypair, or its address in Anchor.toml, in which case the
+// deployed program would reject its own instructions.
+fn check_program_id(cfg: &WithPath<Config>, cargo_toml: &Path) -> Result<()> {
+    let lib_name = Manifest::from_path(cargo_toml)?.lib_name()?;
+    let lib_rs = cargo_toml
+        .parent()
+        .ok_or_else(|| anyhow!("Unable to find parent"))?
+        .join("src/lib.rs");
+    let declared = match fs::read_to_string(lib_rs)
+        .ok()
+        .and_then(|src| find_declare_id(&src))
+    {
+        None => return Ok(()),
+        Some((_, declared)) => declared,
+    };
+
+    let workspace_dir = cfg.path().parent().expect("Invalid Anchor.toml");
+    let keypair_path = workspace_dir
+        .join("target/deploy")
+        .join(format!("{}-keypair.json", lib_name));
+    if let Ok(keypair) = solana_sdk::signature::read_keypair_file(&keypair_path) {
+        if keypair.pubkey().to_string() != declared {
+            println!(
+                "WARNING: {} declares the program id {}, but its keypair is {}. \
+                 Run `anchor keys sync` to update it.",
+                lib_name,
+                declared,
+                keypair.pubkey()
+            );
+        }
+    }
+
+    let cluster = &cfg.provider.cluster;
+    if let Some(deployment) = cfg.programs.get(cluster).and_then(|p| p.get(&lib_name)) {
+        if deployment.address.to_string() != declared {
+            println!(
+                "WARNING: {} declares the program id {}, but its address in Anchor.toml \
+                 for {} is {}.",
+                lib_name, declared, cluster, deployment.address
+            );
+        }
+    }
+
+    Ok(())
+}
+
 fn localnet(
     cfg_override: &ConfigOverride,
     skip_build: bool,
@@ -2739,6 +4660,7 @@ fn localnet(
                 None,
                 None,
                 false,
+                false,
                 None,
                 None,
                 None,
@@ -2754,11 +4676,11 @@ fn localnet(
             false => Some(validator_flags(cfg)?),
         };
 
-        let validator_handle = &mut start_test_validator(cfg, flags, false)?;
+        let validator_handle = &mut start_test_validator(cfg, flags, false, 0)?;
 
         // Setup log reader.
-        let url = test_validator_rpc_url(cfg);
-        let log_streams = stream_logs(cfg, &url);
+        let ws_url = test_validator_ws_url(cfg, 0)?;
+        let log_stream = stream_logs(cfg, &ws_url, 0, true);
 
         std::io::stdin().lock().lines().next().unwrap().unwrap();
 
@@ -2771,11 +4693,7 @@ fn localnet(
             );
         }
 
-        for mut child in log_streams? {
-            if let Err(err) = child.kill() {
-                println!("Failed to kill subprocess {}: {}", child.id(), err);
-            }
-        }
+        log_stream?.stop();
 
         Ok(())
     })
@@ -2831,3 +4749,237 @@ fn get_node_dns_option() -> Result<&'static str> {
     };
     Ok(option)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_declare_id_skips_comments_and_strings() {
+        let id = "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS";
+        let src = format!(
+            r#"// declare_id!("11111111111111111111111111111111");
+/* declare_id!("11111111111111111111111111111111"); */
+const NOTE: &str = "use declare_id!(\"11111111111111111111111111111111\")";
+const QUOTE: char = '"';
+my_declare_id!("11111111111111111111111111111111");
+anchor_lang::declare_id! ( "{}" );
+"#,
+            id
+        );
+        let (range, declared) = find_declare_id(&src).unwrap();
+        assert_eq!(declared, id);
+        assert_eq!(&src[range], id);
+        assert_eq!(find_declare_id("// declare_id!(\"x\");\n"), None);
+    }
+
+    #[test]
+    fn parse_json_arg_keeps_wide_integers() {
+        let max = u128::MAX.to_string();
+        assert_eq!(parse_json_arg(&max), JsonValue::String(max.clone()));
+        let min = i128::MIN.to_string();
+        assert_eq!(parse_json_arg(&min), JsonValue::String(min.clone()));
+        assert_eq!(parse_json_arg("18446744073709551615"), u64::MAX.into());
+        assert_eq!(parse_json_arg("1.5"), 1.5.into());
+        assert_eq!(parse_json_arg("[1, 2]"), serde_json::json!([1, 2]));
+        assert_eq!(parse_json_arg("name"), JsonValue::String("name".into()));
+
+        // The strings are encoded as the integers they hold.
+        let idl = coder::tests::idl();
+        let mut data = vec![];
+        coder::encode(&idl, &IdlType::U128, &parse_json_arg(&max), &mut data).unwrap();
+        assert_eq!(data, u128::MAX.to_le_bytes());
+    }
+
+    // A workspace in a fresh temporary directory, with a program depending on
+    // another one through a path dependency.
+    fn build_workspace(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join("anchor-build-tests")
+            .join(format!("{}-{}", name, std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        for (program, deps) in &[("a", "b = { path = \"../b\" }"), ("b", "")] {
+            let program_dir = dir.join("programs").join(program);
+            fs::create_dir_all(program_dir.join("src")).unwrap();
+            fs::write(program_dir.join("src/lib.rs"), "// v1").unwrap();
+            fs::write(
+                program_dir.join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{}\"\nversion = \"0.1.0\"\n\n[dependencies]\n{}\n",
+                    program, deps
+                ),
+            )
+            .unwrap();
+        }
+        fs::write(dir.join("Cargo.toml"), "[workspace]\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn build_fingerprint_tracks_build_inputs() {
+        let dir = build_workspace("fingerprint");
+        let cargo_toml = dir.join("programs/a/Cargo.toml");
+        let fingerprint = |args: &[String], toolchain: &str| {
+            build_fingerprint(&dir, &cargo_toml, args, toolchain).unwrap()
+        };
+        let toolchain = "cargo-build-bpf 1.8.5\nRUSTFLAGS=";
+        let base = fingerprint(&[], toolchain);
+        assert_eq!(fingerprint(&[], toolchain), base);
+
+        // Hidden files and build outputs don't count.
+        fs::create_dir_all(dir.join("programs/a/target")).unwrap();
+        fs::write(dir.join("programs/a/target/out"), "").unwrap();
+        fs::write(dir.join("programs/a/.swp"), "").unwrap();
+        assert_eq!(fingerprint(&[], toolchain), base);
+
+        assert_ne!(
+            fingerprint(&["--features".into(), "x".into()], toolchain),
+            base
+        );
+        assert_ne!(fingerprint(&[], "cargo-build-bpf 1.9.0\nRUSTFLAGS="), base);
+        assert_ne!(
+            fingerprint(&[], "cargo-build-bpf 1.8.5\nRUSTFLAGS=-C opt-level=1"),
+            base
+        );
+
+        fs::write(dir.join("programs/b/src/lib.rs"), "// v2").unwrap();
+        let dep_changed = fingerprint(&[], toolchain);
+        assert_ne!(dep_changed, base);
+        fs::write(dir.join("Cargo.lock"), "version = 3\n").unwrap();
+        assert_ne!(fingerprint(&[], toolchain), dep_changed);
+    }
+
+    #[test]
+    fn build_cache_is_fresh_for_its_fingerprint() {
+        let dir = build_workspace("cache");
+        let cache_dir = dir.join("target/.anchor-cache/a");
+        let binary = dir.join("target/deploy/a.so");
+        let cargo_toml = dir.join("programs/a/Cargo.toml");
+        let toolchain = "cargo-build-bpf 1.8.5\nRUSTFLAGS=";
+        let fingerprint = build_fingerprint(&dir, &cargo_toml, &[], toolchain).unwrap();
+
+        fs::create_dir_all(&cache_dir).unwrap();
+        fs::write(cache_dir.join("fingerprint"), &fingerprint).unwrap();
+        // Without a binary, the build must run again.
+        assert!(!is_build_fresh(&cache_dir, &binary, &fingerprint));
+        fs::create_dir_all(binary.parent().unwrap()).unwrap();
+        fs::write(&binary, "").unwrap();
+        assert!(is_build_fresh(&cache_dir, &binary, &fingerprint));
+
+        // Any change to the inputs invalidates the cache.
+        fs::write(dir.join("programs/a/src/lib.rs"), "// v2").unwrap();
+        let changed = build_fingerprint(&dir, &cargo_toml, &[], toolchain).unwrap();
+        assert!(!is_build_fresh(&cache_dir, &binary, &changed));
+        let upgraded = build_fingerprint(&dir, &cargo_toml, &[], "cargo-build-bpf 1.9.0").unwrap();
+        assert!(!is_build_fresh(&cache_dir, &binary, &upgraded));
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+        assert!(!is_build_fresh(&cache_dir, &binary, &fingerprint));
+    }
+
+    #[test]
+    fn test_shards_are_disjoint_and_cover_every_file() {
+        let dir = std::env::temp_dir()
+            .join("anchor-test-selection")
+            .join(std::process::id().to_string());
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        for file in &[
+            "a.ts",
+            "b.js",
+            "c.ts",
+            "d.ts",
+            "e.js",
+            "nested/f.ts",
+            "notes.md",
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let dir_arg = dir.display().to_string();
+        assert!(is_test_files_arg(&dir_arg));
+        let pattern = format!("{}/**/*.ts", dir_arg);
+        let files = expand_test_files(&[dir_arg, pattern]).unwrap();
+        // The files of the directory, and the `.ts` files of the pattern.
+        assert_eq!(files.len(), 6);
+
+        for parallel in 1..=8 {
+            let shards = shard_test_files(files.clone(), parallel);
+            assert_eq!(shards.len(), parallel.min(files.len()));
+            assert!(shards.iter().all(|shard| !shard.is_empty()));
+            let mut sharded: Vec<String> = shards.into_iter().flatten().collect();
+            sharded.sort();
+            // Every file is in exactly one shard.
+            assert_eq!(sharded, files);
+        }
+    }
+
+    #[test]
+    fn test_validators_never_share_ports() {
+        use crate::config::Validator;
+
+        let mut cfg = Config::default();
+        let ports = |cfg: &Config, instance| {
+            let (rpc_port, faucet_port) = test_validator_ports(cfg, instance);
+            let flags = isolate_validator_flags(cfg, vec![], instance);
+            let gossip_port = flags
+                .iter()
+                .position(|flag| flag == "--gossip-port")
+                .map(|idx| flags[idx + 1].parse::<u16>().unwrap());
+            // The RPC websocket listens on the port after the RPC port.
+            let mut ports = vec![rpc_port, rpc_port + 1, faucet_port];
+            ports.extend(gossip_port);
+            ports
+        };
+        let assert_disjoint = |cfg: &Config| {
+            let mut seen = BTreeSet::new();
+            for instance in 0..16 {
+                for port in ports(cfg, instance) {
+                    assert!(seen.insert(port), "port {} is shared", port);
+                }
+            }
+        };
+        assert_eq!(test_validator_ports(&cfg, 0), (8899, 9900));
+        assert_disjoint(&cfg);
+
+        cfg.test = Some(Test {
+            genesis: None,
+            clone: None,
+            validator: Some(Validator {
+                rpc_port: 10000,
+                faucet_port: Some(10100),
+                gossip_port: Some(10200),
+                ..Validator::default()
+            }),
+            startup_wait: None,
+        });
+        assert_eq!(test_validator_ports(&cfg, 1), (10002, 10101));
+        assert_disjoint(&cfg);
+
+        // Ports given in the validator flags are replaced by the instance's.
+        let flags = vec![
+            "--rpc-port".to_string(),
+            "8899".to_string(),
+            "--gossip-port".to_string(),
+            "8001".to_string(),
+            "--limit-ledger-size".to_string(),
+            "100".to_string(),
+        ];
+        assert_eq!(
+            isolate_validator_flags(&cfg, flags, 2),
+            vec![
+                "--limit-ledger-size",
+                "100",
+                "--rpc-port",
+                "10004",
+                "--faucet-port",
+                "10102",
+                "--gossip-port",
+                "10202",
+            ]
+        );
+    }
+}
@@ -0,0 +1,591 @@
+//! Decoding of program logs, used by `anchor logs` and to stream the logs of
+//! the test validator during `anchor test`.
+//!
+//! Logs are received over a websocket subscription and decoded per
+//! transaction: program addresses are replaced by their names, events are
+//! deserialized with the IDL of the program emitting them, and custom error
+//! codes are mapped to the errors of the IDL or of the framework.
+
+use crate::coder;
+use anchor_lang::__private::base64;
+use anchor_lang::error::ErrorCode;
+use anchor_syn::idl::Idl;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
+use solana_client::pubsub_client::{PubsubClient, PubsubClientSubscription};
+use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use solana_client::rpc_response::{Response, RpcLogsResponse};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+pub type LogsSubscription = PubsubClientSubscription<Response<RpcLogsResponse>>;
+
+/// A program whose logs are decoded.
+pub struct LogProgram {
+    pub address: Pubkey,
+    /// Name the program is shown with, e.g., its library name.
+    pub name: Option<String>,
+    /// IDL of the program, if it's an Anchor program.
+    pub idl: Option<Idl>,
+}
+
+/// Decodes the logs of transactions invoking a set of known programs.
+pub struct LogDecoder {
+    programs: HashMap<String, LogProgram>,
+}
+
+impl LogDecoder {
+    pub fn new(programs: Vec<LogProgram>) -> Self {
+        Self {
+            programs: programs
+                .into_iter()
+                .map(|program| (program.address.to_string(), program))
+                .collect(),
+        }
+    }
+
+    pub fn program(&self, address: &Pubkey) -> Option<&LogProgram> {
+        self.programs.get(&address.to_string())
+    }
+
+    /// Whether the transaction invoked any of the known programs.
+    pub fn is_known(&self, tx: &DecodedTransaction) -> bool {
+        self.first_known(tx).is_some()
+    }
+
+    /// The first of the known programs the transaction invoked, if any.
+    pub fn first_known<'t>(&self, tx: &'t DecodedTransaction) -> Option<&'t str> {
+        tx.programs
+            .iter()
+            .find(|p| self.programs.contains_key(*p))
+            .map(String::as_str)
+    }
+
+    pub fn decode(&self, response: &Response<RpcLogsResponse>) -> DecodedTransaction {
+        let mut programs: Vec<String> = vec![];
+        let mut stack: Vec<String> = vec![];
+        let mut logs = vec![];
+        for line in &response.value.logs {
+            let mut log = DecodedLog {
+                program: stack.last().cloned(),
+                depth: stack.len(),
+                message: line.clone(),
+                event: None,
+                error: None,
+            };
+            if let Some(data) = line
+                .strip_prefix("Program log: ")
+                .or_else(|| line.strip_prefix("Program data: "))
+            {
+                log.event = stack.last().and_then(|p| self.decode_event(p, data));
+            } else if let Some(rest) = line.strip_prefix("Program ") {
+                let mut parts = rest.splitn(2, ' ');
+                let address = parts.next().unwrap_or_default().to_string();
+                let action = parts.next().unwrap_or_default();
+                if action.starts_with("invoke") {
+                    stack.push(address.clone());
+                    if !programs.contains(&address) {
+                        programs.push(address.clone());
+                    }
+                } else if action == "success" {
+                    stack.pop();
+                } else if let Some(reason) = action.strip_prefix("failed: ") {
+                    stack.pop();
+                    log.error = reason
+                        .strip_prefix("custom program error: 0x")
+                        .and_then(|code| u32::from_str_radix(code, 16).ok())
+                        .map(|code| self.decode_error(&address, code));
+                }
+                if let Some(name) = self.programs.get(&address).and_then(|p| p.name.as_ref()) {
+                    log.message = line.replacen(&address, name, 1);
+                }
+                log.depth = log.depth.min(stack.len());
+                log.program = Some(address);
+            }
+            logs.push(log);
+        }
+
+        DecodedTransaction {
+            signature: response.value.signature.clone(),
+            slot: response.context.slot,
+            err: response.value.err.as_ref().map(|err| err.to_string()),
+            programs,
+            logs,
+        }
+    }
+
+    fn decode_event(&self, program: &str, data: &str) -> Option<DecodedEvent> {
+        let idl = self.programs.get(program)?.idl.as_ref()?;
+        let data = base64::decode(data).ok()?;
+        let event = coder::find_event(idl, &data)?;
+        Some(DecodedEvent {
+            name: event.name.clone(),
+            data: coder::decode_event(idl, event, &data).ok()?,
+        })
+    }
+
+    fn decode_error(&self, program: &str, code: u32) -> DecodedError {
+        let idl = self.programs.get(program).and_then(|p| p.idl.as_ref());
+        let idl_error = idl
+            .and_then(|idl| idl.errors.as_ref())
+            .and_then(|errors| errors.iter().find(|e| e.code == code));
+        if let Some(e) = idl_error {
+            return DecodedError {
+                code,
+                name: Some(e.name.clone()),
+                msg: e.msg.clone(),
+            };
+        }
+        // Framework errors only make sense for Anchor programs.
+        match ErrorCode::try_from(code) {
+            Ok(e) if idl.is_some() => DecodedError {
+                code,
+                name: Some(format!("{:?}", e)),
+                msg: Some(e.to_string()),
+            },
+            _ => DecodedError {
+                code,
+                name: None,
+                msg: None,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedTransaction {
+    pub signature: String,
+    pub slot: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub err: Option<String>,
+    /// Addresses of the programs invoked, in order of their first invocation.
+    pub programs: Vec<String>,
+    pub logs: Vec<DecodedLog>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedLog {
+    /// Address of the program the line is about, or which logged it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub program: Option<String>,
+    /// Depth of the invocation the line is about, for indentation.
+    #[serde(skip)]
+    pub depth: usize,
+    /// The line, with the program's address replaced by its name.
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<DecodedEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<DecodedError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DecodedEvent {
+    pub name: String,
+    pub data: JsonValue,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DecodedError {
+    pub code: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msg: Option<String>,
+}
+
+impl fmt::Display for DecodedTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Transaction {} (slot {})", self.signature, self.slot)?;
+        if let Some(err) = &self.err {
+            write!(f, " failed: {}", err)?;
+        }
+        writeln!(f)?;
+        for log in &self.logs {
+            write!(f, "  {}", "  ".repeat(log.depth))?;
+            match &log.event {
+                Some(event) => write!(f, "Program event: {} {}", event.name, event.data)?,
+                None => write!(f, "{}", log.message)?,
+            }
+            if let Some(error) = &log.error {
+                match (&error.name, &error.msg) {
+                    (Some(name), Some(msg)) => write!(f, " ({}: {})", name, msg)?,
+                    (Some(name), None) => write!(f, " ({})", name)?,
+                    _ => {}
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Subscribes to the logs of all transactions, with `None`, or otherwise to
+/// the logs of the transactions mentioning each of `programs`, over the
+/// websocket at `ws_url`. Each response is received along with the program
+/// of its subscription, or `None` for all programs.
+pub fn subscribe(
+    ws_url: &str,
+    programs: Option<&[Pubkey]>,
+) -> Result<(
+    Vec<LogsSubscription>,
+    Receiver<(Option<String>, Response<RpcLogsResponse>)>,
+)> {
+    let programs: Vec<Option<String>> = match programs {
+        None => vec![None],
+        Some(programs) => programs.iter().map(|p| Some(p.to_string())).collect(),
+    };
+    let (sender, receiver) = channel();
+    let mut subscriptions = vec![];
+    for program in programs {
+        let filter = match &program {
+            None => RpcTransactionLogsFilter::All,
+            Some(program) => RpcTransactionLogsFilter::Mentions(vec![program.clone()]),
+        };
+        let config = RpcTransactionLogsConfig {
+            commitment: Some(CommitmentConfig::confirmed()),
+        };
+        let (subscription, responses) = PubsubClient::logs_subscribe(ws_url, filter, config)
+            .map_err(|e| anyhow!("Unable to subscribe to the logs at {}: {}", ws_url, e))?;
+        subscriptions.push(subscription);
+        // Merges the responses of every subscription into one channel.
+        let sender = sender.clone();
+        std::thread::spawn(move || {
+            for response in responses {
+                if sender.send((program.clone(), response)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    Ok((subscriptions, receiver))
+}
+
+/// Writes decoded logs to a file per program, in the background.
+///
+/// Unlike a `PubsubClient` subscription, whose thread can only notice it's
+/// been dropped once a message arrives, the stream's websocket times out
+/// reads, so that it can be closed while no transaction is being logged.
+pub struct LogStream {
+    exit: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl LogStream {
+    /// Starts writing each transaction invoking a program with an entry in
+    /// `files`, keyed by address, to that program's file. With `all_logs`,
+    /// e.g., for a local validator, all logs are subscribed to at once.
+    /// Otherwise, each program is subscribed to on its own, since RPC nodes
+    /// of public clusters commonly reject or throttle subscriptions to the
+    /// logs of every transaction.
+    pub fn start(
+        ws_url: &str,
+        decoder: LogDecoder,
+        files: HashMap<String, PathBuf>,
+        all_logs: bool,
+    ) -> Result<Self> {
+        let mut socket = connect(ws_url)?;
+        // The program of each subscription request, by request id, or
+        // `None` for all programs.
+        let programs: Vec<Option<String>> = match all_logs {
+            true => vec![None],
+            false => files.keys().cloned().map(Some).collect(),
+        };
+        for (id, program) in programs.iter().enumerate() {
+            let filter = match program {
+                None => json!("all"),
+                Some(program) => json!({ "mentions": [program] }),
+            };
+            let request = json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "logsSubscribe",
+                "params": [filter, { "commitment": "confirmed" }],
+            });
+            socket
+                .write_message(Message::Text(request.to_string()))
+                .map_err(|e| anyhow!("Unable to subscribe to the logs at {}: {}", ws_url, e))?;
+        }
+
+        let exit = Arc::new(AtomicBool::new(false));
+        let thread_exit = exit.clone();
+        let thread = std::thread::spawn(move || {
+            // The program of each subscription, by subscription id.
+            let mut subscriptions: HashMap<u64, Option<String>> = HashMap::new();
+            while !thread_exit.load(Ordering::Relaxed) {
+                let message = match socket.read_message() {
+                    Ok(Message::Text(text)) => text,
+                    Ok(_) => continue,
+                    Err(tungstenite::Error::Io(e))
+                        if matches!(
+                            e.kind(),
+                            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                        ) =>
+                    {
+                        continue
+                    }
+                    // The connection was closed, e.g., by shutting down the
+                    // validator.
+                    Err(_) => break,
+                };
+                let message: JsonValue = match serde_json::from_str(&message) {
+                    Ok(message) => message,
+                    Err(_) => continue,
+                };
+
+                // Replies to the subscription requests.
+                if let (Some(id), Some(subscription)) =
+                    (message["id"].as_u64(), message["result"].as_u64())
+                {
+                    if let Some(program) = programs.get(id as usize) {
+                        subscriptions.insert(subscription, program.clone());
+                    }
+                    continue;
+                }
+
+                let params = &message["params"];
+                let subscribed = match params["subscription"]
+                    .as_u64()
+                    .and_then(|id| subscriptions.get(&id))
+                {
+                    Some(subscribed) => subscribed,
+                    None => continue,
+                };
+                let response: Response<RpcLogsResponse> =
+                    match serde_json::from_value(params["result"].clone()) {
+                        Ok(response) => response,
+                        Err(_) => continue,
+                    };
+                let tx = decoder.decode(&response);
+                // A transaction invoking several programs is received once
+                // per subscription, so only the subscription's program is
+                // written to.
+                let programs = tx
+                    .programs
+                    .iter()
+                    .filter(|program| subscribed.as_ref().map_or(true, |p| p == *program));
+                for program in programs {
+                    if let Some(path) = files.get(program) {
+                        let written = OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(path)
+                            .and_then(|mut file| writeln!(file, "{}", tx));
+                        if let Err(err) = written {
+                            eprintln!("Failed to write logs to {}: {}", path.display(), err);
+                        }
+                    }
+                }
+            }
+            // Best effort, the connection may be closed already.
+            let _ = socket.close(None);
+            let _ = socket.write_pending();
+        });
+        Ok(Self { exit, thread })
+    }
+
+    /// Stops writing logs and closes the subscriptions.
+    pub fn stop(self) {
+        self.exit.store(true, Ordering::Relaxed);
+        if self.thread.join().is_err() {
+            eprintln!("Failed to stop streaming logs");
+        }
+    }
+}
+
+// How long the log stream's thread waits for a message before checking
+// whether it was stopped.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Connects to the websocket at `ws_url`, with reads timing out after
+// `READ_TIMEOUT`.
+fn connect(ws_url: &str) -> Result<WebSocket<MaybeTlsStream<TcpStream>>> {
+    let (socket, _response) = tungstenite::connect(ws_url)
+        .map_err(|e| anyhow!("Unable to connect to {}: {}", ws_url, e))?;
+    // The handshake expects blocking reads, so the timeout is only set once
+    // connected.
+    let stream = match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream,
+        MaybeTlsStream::NativeTls(stream) => stream.get_ref(),
+        _ => return Err(anyhow!("Unsupported websocket stream for {}", ws_url)),
+    };
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coder::tests::idl;
+    use anchor_syn::idl::{IdlErrorCode, IdlEvent, IdlEventField, IdlType};
+    use solana_client::rpc_response::RpcResponseContext;
+
+    fn response(logs: &[String]) -> Response<RpcLogsResponse> {
+        Response {
+            context: RpcResponseContext { slot: 1 },
+            value: RpcLogsResponse {
+                signature: "signature".to_string(),
+                err: None,
+                logs: logs.to_vec(),
+            },
+        }
+    }
+
+    fn decoder(program: Pubkey, other: Pubkey) -> LogDecoder {
+        let mut idl = idl();
+        idl.events = Some(vec![IdlEvent {
+            name: "Deposited".to_string(),
+            fields: vec![IdlEventField {
+                name: "amount".to_string(),
+                ty: IdlType::U64,
+                index: false,
+            }],
+        }]);
+        idl.errors = Some(vec![IdlErrorCode {
+            code: 6000,
+            name: "Overflow".to_string(),
+            msg: Some("Amount overflowed".to_string()),
+        }]);
+        LogDecoder::new(vec![
+            LogProgram {
+                address: program,
+                name: Some("fixture".to_string()),
+                idl: Some(idl),
+            },
+            // A program without an IDL, e.g., a native program.
+            LogProgram {
+                address: other,
+                name: None,
+                idl: None,
+            },
+        ])
+    }
+
+    #[test]
+    fn decode_tracks_invocations() {
+        let program = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let mut event = coder::event_discriminator("Deposited").to_vec();
+        event.extend(&5u64.to_le_bytes());
+        let logs = vec![
+            format!("Program {} invoke [1]", program),
+            "Program log: Instruction: Deposit".to_string(),
+            format!("Program {} invoke [2]", other),
+            format!("Program {} success", other),
+            format!("Program data: {}", base64::encode(&event)),
+            format!("Program {} consumed 100 of 200000 compute units", program),
+            format!("Program {} failed: custom program error: 0x1770", program),
+        ];
+        let tx = decoder(program, other).decode(&response(&logs));
+
+        assert_eq!(tx.programs, vec![program.to_string(), other.to_string()]);
+        let lines: Vec<_> = tx
+            .logs
+            .iter()
+            .map(|log| (log.program.clone(), log.depth))
+            .collect();
+        let (program, other) = (Some(program.to_string()), Some(other.to_string()));
+        assert_eq!(
+            lines,
+            vec![
+                (program.clone(), 0),
+                (program.clone(), 1),
+                (other.clone(), 1),
+                (other, 1),
+                (program.clone(), 1),
+                (program.clone(), 1),
+                (program, 0),
+            ]
+        );
+        assert_eq!(tx.logs[0].message, "Program fixture invoke [1]");
+        assert_eq!(
+            tx.logs[6].message,
+            "Program fixture failed: custom program error: 0x1770"
+        );
+
+        let event = tx.logs[4].event.as_ref().unwrap();
+        assert_eq!(event.name, "Deposited");
+        assert_eq!(event.data, serde_json::json!({ "amount": 5 }));
+        assert!(tx.logs[1].event.is_none());
+
+        let error = tx.logs[6].error.as_ref().unwrap();
+        assert_eq!(error.code, 6000);
+        assert_eq!(error.name.as_deref(), Some("Overflow"));
+        assert_eq!(error.msg.as_deref(), Some("Amount overflowed"));
+    }
+
+    #[test]
+    fn decode_framework_errors_of_anchor_programs() {
+        let program = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let decoder = decoder(program, other);
+        let failed = |address: &Pubkey, code: u32| {
+            let logs = vec![
+                format!("Program {} invoke [1]", address),
+                format!(
+                    "Program {} failed: custom program error: {:#x}",
+                    address, code
+                ),
+            ];
+            decoder.decode(&response(&logs)).logs[1]
+                .error
+                .as_ref()
+                .map(|e| (e.code, e.name.clone()))
+        };
+
+        let code = ErrorCode::ConstraintMut as u32;
+        assert_eq!(
+            failed(&program, code),
+            Some((code, Some("ConstraintMut".to_string())))
+        );
+        // Unknown to both the IDL and the framework.
+        assert_eq!(failed(&program, 7000), Some((7000, None)));
+        // Framework errors are only decoded for programs with an IDL.
+        assert_eq!(failed(&other, code), Some((code, None)));
+        assert_eq!(failed(&Pubkey::new_unique(), code), Some((code, None)));
+    }
+
+    #[test]
+    fn first_known_program() {
+        let program = Pubkey::new_unique();
+        let other = Pubkey::new_unique();
+        let unknown = Pubkey::new_unique();
+        let decoder = decoder(program, other);
+        let logs = vec![
+            format!("Program {} invoke [1]", unknown),
+            format!("Program {} invoke [2]", other),
+            format!("Program {} success", other),
+            format!("Program {} invoke [2]", program),
+            format!("Program {} success", program),
+            format!("Program {} success", unknown),
+        ];
+        let tx = decoder.decode(&response(&logs));
+        assert!(decoder.is_known(&tx));
+        assert_eq!(decoder.first_known(&tx), Some(other.to_string().as_str()));
+
+        let logs = vec![
+            format!("Program {} invoke [1]", unknown),
+            format!("Program {} success", unknown),
+        ];
+        let tx = decoder.decode(&response(&logs));
+        assert!(!decoder.is_known(&tx));
+        assert_eq!(decoder.first_known(&tx), None);
+    }
+}
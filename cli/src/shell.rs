@@ -0,0 +1,461 @@
+//! The Rust shell, `anchor shell --rust`: a REPL sending the instructions of
+//! the workspace's programs and decoding their accounts with their IDLs,
+//! without a node toolchain.
+
+use crate::coder;
+use crate::config::ProgramWorkspace;
+use crate::instruction;
+use anchor_syn::idl::{IdlAccountItem, IdlInstruction};
+use anyhow::{anyhow, Result};
+use heck::MixedCase;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use serde_json::Value as JsonValue;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::native_token::sol_to_lamports;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+const HELP: &str = r#"Commands:
+  <program>.<instruction> accounts={...} args={...} [signers=[<keypair>, ...]]
+        Sends an instruction. PDAs with seeds in the IDL, well known programs
+        and sysvars can be omitted from the accounts. The wallet pays and signs.
+  fetch [<program>.<Account>] <address>
+        Fetches and decodes an account, by default with the IDL of its owner.
+  pda <program>.<instruction>.<account> [accounts={...}] [args={...}]
+        Derives the address of a PDA from its seeds in the IDL.
+  airdrop <sol> [<address>]
+        Airdrops SOL to the address, by default to the wallet.
+  programs
+        Lists the programs, with their instructions and accounts.
+  help
+  exit"#;
+
+pub struct Shell {
+    programs: Vec<ProgramWorkspace>,
+    url: String,
+    client: RpcClient,
+    payer: Keypair,
+}
+
+impl Shell {
+    pub fn new(
+        programs: Vec<ProgramWorkspace>,
+        url: &str,
+        client: RpcClient,
+        payer: Keypair,
+    ) -> Self {
+        Self {
+            programs,
+            url: url.to_string(),
+            client,
+            payer,
+        }
+    }
+
+    /// Runs the REPL until `exit`, keeping its history in `history`.
+    pub fn run(&self, history: &Path) -> Result<()> {
+        let mut editor = Editor::<ShellHelper>::new();
+        editor.set_helper(Some(ShellHelper::new(&self.programs)));
+        // There's no history on the first run.
+        let _ = editor.load_history(history);
+
+        println!(
+            "Connected to {} with wallet {}. Type `help` for the commands.",
+            self.url,
+            self.payer.pubkey()
+        );
+        loop {
+            let line = match editor.readline("anchor> ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e.into()),
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            editor.add_history_entry(line);
+            if line == "exit" || line == "quit" {
+                break;
+            }
+            if let Err(e) = self.execute(line) {
+                println!("Error: {}", e);
+            }
+        }
+        editor.save_history(history)?;
+        Ok(())
+    }
+
+    fn execute(&self, line: &str) -> Result<()> {
+        let (command, rest) = split_command(line);
+        match command {
+            "help" => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            "programs" => {
+                self.print_programs();
+                Ok(())
+            }
+            "fetch" => self.fetch(rest),
+            "pda" => self.pda(rest),
+            "airdrop" => self.airdrop(rest),
+            _ if command.contains('.') => self.send(command, rest),
+            _ => Err(anyhow!("Unknown command {}, see `help`", command)),
+        }
+    }
+
+    fn program(&self, name: &str) -> Result<&ProgramWorkspace> {
+        self.programs
+            .iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| anyhow!("Program {} not found", name))
+    }
+
+    fn instruction(&self, program: &str, ix: &str) -> Result<(&ProgramWorkspace, &IdlInstruction)> {
+        let program = self.program(program)?;
+        let ix = program
+            .idl
+            .instructions
+            .iter()
+            .find(|i| i.name == ix.to_mixed_case())
+            .ok_or_else(|| anyhow!("Instruction {} not found in {}", ix, program.name))?;
+        Ok((program, ix))
+    }
+
+    fn print_programs(&self) {
+        for program in &self.programs {
+            println!("{} ({})", program.name, program.program_id);
+            let instructions: Vec<&str> = program
+                .idl
+                .instructions
+                .iter()
+                .map(|ix| ix.name.as_str())
+                .collect();
+            println!("  instructions: {}", instructions.join(", "));
+            let accounts: Vec<&str> = program
+                .idl
+                .accounts
+                .iter()
+                .map(|acc| acc.name.as_str())
+                .collect();
+            println!("  accounts: {}", accounts.join(", "));
+        }
+    }
+
+    fn send(&self, target: &str, rest: &str) -> Result<()> {
+        let (program, ix) = target
+            .split_once('.')
+            .ok_or_else(|| anyhow!("Expected <program>.<instruction>"))?;
+        let (program, ix) = self.instruction(program, ix)?;
+        let mut options = parse_options(rest, &["accounts", "args", "signers"])?;
+        let accounts = options.remove("accounts").unwrap_or(JsonValue::Null);
+        let args = options.remove("args").unwrap_or(JsonValue::Null);
+        let keypairs = match options.remove("signers") {
+            None => vec![],
+            Some(JsonValue::String(path)) => vec![read_keypair(&path)?],
+            Some(JsonValue::Array(paths)) => paths
+                .iter()
+                .map(|path| match path.as_str() {
+                    Some(path) => read_keypair(path),
+                    None => Err(anyhow!("Expected a keypair path, found {}", path)),
+                })
+                .collect::<Result<_>>()?,
+            Some(value) => return Err(anyhow!("Expected keypair paths, found {}", value)),
+        };
+
+        let ix = instruction::build_instruction(
+            &program.program_id,
+            &program.idl,
+            ix,
+            &accounts,
+            &args,
+        )?;
+        let signers = instruction::signers(&ix, &self.payer, &keypairs)?;
+        let (recent_hash, _fee_calc) = self.client.get_recent_blockhash()?;
+        let tx = Transaction::new_signed_with_payer(
+            &[ix],
+            Some(&self.payer.pubkey()),
+            &signers,
+            recent_hash,
+        );
+        let signature = self.client.send_and_confirm_transaction(&tx)?;
+        println!("{}", signature);
+        Ok(())
+    }
+
+    fn fetch(&self, rest: &str) -> Result<()> {
+        let tokens: Vec<&str> = rest.split_whitespace().collect();
+        let (account_type, address) = match tokens.as_slice() {
+            [account_type, address] => (Some(*account_type), *address),
+            [address] => (None, *address),
+            _ => return Err(anyhow!("Expected fetch [<program>.<Account>] <address>")),
+        };
+        let address = Pubkey::from_str(address)?;
+        let account = self
+            .client
+            .get_account(&address)
+            .map_err(|_| anyhow!("Account {} not found", address))?;
+
+        let (program, name) = match account_type {
+            Some(account_type) => {
+                let (program, name) = account_type
+                    .split_once('.')
+                    .ok_or_else(|| anyhow!("Expected <program>.<Account>"))?;
+                (self.program(program)?, name.to_string())
+            }
+            None => {
+                let program = self
+                    .programs
+                    .iter()
+                    .find(|p| p.program_id == account.owner)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Account {} is owned by {}, which has no IDL",
+                            address,
+                            account.owner
+                        )
+                    })?;
+                let account_def = coder::find_account(&program.idl, &account.data)
+                    .ok_or_else(|| anyhow!("Unknown account type of {}", address))?;
+                (program, account_def.name.clone())
+            }
+        };
+        let value = coder::decode_account(&program.idl, &name, &account.data)?;
+        println!(
+            "{}.{} {}",
+            program.name,
+            name,
+            serde_json::to_string_pretty(&value)?
+        );
+        Ok(())
+    }
+
+    fn pda(&self, rest: &str) -> Result<()> {
+        let (target, rest) = split_command(rest);
+        let mut parts = target.splitn(3, '.');
+        let (program, ix, account) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(program), Some(ix), Some(account)) => (program, ix, account),
+            _ => return Err(anyhow!("Expected pda <program>.<instruction>.<account>")),
+        };
+        let (program, ix) = self.instruction(program, ix)?;
+        let mut options = parse_options(rest, &["accounts", "args"])?;
+        let accounts = options.remove("accounts").unwrap_or(JsonValue::Null);
+        let args = options.remove("args").unwrap_or(JsonValue::Null);
+
+        let (address, bump) = instruction::find_account_pda(
+            &program.program_id,
+            &program.idl,
+            ix,
+            account,
+            &accounts,
+            &args,
+        )?;
+        println!("{} (bump {})", address, bump);
+        Ok(())
+    }
+
+    fn airdrop(&self, rest: &str) -> Result<()> {
+        let tokens: Vec<&str> = rest.split_whitespace().collect();
+        let (sol, address) = match tokens.as_slice() {
+            [sol] => (*sol, self.payer.pubkey()),
+            [sol, address] => (*sol, Pubkey::from_str(address)?),
+            _ => return Err(anyhow!("Expected airdrop <sol> [<address>]")),
+        };
+        let sol: f64 = sol.parse()?;
+        let signature = self
+            .client
+            .request_airdrop(&address, sol_to_lamports(sol))?;
+        for _ in 0..30 {
+            if self.client.confirm_transaction(&signature)? {
+                println!("Airdropped {} SOL to {}", sol, address);
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_secs(1));
+        }
+        Err(anyhow!("Airdrop {} wasn't confirmed", signature))
+    }
+}
+
+fn read_keypair(path: &str) -> Result<Keypair> {
+    read_keypair_file(&*shellexpand::tilde(path))
+        .map_err(|_| anyhow!("Unable to read keypair file {}", path))
+}
+
+// Splits a line into its first word and the rest.
+fn split_command(line: &str) -> (&str, &str) {
+    let line = line.trim();
+    match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim_start()),
+        None => (line, ""),
+    }
+}
+
+// Parses `key=value` options, where values are JSON, or a single word
+// otherwise, e.g., a path.
+fn parse_options(mut s: &str, keys: &[&str]) -> Result<HashMap<String, JsonValue>> {
+    let mut options = HashMap::new();
+    loop {
+        s = s.trim_start();
+        if s.is_empty() {
+            return Ok(options);
+        }
+        let (key, rest) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected key=value, found {}", s))?;
+        if !keys.contains(&key) {
+            return Err(anyhow!(
+                "Unknown option {}, expected {}",
+                key,
+                keys.join(", ")
+            ));
+        }
+        let mut values = serde_json::Deserializer::from_str(rest).into_iter::<JsonValue>();
+        let value = match values.next() {
+            Some(Ok(value)) => {
+                s = &rest[values.byte_offset()..];
+                value
+            }
+            _ => {
+                let (word, rest) = split_command(rest);
+                s = rest;
+                JsonValue::String(word.to_string())
+            }
+        };
+        options.insert(key.to_string(), value);
+    }
+}
+
+// Tab-completion of the commands, instructions, accounts and PDAs.
+struct ShellHelper {
+    commands: Vec<String>,
+    accounts: Vec<String>,
+    pdas: Vec<String>,
+}
+
+impl ShellHelper {
+    fn new(programs: &[ProgramWorkspace]) -> Self {
+        let mut helper = Self {
+            commands: ["fetch", "pda", "airdrop", "programs", "help", "exit"]
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            accounts: vec![],
+            pdas: vec![],
+        };
+        for program in programs {
+            for ix in &program.idl.instructions {
+                helper
+                    .commands
+                    .push(format!("{}.{}", program.name, ix.name));
+                let prefix = format!("{}.{}.", program.name, ix.name);
+                pda_paths(&ix.accounts, &prefix, &mut helper.pdas);
+            }
+            for account in &program.idl.accounts {
+                helper
+                    .accounts
+                    .push(format!("{}.{}", program.name, account.name));
+            }
+        }
+        helper
+    }
+}
+
+fn pda_paths(items: &[IdlAccountItem], prefix: &str, paths: &mut Vec<String>) {
+    for item in items {
+        match item {
+            IdlAccountItem::IdlAccount(acc) if acc.pda.is_some() => {
+                paths.push(format!("{}{}", prefix, acc.name))
+            }
+            IdlAccountItem::IdlAccount(_) => {}
+            IdlAccountItem::IdlAccounts(accs) => {
+                pda_paths(&accs.accounts, &format!("{}{}.", prefix, accs.name), paths)
+            }
+        }
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos].rfind(' ').map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        let previous: Vec<&str> = line[..start].split_whitespace().collect();
+        let candidates = match previous.as_slice() {
+            [] => &self.commands,
+            ["fetch"] => &self.accounts,
+            ["pda"] => &self.pdas,
+            _ => return Ok((start, vec![])),
+        };
+        Ok((
+            start,
+            candidates
+                .iter()
+                .filter(|c| c.starts_with(word))
+                .cloned()
+                .collect(),
+        ))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const KEYS: &[&str] = &["args", "accounts", "signer", "amount"];
+
+    #[test]
+    fn parse_options_json_values() {
+        let options = parse_options(
+            r#"args={"name": "a b", "data": [1, 2]}  amount=5 accounts={"vault":"abc"}"#,
+            KEYS,
+        )
+        .unwrap();
+        assert_eq!(options.len(), 3);
+        assert_eq!(options["args"], json!({"name": "a b", "data": [1, 2]}));
+        assert_eq!(options["amount"], json!(5));
+        assert_eq!(options["accounts"], json!({"vault": "abc"}));
+    }
+
+    #[test]
+    fn parse_options_words() {
+        let options = parse_options("signer=~/.config/solana/id.json amount=1sol", KEYS).unwrap();
+        assert_eq!(options["signer"], json!("~/.config/solana/id.json"));
+        assert_eq!(options["amount"], json!("1sol"));
+        assert!(parse_options("  ", KEYS).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_options_errors() {
+        assert!(parse_options("args", KEYS).is_err());
+        assert!(parse_options("amount=1 payer=abc", KEYS).is_err());
+    }
+}
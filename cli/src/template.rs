@@ -1,9 +1,15 @@
 use crate::config::ProgramWorkspace;
 use crate::VERSION;
 use anchor_syn::idl::Idl;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use heck::{CamelCase, MixedCase, SnakeCase};
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use walkdir::WalkDir;
 
 pub fn default_program_id() -> Pubkey {
     "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS"
@@ -19,6 +25,309 @@ members = [
 "#
 }
 
+/// Project templates of `anchor init --template`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProjectTemplate {
+    /// A program tested with mocha, in TypeScript or JavaScript.
+    Basic,
+    /// A program tested in Rust with anchor-client, without node dependencies.
+    RustTests,
+    /// Two programs, one invoking the other through CPI.
+    MultiplePrograms,
+    /// A program storing its data in a large zero copy account.
+    ZeroCopy,
+    /// A program minting SPL tokens.
+    TokenProgram,
+    /// A directory copied into the workspace, with its variables substituted,
+    /// see [`TemplateVariables`].
+    Custom(PathBuf),
+}
+
+pub const TEMPLATES: &[&str] = &[
+    "basic",
+    "rust-tests",
+    "multiple-programs",
+    "zero-copy",
+    "token-program",
+];
+
+/// Files to write, relative to the workspace root.
+pub type TemplateFiles = Vec<(PathBuf, Vec<u8>)>;
+
+/// A new workspace, as rendered by a [`ProjectTemplate`].
+pub struct TemplateWorkspace {
+    /// Library name and address of each program, for Anchor.toml.
+    pub programs: Vec<(String, Pubkey)>,
+    /// Command of the `test` script, if any.
+    pub test_script: Option<String>,
+    pub files: TemplateFiles,
+}
+
+impl TemplateWorkspace {
+    pub fn contains(&self, path: &str) -> bool {
+        self.files.iter().any(|(p, _)| p == Path::new(path))
+    }
+}
+
+impl ProjectTemplate {
+    /// Renders the files of a new workspace named `name`.
+    pub fn render_workspace(&self, name: &str, javascript: bool) -> Result<TemplateWorkspace> {
+        if javascript && *self != ProjectTemplate::Basic {
+            return Err(anyhow!(
+                "The {} template doesn't support --javascript",
+                self
+            ));
+        }
+        let mut workspace = TemplateWorkspace {
+            programs: vec![(name.to_snake_case(), default_program_id())],
+            test_script: None,
+            files: vec![],
+        };
+        if let ProjectTemplate::Custom(dir) = self {
+            let variables = TemplateVariables::new(name, self);
+            workspace.files = variables.render_dir(dir, |_| true)?;
+        } else {
+            workspace.files = self.render_program(name)?;
+        }
+
+        let ts_mocha_script = "yarn run ts-mocha -p ./tsconfig.json -t 1000000 tests/**/*.ts";
+        let (test_script, files) = match self {
+            ProjectTemplate::Basic if javascript => (
+                "yarn run mocha -t 1000000 tests/",
+                vec![
+                    ("package.json".to_string(), package_json()),
+                    (format!("tests/{}.js", name), mocha(name)),
+                    (
+                        "migrations/deploy.js".to_string(),
+                        deploy_script().to_string(),
+                    ),
+                ],
+            ),
+            ProjectTemplate::Basic => (
+                ts_mocha_script,
+                ts_files(name, ts_package_json(), ts_mocha(name)),
+            ),
+            ProjectTemplate::ZeroCopy => (
+                ts_mocha_script,
+                ts_files(name, ts_package_json(), zero_copy_ts_mocha(name)),
+            ),
+            ProjectTemplate::TokenProgram => (
+                ts_mocha_script,
+                ts_files(name, token_ts_package_json(), token_ts_mocha(name)),
+            ),
+            ProjectTemplate::MultiplePrograms => {
+                // The second program needs an address of its own, so its
+                // keypair is generated up front.
+                let caller = format!("{}_caller", name.to_snake_case());
+                let keypair = Keypair::new();
+                workspace.programs.push((caller.clone(), keypair.pubkey()));
+                workspace.files.extend(vec![
+                    (
+                        format!("programs/{}/Cargo.toml", caller).into(),
+                        caller_cargo_toml(name).into_bytes(),
+                    ),
+                    (
+                        format!("programs/{}/Xargo.toml", caller).into(),
+                        xargo_toml().as_bytes().to_vec(),
+                    ),
+                    (
+                        format!("programs/{}/src/lib.rs", caller).into(),
+                        caller_lib_rs(name, &keypair.pubkey()).into_bytes(),
+                    ),
+                    (
+                        format!("target/deploy/{}-keypair.json", caller).into(),
+                        serde_json::to_vec(&keypair.to_bytes().to_vec())?,
+                    ),
+                ]);
+                (
+                    ts_mocha_script,
+                    ts_files(name, ts_package_json(), caller_ts_mocha(name)),
+                )
+            }
+            ProjectTemplate::RustTests => (
+                "cargo test --manifest-path tests/Cargo.toml",
+                vec![
+                    ("tests/Cargo.toml".to_string(), rust_tests_cargo_toml(name)),
+                    (
+                        "tests/src/lib.rs".to_string(),
+                        rust_tests_lib_rs().to_string(),
+                    ),
+                    (
+                        "tests/src/test_initialize.rs".to_string(),
+                        rust_tests_test_initialize(name),
+                    ),
+                    ("migrations/Cargo.toml".to_string(), migrations_cargo_toml()),
+                    (
+                        "migrations/src/main.rs".to_string(),
+                        migrations_main_rs().to_string(),
+                    ),
+                ],
+            ),
+            // Custom templates bring their own Anchor.toml, if they need scripts.
+            ProjectTemplate::Custom(_) => ("", vec![]),
+        };
+        if !test_script.is_empty() {
+            workspace.test_script = Some(test_script.to_string());
+        }
+        workspace.files.extend(
+            files
+                .into_iter()
+                .map(|(path, contents)| (PathBuf::from(path), contents.into_bytes())),
+        );
+
+        for (path, contents) in [
+            ("Cargo.toml", virtual_manifest()),
+            (".gitignore", git_ignore()),
+        ] {
+            if !workspace.contains(path) {
+                workspace
+                    .files
+                    .push((path.into(), contents.as_bytes().to_vec()));
+            }
+        }
+        Ok(workspace)
+    }
+
+    /// Renders the files of a new program crate named `name`, i.e., the
+    /// `programs/{{name}}` directory of custom templates.
+    pub fn render_program(&self, name: &str) -> Result<TemplateFiles> {
+        let (cargo_toml, lib_rs) = match self {
+            ProjectTemplate::Basic
+            | ProjectTemplate::RustTests
+            | ProjectTemplate::MultiplePrograms => (cargo_toml(name), lib_rs(name)),
+            ProjectTemplate::ZeroCopy => (cargo_toml(name), zero_copy_lib_rs(name)),
+            ProjectTemplate::TokenProgram => (
+                format!("{}anchor-spl = \"{}\"\n", cargo_toml(name), VERSION),
+                token_lib_rs(name),
+            ),
+            ProjectTemplate::Custom(dir) => {
+                let program_dir = Path::new("programs").join("{{name}}");
+                let files = TemplateVariables::new(name, self)
+                    .render_dir(dir, |path| path.starts_with(&program_dir))?;
+                if files.is_empty() {
+                    return Err(anyhow!(
+                        "Template {} has no {} directory",
+                        dir.display(),
+                        program_dir.display()
+                    ));
+                }
+                return Ok(files);
+            }
+        };
+        let dir = Path::new("programs").join(name);
+        Ok(vec![
+            (dir.join("Cargo.toml"), cargo_toml.into_bytes()),
+            (dir.join("Xargo.toml"), xargo_toml().as_bytes().to_vec()),
+            (dir.join("src/lib.rs"), lib_rs.into_bytes()),
+        ])
+    }
+}
+
+impl FromStr for ProjectTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "basic" => Ok(ProjectTemplate::Basic),
+            "rust-tests" => Ok(ProjectTemplate::RustTests),
+            "multiple-programs" => Ok(ProjectTemplate::MultiplePrograms),
+            "zero-copy" => Ok(ProjectTemplate::ZeroCopy),
+            "token-program" => Ok(ProjectTemplate::TokenProgram),
+            _ if Path::new(s).is_dir() => Ok(ProjectTemplate::Custom(Path::new(s).canonicalize()?)),
+            _ => Err(anyhow!(
+                "Unknown template {}, expected one of {} or a template directory",
+                s,
+                TEMPLATES.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ProjectTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProjectTemplate::Basic => write!(f, "basic"),
+            ProjectTemplate::RustTests => write!(f, "rust-tests"),
+            ProjectTemplate::MultiplePrograms => write!(f, "multiple-programs"),
+            ProjectTemplate::ZeroCopy => write!(f, "zero-copy"),
+            ProjectTemplate::TokenProgram => write!(f, "token-program"),
+            ProjectTemplate::Custom(dir) => write!(f, "{}", dir.display()),
+        }
+    }
+}
+
+/// Variables substituted in the paths and contents of custom templates:
+///
+/// | Variable             | Value                                       |
+/// |----------------------|---------------------------------------------|
+/// | `{{name}}`           | Name of the workspace or program            |
+/// | `{{snake_name}}`     | The name in snake case, i.e., the lib name  |
+/// | `{{camel_name}}`     | The name in camel case, e.g., for IDL types |
+/// | `{{program_id}}`     | Placeholder address of the program          |
+/// | `{{anchor_version}}` | Version of the CLI                          |
+/// | `{{template}}`       | Path of the template                        |
+pub struct TemplateVariables(Vec<(&'static str, String)>);
+
+impl TemplateVariables {
+    pub fn new(name: &str, template: &ProjectTemplate) -> Self {
+        Self(vec![
+            ("{{name}}", name.to_string()),
+            ("{{snake_name}}", name.to_snake_case()),
+            ("{{camel_name}}", name.to_camel_case()),
+            ("{{program_id}}", default_program_id().to_string()),
+            ("{{anchor_version}}", VERSION.to_string()),
+            ("{{template}}", template.to_string()),
+        ])
+    }
+
+    pub fn render(&self, s: &str) -> String {
+        self.0
+            .iter()
+            .fold(s.to_string(), |s, (var, value)| s.replace(var, value))
+    }
+
+    /// Renders the files of `dir` whose path, before substitution, matches
+    /// `filter`. Files that aren't UTF-8 are copied as is.
+    fn render_dir(&self, dir: &Path, filter: impl Fn(&Path) -> bool) -> Result<TemplateFiles> {
+        let mut files = vec![];
+        let entries = WalkDir::new(dir)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != ".git");
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let path = entry.path().strip_prefix(dir)?;
+            if !filter(path) {
+                continue;
+            }
+            let contents = fs::read(entry.path())?;
+            let contents = match String::from_utf8(contents) {
+                Ok(s) => self.render(&s).into_bytes(),
+                Err(e) => e.into_bytes(),
+            };
+            files.push((
+                PathBuf::from(self.render(&path.to_string_lossy())),
+                contents,
+            ));
+        }
+        Ok(files)
+    }
+}
+
+fn ts_files(name: &str, package_json: String, mocha: String) -> Vec<(String, String)> {
+    vec![
+        ("tsconfig.json".to_string(), ts_config().to_string()),
+        ("package.json".to_string(), package_json),
+        (
+            "migrations/deploy.ts".to_string(),
+            ts_deploy_script().to_string(),
+        ),
+        (format!("tests/{}.ts", name), mocha),
+    ]
+}
+
 pub fn credentials(token: &str) -> String {
     format!(
         r#"[registry]
@@ -161,6 +470,40 @@ module.exports = async function (provider) {
 "#
 }
 
+pub fn migrations_cargo_toml() -> String {
+    format!(
+        r#"[package]
+name = "migrations"
+version = "0.1.0"
+description = "Migrations run by `anchor migrate`"
+edition = "2018"
+
+# Migrations run off chain, so they aren't part of the programs' workspace.
+[workspace]
+
+[dependencies]
+anchor-client = "{0}"
+anyhow = "1.0.32"
+"#,
+        VERSION,
+    )
+}
+
+pub fn migrations_main_rs() -> &'static str {
+    r#"use anchor_client::migration::{Migrations, Provider};
+
+fn main() -> anyhow::Result<()> {
+    // Migrations run in order of their ids, once per cluster.
+    Migrations::new().add(1, "initialize", initialize).run()
+}
+
+fn initialize(_provider: &Provider) -> anyhow::Result<()> {
+    // Add your deploy script here, e.g., with `provider.program(program_id)`.
+    Ok(())
+}
+"#
+}
+
 pub fn xargo_toml() -> &'static str {
     r#"[target.bpfel-unknown-unknown.dependencies.std]
 features = []
@@ -274,6 +617,369 @@ describe('{}', () => {{
     )
 }
 
+pub fn zero_copy_lib_rs(name: &str) -> String {
+    format!(
+        r#"use anchor_lang::prelude::*;
+
+declare_id!("{}");
+
+#[program]
+pub mod {} {{
+    use super::*;
+    pub fn initialize(ctx: Context<Initialize>) -> ProgramResult {{
+        let mut data = ctx.accounts.data.load_init()?;
+        data.authority = ctx.accounts.authority.key();
+        Ok(())
+    }}
+
+    pub fn update(ctx: Context<Update>, index: u64, value: u64) -> ProgramResult {{
+        let mut data = ctx.accounts.data.load_mut()?;
+        let entry = data
+            .values
+            .get_mut(index as usize)
+            .ok_or(ErrorCode::IndexOutOfBounds)?;
+        *entry = value;
+        Ok(())
+    }}
+}}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {{
+    // Zero copy accounts are too large to be created with `init`, so they're
+    // created by the client, in the same transaction.
+    #[account(zero)]
+    pub data: AccountLoader<'info, Data>,
+    pub authority: Signer<'info>,
+}}
+
+#[derive(Accounts)]
+pub struct Update<'info> {{
+    #[account(mut, has_one = authority)]
+    pub data: AccountLoader<'info, Data>,
+    pub authority: Signer<'info>,
+}}
+
+// Accessed in place, without deserializing the whole account.
+#[account(zero_copy)]
+pub struct Data {{
+    pub authority: Pubkey,
+    pub values: [u64; 1024],
+}}
+
+#[error]
+pub enum ErrorCode {{
+    #[msg("Index out of bounds")]
+    IndexOutOfBounds,
+}}
+"#,
+        default_program_id(),
+        name.to_snake_case(),
+    )
+}
+
+pub fn zero_copy_ts_mocha(name: &str) -> String {
+    format!(
+        r#"import * as anchor from '@project-serum/anchor';
+import {{ Program }} from '@project-serum/anchor';
+import {{ assert }} from 'chai';
+import {{ {0} }} from '../target/types/{1}';
+
+describe('{2}', () => {{
+
+  // Configure the client to use the local cluster.
+  const provider = anchor.Provider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.{0} as Program<{0}>;
+  const data = anchor.web3.Keypair.generate();
+
+  it('Is initialized!', async () => {{
+    await program.rpc.initialize({{
+      accounts: {{
+        data: data.publicKey,
+        authority: provider.wallet.publicKey,
+      }},
+      instructions: [await program.account.data.createInstruction(data)],
+      signers: [data],
+    }});
+  }});
+
+  it('Updates a value', async () => {{
+    await program.rpc.update(new anchor.BN(0), new anchor.BN(42), {{
+      accounts: {{
+        data: data.publicKey,
+        authority: provider.wallet.publicKey,
+      }},
+    }});
+    const account = await program.account.data.fetch(data.publicKey);
+    assert.ok(account.values[0].eq(new anchor.BN(42)));
+  }});
+}});
+"#,
+        name.to_camel_case(),
+        name.to_snake_case(),
+        name,
+    )
+}
+
+pub fn token_lib_rs(name: &str) -> String {
+    format!(
+        r#"use anchor_lang::prelude::*;
+use anchor_spl::token::{{self, Mint, MintTo, Token, TokenAccount}};
+
+declare_id!("{}");
+
+#[program]
+pub mod {} {{
+    use super::*;
+    pub fn initialize(_ctx: Context<Initialize>) -> ProgramResult {{
+        Ok(())
+    }}
+
+    pub fn mint_tokens(ctx: Context<MintTokens>, amount: u64) -> ProgramResult {{
+        let cpi_accounts = MintTo {{
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.token_account.to_account_info(),
+            authority: ctx.accounts.authority.to_account_info(),
+        }};
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token::mint_to(cpi_ctx, amount)
+    }}
+}}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {{
+    #[account(init, payer = authority, mint::decimals = 9, mint::authority = authority)]
+    pub mint: Account<'info, Mint>,
+    #[account(init, payer = authority, token::mint = mint, token::authority = authority)]
+    pub token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}}
+
+#[derive(Accounts)]
+pub struct MintTokens<'info> {{
+    #[account(mut)]
+    pub mint: Account<'info, Mint>,
+    #[account(mut, constraint = token_account.mint == mint.key())]
+    pub token_account: Account<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}}
+"#,
+        default_program_id(),
+        name.to_snake_case(),
+    )
+}
+
+pub fn token_ts_package_json() -> String {
+    format!(
+        r#"{{
+    "dependencies": {{
+        "@project-serum/anchor": "^{0}",
+        "@solana/spl-token": "^0.1.8"
+    }},
+    "devDependencies": {{
+        "chai": "^4.3.4",
+        "mocha": "^9.0.3",
+        "ts-mocha": "^8.0.0",
+        "@types/mocha": "^9.0.0",
+        "typescript": "^4.3.5"
+    }}
+}}
+"#,
+        VERSION
+    )
+}
+
+pub fn token_ts_mocha(name: &str) -> String {
+    format!(
+        r#"import * as anchor from '@project-serum/anchor';
+import {{ Program }} from '@project-serum/anchor';
+import {{ TOKEN_PROGRAM_ID }} from '@solana/spl-token';
+import {{ assert }} from 'chai';
+import {{ {0} }} from '../target/types/{1}';
+
+describe('{2}', () => {{
+
+  // Configure the client to use the local cluster.
+  const provider = anchor.Provider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.{0} as Program<{0}>;
+  const mint = anchor.web3.Keypair.generate();
+  const tokenAccount = anchor.web3.Keypair.generate();
+
+  it('Is initialized!', async () => {{
+    await program.rpc.initialize({{
+      accounts: {{
+        mint: mint.publicKey,
+        tokenAccount: tokenAccount.publicKey,
+        authority: provider.wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      }},
+      signers: [mint, tokenAccount],
+    }});
+  }});
+
+  it('Mints tokens', async () => {{
+    await program.rpc.mintTokens(new anchor.BN(100), {{
+      accounts: {{
+        mint: mint.publicKey,
+        tokenAccount: tokenAccount.publicKey,
+        authority: provider.wallet.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      }},
+    }});
+    const balance = await provider.connection.getTokenAccountBalance(
+      tokenAccount.publicKey
+    );
+    assert.equal(balance.value.amount, '100');
+  }});
+}});
+"#,
+        name.to_camel_case(),
+        name.to_snake_case(),
+        name,
+    )
+}
+
+pub fn caller_cargo_toml(name: &str) -> String {
+    format!(
+        r#"{0}{1} = {{ package = "{2}", path = "../{2}", features = ["cpi"] }}
+"#,
+        cargo_toml(&format!("{}_caller", name.to_snake_case())),
+        name.to_snake_case(),
+        name,
+    )
+}
+
+pub fn caller_lib_rs(name: &str, program_id: &Pubkey) -> String {
+    format!(
+        r#"use anchor_lang::prelude::*;
+use {0}::cpi::accounts::Initialize;
+use {0}::program::{1};
+
+declare_id!("{2}");
+
+#[program]
+pub mod {0}_caller {{
+    use super::*;
+    pub fn call_initialize(ctx: Context<CallInitialize>) -> ProgramResult {{
+        let cpi_ctx = CpiContext::new(ctx.accounts.{0}_program.to_account_info(), Initialize {{}});
+        {0}::cpi::initialize(cpi_ctx)
+    }}
+}}
+
+#[derive(Accounts)]
+pub struct CallInitialize<'info> {{
+    pub {0}_program: Program<'info, {1}>,
+}}
+"#,
+        name.to_snake_case(),
+        name.to_camel_case(),
+        program_id,
+    )
+}
+
+pub fn caller_ts_mocha(name: &str) -> String {
+    format!(
+        r#"import * as anchor from '@project-serum/anchor';
+import {{ Program }} from '@project-serum/anchor';
+import {{ {0} }} from '../target/types/{1}';
+import {{ {0}Caller }} from '../target/types/{1}_caller';
+
+describe('{2}', () => {{
+
+  // Configure the client to use the local cluster.
+  anchor.setProvider(anchor.Provider.env());
+
+  const program = anchor.workspace.{0} as Program<{0}>;
+  const caller = anchor.workspace.{0}Caller as Program<{0}Caller>;
+
+  it('Is initialized through CPI!', async () => {{
+    const tx = await caller.rpc.callInitialize({{
+      accounts: {{
+        {3}Program: program.programId,
+      }},
+    }});
+    console.log("Your transaction signature", tx);
+  }});
+}});
+"#,
+        name.to_camel_case(),
+        name.to_snake_case(),
+        name,
+        name.to_mixed_case(),
+    )
+}
+
+pub fn rust_tests_cargo_toml(name: &str) -> String {
+    format!(
+        r#"[package]
+name = "tests"
+version = "0.1.0"
+description = "Created with Anchor"
+edition = "2018"
+
+# Tests run off chain, so they aren't part of the programs' workspace.
+[workspace]
+
+[dependencies]
+anchor-client = "{0}"
+{1} = {{ package = "{2}", path = "../programs/{2}", features = ["no-entrypoint"] }}
+"#,
+        VERSION,
+        name.to_snake_case(),
+        name,
+    )
+}
+
+pub fn rust_tests_lib_rs() -> &'static str {
+    r#"#[cfg(test)]
+mod test_initialize;
+"#
+}
+
+pub fn rust_tests_test_initialize(name: &str) -> String {
+    format!(
+        r#"use anchor_client::solana_sdk::commitment_config::CommitmentConfig;
+use anchor_client::solana_sdk::signature::read_keypair_file;
+use anchor_client::{{Client, Cluster}};
+use std::rc::Rc;
+use std::str::FromStr;
+
+#[test]
+fn test_initialize() {{
+    // Set by `anchor test` to the validator it starts.
+    let url = std::env::var("ANCHOR_PROVIDER_URL").unwrap();
+    let wallet = std::env::var("ANCHOR_WALLET").unwrap();
+    let payer = read_keypair_file(&wallet).unwrap();
+
+    let client = Client::new_with_options(
+        Cluster::from_str(&url).unwrap(),
+        Rc::new(payer),
+        CommitmentConfig::confirmed(),
+    );
+    let program = client.program({0}::ID);
+    let tx = program
+        .request()
+        .accounts({0}::accounts::Initialize {{}})
+        .args({0}::instruction::Initialize {{}})
+        .send()
+        .unwrap();
+    println!("Your transaction signature {{}}", tx);
+}}
+"#,
+        name.to_snake_case(),
+    )
+}
+
 pub fn ts_config() -> &'static str {
     r#"{
   "compilerOptions": {
@@ -347,3 +1053,28 @@ anchor.workspace.{} = new anchor.Program({}, new PublicKey("{}"), provider);
 
     Ok(eval_string)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caller_cargo_toml_renames_hyphenated_package() {
+        let manifest: toml::Value = toml::from_str(&caller_cargo_toml("my-program")).unwrap();
+        assert_eq!(
+            manifest["package"]["name"].as_str(),
+            Some("my_program_caller")
+        );
+        let dependency = &manifest["dependencies"]["my_program"];
+        assert_eq!(dependency["package"].as_str(), Some("my-program"));
+        assert_eq!(dependency["path"].as_str(), Some("../my-program"));
+    }
+
+    #[test]
+    fn rust_tests_cargo_toml_renames_hyphenated_package() {
+        let manifest: toml::Value = toml::from_str(&rust_tests_cargo_toml("my-program")).unwrap();
+        let dependency = &manifest["dependencies"]["my_program"];
+        assert_eq!(dependency["package"].as_str(), Some("my-program"));
+        assert_eq!(dependency["path"].as_str(), Some("../programs/my-program"));
+    }
+}
@@ -0,0 +1,470 @@
+//! Decoding of transactions, used by `anchor tx decode`.
+//!
+//! Instructions of programs with an IDL are identified by their sighash, and
+//! their accounts and arguments are named and decoded with the IDL. Inner
+//! instructions and logs are only known for transactions fetched from the
+//! cluster, in which case the logs are decoded as by `anchor logs`.
+
+use crate::coder;
+use crate::instruction;
+use crate::logs::{DecodedTransaction, LogDecoder};
+use anchor_lang::__private::base64;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_response::{Response, RpcLogsResponse, RpcResponseContext};
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::message::Message;
+use solana_sdk::sanitize::Sanitize;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use solana_transaction_status::{UiInstruction, UiTransactionEncoding};
+use std::fmt;
+
+/// A transaction to decode, along with its status if it was executed.
+pub struct RawTransaction {
+    pub transaction: Transaction,
+    pub slot: Option<u64>,
+    pub err: Option<TransactionError>,
+    pub logs: Option<Vec<String>>,
+    /// Inner instructions, by index of the instruction invoking them.
+    pub inner_instructions: Vec<(u8, Vec<CompiledInstruction>)>,
+}
+
+impl RawTransaction {
+    /// Fetches an executed transaction from the cluster.
+    pub fn fetch(client: &RpcClient, signature: &Signature) -> Result<Self> {
+        let confirmed = client
+            .get_transaction(signature, UiTransactionEncoding::Base64)
+            .map_err(|e| anyhow!("Unable to fetch transaction {}: {}", signature, e))?;
+        let transaction = confirmed
+            .transaction
+            .transaction
+            .decode()
+            .ok_or_else(|| anyhow!("Unable to decode transaction {}", signature))?;
+        let mut raw = Self {
+            transaction,
+            slot: Some(confirmed.slot),
+            err: None,
+            logs: None,
+            inner_instructions: vec![],
+        };
+        if let Some(meta) = confirmed.transaction.meta {
+            raw.err = meta.err;
+            raw.logs = meta.log_messages;
+            for inner in meta.inner_instructions.unwrap_or_default() {
+                let instructions = inner
+                    .instructions
+                    .into_iter()
+                    .filter_map(|ix| match ix {
+                        UiInstruction::Compiled(ix) => Some(CompiledInstruction {
+                            program_id_index: ix.program_id_index,
+                            accounts: ix.accounts,
+                            data: bs58::decode(&ix.data).into_vec().ok()?,
+                        }),
+                        UiInstruction::Parsed(_) => None,
+                    })
+                    .collect();
+                raw.inner_instructions.push((inner.index, instructions));
+            }
+        }
+        raw.sanitize()?;
+        Ok(raw)
+    }
+
+    /// Parses a serialized transaction, encoded in base64 or base58.
+    pub fn parse(encoded: &str) -> Result<Self> {
+        let transaction = base64::decode(encoded)
+            .ok()
+            .and_then(|bytes| bincode::deserialize(&bytes).ok())
+            .or_else(|| {
+                bs58::decode(encoded)
+                    .into_vec()
+                    .ok()
+                    .and_then(|bytes| bincode::deserialize(&bytes).ok())
+            })
+            .ok_or_else(|| {
+                anyhow!("Expected a signature, or a base64 or base58 encoded transaction")
+            })?;
+        let raw = Self {
+            transaction,
+            slot: None,
+            err: None,
+            logs: None,
+            inner_instructions: vec![],
+        };
+        raw.sanitize()?;
+        Ok(raw)
+    }
+
+    // Checks that the accounts of all instructions are in the message, as
+    // they're looked up by index when decoding.
+    fn sanitize(&self) -> Result<()> {
+        self.transaction
+            .sanitize()
+            .map_err(|e| anyhow!("Invalid transaction: {}", e))?;
+        let num_keys = self.transaction.message.account_keys.len();
+        let in_message = |ix: &CompiledInstruction| {
+            (ix.program_id_index as usize) < num_keys
+                && ix.accounts.iter().all(|i| (*i as usize) < num_keys)
+        };
+        if !self
+            .inner_instructions
+            .iter()
+            .all(|(_, inner)| inner.iter().all(in_message))
+        {
+            return Err(anyhow!(
+                "Invalid transaction: inner instruction account index out of bounds"
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedTx {
+    pub signature: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slot: Option<u64>,
+    pub instructions: Vec<DecodedInstruction>,
+    /// Decoded logs, with the events emitted and the errors returned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logs: Option<DecodedTransaction>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedInstruction {
+    /// Position of the instruction, e.g., `1`, or `1.0` for the first inner
+    /// instruction of the second instruction.
+    pub index: String,
+    pub program: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub program_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub accounts: Vec<DecodedAccountMeta>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub args: Option<JsonValue>,
+    /// Base 58 encoded data, if the instruction couldn't be decoded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// Why the instruction couldn't be decoded, if its program has an IDL.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedAccountMeta {
+    /// Name of the account in the IDL, if any. Accounts past the IDL's are
+    /// remaining accounts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub pubkey: String,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// Decodes a transaction with the IDLs of the decoder's programs.
+pub fn decode(decoder: &LogDecoder, raw: &RawTransaction) -> DecodedTx {
+    let message = &raw.transaction.message;
+    let signature = raw
+        .transaction
+        .signatures
+        .first()
+        .map(ToString::to_string)
+        .unwrap_or_default();
+
+    let mut instructions = vec![];
+    for (i, ix) in message.instructions.iter().enumerate() {
+        instructions.push(decode_instruction(decoder, message, i.to_string(), ix));
+        let inner = raw
+            .inner_instructions
+            .iter()
+            .filter(|(index, _)| *index as usize == i)
+            .flat_map(|(_, inner)| inner);
+        for (j, ix) in inner.enumerate() {
+            instructions.push(decode_instruction(
+                decoder,
+                message,
+                format!("{}.{}", i, j),
+                ix,
+            ));
+        }
+    }
+
+    let logs = raw.logs.as_ref().map(|logs| {
+        decoder.decode(&Response {
+            context: RpcResponseContext {
+                slot: raw.slot.unwrap_or_default(),
+            },
+            value: RpcLogsResponse {
+                signature: signature.clone(),
+                err: raw.err.clone(),
+                logs: logs.clone(),
+            },
+        })
+    });
+
+    DecodedTx {
+        signature,
+        slot: raw.slot,
+        instructions,
+        logs,
+    }
+}
+
+fn decode_instruction(
+    decoder: &LogDecoder,
+    message: &Message,
+    index: String,
+    ix: &CompiledInstruction,
+) -> DecodedInstruction {
+    let program_id = message.account_keys[ix.program_id_index as usize];
+    let program = decoder.program(&program_id);
+    let mut decoded = DecodedInstruction {
+        index,
+        program: program_id.to_string(),
+        program_name: program.and_then(|p| p.name.clone()),
+        name: None,
+        accounts: ix
+            .accounts
+            .iter()
+            .map(|i| {
+                let i = *i as usize;
+                DecodedAccountMeta {
+                    name: None,
+                    pubkey: message.account_keys[i].to_string(),
+                    is_signer: i < message.header.num_required_signatures as usize,
+                    is_writable: is_writable(message, i),
+                }
+            })
+            .collect(),
+        args: None,
+        data: Some(bs58::encode(&ix.data).into_string()),
+        error: None,
+    };
+
+    let idl = match program.and_then(|p| p.idl.as_ref()) {
+        Some(idl) => idl,
+        None => return decoded,
+    };
+    if decoded.program_name.is_none() {
+        decoded.program_name = Some(idl.name.clone());
+    }
+    let idl_ix = match coder::find_instruction(idl, &ix.data) {
+        Some(idl_ix) => idl_ix,
+        None => {
+            decoded.error = Some("Unknown instruction sighash".to_string());
+            return decoded;
+        }
+    };
+    decoded.name = Some(idl_ix.name.clone());
+    for (account, name) in decoded
+        .accounts
+        .iter_mut()
+        .zip(instruction::account_names(&idl_ix.accounts))
+    {
+        account.name = Some(name);
+    }
+    match coder::decode_instruction(idl, idl_ix, &ix.data) {
+        Ok(args) => {
+            decoded.args = Some(args);
+            decoded.data = None;
+        }
+        Err(e) => decoded.error = Some(e.to_string()),
+    }
+    decoded
+}
+
+// Signed accounts come first, then unsigned ones, each with the read-only
+// accounts last.
+fn is_writable(message: &Message, i: usize) -> bool {
+    let header = &message.header;
+    let signed = header.num_required_signatures as usize;
+    if i < signed {
+        i < signed - header.num_readonly_signed_accounts as usize
+    } else {
+        i < message.account_keys.len() - header.num_readonly_unsigned_accounts as usize
+    }
+}
+
+impl fmt::Display for DecodedTx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Transaction {}", self.signature)?;
+        if let Some(slot) = self.slot {
+            write!(f, " (slot {})", slot)?;
+        }
+        writeln!(f)?;
+        for ix in &self.instructions {
+            let indent = "  ".repeat(ix.index.matches('.').count() + 1);
+            write!(f, "{}Instruction {}: ", indent, ix.index)?;
+            match (&ix.program_name, &ix.name) {
+                (Some(program), Some(name)) => write!(f, "{}.{}", program, name)?,
+                (Some(program), None) => write!(f, "{}", program)?,
+                _ => write!(f, "{}", ix.program)?,
+            }
+            writeln!(f)?;
+            for account in &ix.accounts {
+                let mut flags = vec![];
+                if account.is_writable {
+                    flags.push("writable");
+                }
+                if account.is_signer {
+                    flags.push("signer");
+                }
+                let name = account.name.as_deref().unwrap_or("remaining");
+                write!(f, "{}  {}: {}", indent, name, account.pubkey)?;
+                if !flags.is_empty() {
+                    write!(f, " ({})", flags.join(", "))?;
+                }
+                writeln!(f)?;
+            }
+            if let Some(args) = &ix.args {
+                writeln!(f, "{}  args: {}", indent, args)?;
+            }
+            if let Some(data) = &ix.data {
+                writeln!(f, "{}  data: {}", indent, data)?;
+            }
+            if let Some(error) = &ix.error {
+                writeln!(f, "{}  unable to decode: {}", indent, error)?;
+            }
+        }
+        if let Some(logs) = &self.logs {
+            write!(f, "{}", logs)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coder::tests::idl;
+    use crate::logs::LogProgram;
+    use serde_json::json;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+    use solana_sdk::pubkey::Pubkey;
+
+    fn initialize(program: Pubkey, keys: &[Pubkey]) -> Instruction {
+        let idl = idl();
+        let args = json!({
+            "amount": 1,
+            "total": "2",
+            "config": { "fee": null, "inner": { "flag": true, "delta": -3 } },
+        });
+        Instruction {
+            program_id: program,
+            accounts: vec![
+                AccountMeta::new(keys[0], false),
+                AccountMeta::new_readonly(keys[1], true),
+                AccountMeta::new(keys[2], false),
+                AccountMeta::new_readonly(keys[3], false),
+                // A remaining account.
+                AccountMeta::new_readonly(keys[4], false),
+            ],
+            data: coder::encode_instruction(&idl, &idl.instructions[0], &args).unwrap(),
+        }
+    }
+
+    #[test]
+    fn decode_nested_accounts() {
+        let program = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let keys: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+        let message = Message::new(&[initialize(program, &keys)], Some(&payer));
+        let raw = RawTransaction::parse(&base64::encode(
+            bincode::serialize(&Transaction::new_unsigned(message)).unwrap(),
+        ))
+        .unwrap();
+        let decoder = LogDecoder::new(vec![LogProgram {
+            address: program,
+            name: None,
+            idl: Some(idl()),
+        }]);
+
+        let decoded = decode(&decoder, &raw);
+        assert_eq!(decoded.instructions.len(), 1);
+        let ix = &decoded.instructions[0];
+        assert_eq!(ix.program_name.as_deref(), Some("fixture"));
+        assert_eq!(ix.name.as_deref(), Some("initialize"));
+        assert_eq!(
+            ix.args,
+            Some(json!({
+                "amount": 1,
+                "total": "2",
+                "config": { "fee": null, "inner": { "flag": true, "delta": -3 } },
+            }))
+        );
+        assert!(ix.data.is_none() && ix.error.is_none());
+
+        let accounts: Vec<_> = ix
+            .accounts
+            .iter()
+            .map(|a| (a.name.as_deref(), a.is_writable, a.is_signer))
+            .collect();
+        assert_eq!(
+            accounts,
+            vec![
+                (Some("state"), true, false),
+                (Some("auth.authority"), false, true),
+                (Some("auth.nested.vault"), true, false),
+                (Some("systemProgram"), false, false),
+                (None, false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_unknown_instruction() {
+        let program = Pubkey::new_unique();
+        let ix = Instruction::new_with_bytes(program, &[1, 2, 3], vec![]);
+        let raw = RawTransaction {
+            transaction: Transaction::new_unsigned(Message::new(&[ix], None)),
+            slot: None,
+            err: None,
+            logs: None,
+            inner_instructions: vec![],
+        };
+        let decoder = LogDecoder::new(vec![LogProgram {
+            address: program,
+            name: None,
+            idl: Some(idl()),
+        }]);
+        let ix = &decode(&decoder, &raw).instructions[0];
+        assert_eq!(ix.name, None);
+        assert_eq!(ix.data.as_deref(), Some("Ldp"));
+        assert_eq!(ix.error.as_deref(), Some("Unknown instruction sighash"));
+    }
+
+    #[test]
+    fn is_writable_by_header() {
+        let payer = Pubkey::new_unique();
+        let keys: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+        let message = Message::new(&[initialize(Pubkey::new_unique(), &keys)], Some(&payer));
+        let writable = |key: &Pubkey| {
+            let i = message.account_keys.iter().position(|k| k == key).unwrap();
+            is_writable(&message, i)
+        };
+        // Writable and read-only signers, then unsigned accounts.
+        assert!(writable(&payer));
+        assert!(!writable(&keys[1]));
+        assert!(writable(&keys[0]));
+        assert!(writable(&keys[2]));
+        assert!(!writable(&keys[3]));
+        assert!(!writable(&keys[4]));
+    }
+
+    #[test]
+    fn parse_rejects_out_of_bounds_accounts() {
+        let ix = Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]);
+        let mut message = Message::new(&[ix], Some(&Pubkey::new_unique()));
+        message.instructions[0].accounts.push(10);
+        let tx = Transaction::new_unsigned(message);
+        let encoded = base64::encode(bincode::serialize(&tx).unwrap());
+        assert!(RawTransaction::parse(&encoded).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! Checks that instructions encoded from an IDL by the CLI match the data of
+//! the program's own instructions, for the program of `fixtures/idl.json`.
+
+use anchor_cli::coder::encode_instruction;
+use anchor_lang::prelude::*;
+use anchor_lang::InstructionData;
+use anchor_syn::idl::Idl;
+use serde_json::{json, Value as JsonValue};
+
+declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
+
+#[program]
+pub mod fixture {
+    use super::*;
+
+    pub fn initialize(
+        _ctx: Context<Initialize>,
+        amount: u64,
+        total: u128,
+        config: Config,
+    ) -> ProgramResult {
+        let _ = (amount, total, config);
+        Ok(())
+    }
+
+    pub fn set_side(_ctx: Context<SetSide>, side: Side) -> ProgramResult {
+        let _ = side;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct Initialize {}
+
+#[derive(Accounts)]
+pub struct SetSide {}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Config {
+    pub fee: Option<u64>,
+    pub inner: Inner,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Inner {
+    pub flag: bool,
+    pub delta: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub enum Side {
+    Bid,
+    Ask { price: u64 },
+    Both(u8, i128),
+}
+
+fn encode(name: &str, args: JsonValue) -> Vec<u8> {
+    let idl: Idl = serde_json::from_str(include_str!("fixtures/idl.json")).unwrap();
+    let ix = idl.instructions.iter().find(|ix| ix.name == name).unwrap();
+    encode_instruction(&idl, ix, &args).unwrap()
+}
+
+#[test]
+fn initialize() {
+    let data = encode(
+        "initialize",
+        json!({
+            "amount": u64::MAX,
+            // Wide integers are given as strings, since they don't fit in
+            // JSON numbers.
+            "total": u128::MAX.to_string(),
+            "config": { "fee": 10, "inner": { "flag": true, "delta": -3 } },
+        }),
+    );
+    let expected = fixture::instruction::Initialize {
+        amount: u64::MAX,
+        total: u128::MAX,
+        config: Config {
+            fee: Some(10),
+            inner: Inner {
+                flag: true,
+                delta: -3,
+            },
+        },
+    };
+    assert_eq!(data, expected.data());
+
+    let data = encode(
+        "initialize",
+        json!({
+            "amount": 0,
+            "total": "1",
+            "config": { "fee": null, "inner": { "flag": false, "delta": 0 } },
+        }),
+    );
+    let expected = fixture::instruction::Initialize {
+        amount: 0,
+        total: 1,
+        config: Config {
+            fee: None,
+            inner: Inner {
+                flag: false,
+                delta: 0,
+            },
+        },
+    };
+    assert_eq!(data, expected.data());
+}
+
+#[test]
+fn set_side() {
+    let sides = vec![
+        (json!("Bid"), Side::Bid),
+        (json!({ "Ask": { "price": 5 } }), Side::Ask { price: 5 }),
+        (
+            json!({ "Both": [7, i128::MIN.to_string()] }),
+            Side::Both(7, i128::MIN),
+        ),
+    ];
+    for (side, expected) in sides {
+        let data = encode("setSide", json!({ "side": side }));
+        let expected = fixture::instruction::SetSide { side: expected };
+        assert_eq!(data, expected.data());
+    }
+}
@@ -1,6 +1,19 @@
 use anyhow::{anyhow, Result};
+use reqwest::header::{HeaderName, HeaderValue, CONTENT_TYPE};
 use serde::{Deserialize, Serialize};
+use solana_client::client_error::Result as ClientResult;
+use solana_client::rpc_client::{RpcClient, RpcClientConfig};
+use solana_client::rpc_custom_error::JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE;
+use solana_client::rpc_request::{RpcError, RpcRequest, RpcResponseErrorData};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
 use url::Url;
 
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
@@ -11,6 +24,118 @@ pub enum Cluster {
     Localnet,
     Debug,
     Custom(String, String),
+    /// A cluster saved in the user's clusters config, under the given name.
+    Named(String, ClusterEndpoint),
+}
+
+/// An RPC endpoint of a named cluster.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ClusterEndpoint {
+    pub url: String,
+    pub ws_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commitment: Option<String>,
+    /// HTTP headers to send with each request, e.g., an API key.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// Named clusters of the user, stored in `~/.config/anchor/clusters.toml`:
+///
+/// ```toml
+/// [clusters.my-rpc]
+/// url = "https://my-rpc.com"
+/// ws_url = "wss://my-rpc.com/ws"
+/// commitment = "confirmed"
+/// headers = { x-api-key = "..." }
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ClustersConfig {
+    #[serde(default)]
+    pub clusters: BTreeMap<String, ClusterEndpoint>,
+}
+
+impl ClustersConfig {
+    pub fn path() -> Result<PathBuf> {
+        dirs::home_dir()
+            .map(|home| home.join(".config/anchor/clusters.toml"))
+            .ok_or_else(|| anyhow!("Unable to find the home directory"))
+    }
+
+    /// Loads the config, which is empty if the file doesn't exist.
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| anyhow!("Invalid {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Adds a cluster, replacing any cluster of the same name.
+    pub fn add(&mut self, name: &str, endpoint: ClusterEndpoint) -> Result<()> {
+        if !is_cluster_name(name) {
+            return Err(anyhow!(
+                "Cluster names may only contain letters, digits, '-' and '_'"
+            ));
+        }
+        if builtin_cluster(name).is_some() || name.to_lowercase().contains("http") {
+            return Err(anyhow!("{} is reserved", name));
+        }
+        Url::parse(&endpoint.url)?;
+        Url::parse(&endpoint.ws_url)?;
+        if let Some(commitment) = &endpoint.commitment {
+            CommitmentConfig::from_str(commitment)
+                .map_err(|_| anyhow!("Invalid commitment: {}", commitment))?;
+        }
+        for (name, value) in &endpoint.headers {
+            HeaderName::from_str(name).map_err(|_| anyhow!("Invalid header name: {}", name))?;
+            HeaderValue::from_str(value)
+                .map_err(|_| anyhow!("Invalid value of header {}", name))?;
+        }
+        self.clusters.insert(name.to_string(), endpoint);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Cluster> {
+        self.clusters
+            .get(name)
+            .map(|endpoint| Cluster::Named(name.to_string(), endpoint.clone()))
+    }
+}
+
+impl FromStr for ClustersConfig {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        toml::from_str(s).map_err(Into::into)
+    }
+}
+
+fn is_cluster_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn builtin_cluster(name: &str) -> Option<Cluster> {
+    match name.to_lowercase().as_str() {
+        "t" | "testnet" => Some(Cluster::Testnet),
+        "m" | "mainnet" => Some(Cluster::Mainnet),
+        "d" | "devnet" => Some(Cluster::Devnet),
+        "l" | "localnet" => Some(Cluster::Localnet),
+        "g" | "debug" => Some(Cluster::Debug),
+        _ => None,
+    }
 }
 
 impl Default for Cluster {
@@ -22,42 +147,53 @@ impl Default for Cluster {
 impl FromStr for Cluster {
     type Err = anyhow::Error;
     fn from_str(s: &str) -> Result<Cluster> {
+        if let Some(cluster) = builtin_cluster(s) {
+            return Ok(cluster);
+        }
         match s.to_lowercase().as_str() {
-            "t" | "testnet" => Ok(Cluster::Testnet),
-            "m" | "mainnet" => Ok(Cluster::Mainnet),
-            "d" | "devnet" => Ok(Cluster::Devnet),
-            "l" | "localnet" => Ok(Cluster::Localnet),
-            "g" | "debug" => Ok(Cluster::Debug),
             url if url.contains("http") => {
                 let http_url = url;
 
                 // Websocket port is always +1 the http port.
                 let mut ws_url = Url::parse(http_url)?;
                 if let Some(port) = ws_url.port() {
-                    ws_url.set_port(Some(port + 1))
+                    ws_url
+                        .set_port(Some(port + 1))
                         .map_err(|_| anyhow!("Unable to set port"))?;
                 } else {
-                    ws_url.set_port(Some(8900))
+                    ws_url
+                        .set_port(Some(8900))
                         .map_err(|_| anyhow!("Unable to set port"))?;
                 }
                 if ws_url.scheme() == "https" {
-                    ws_url.set_scheme("wss")
+                    ws_url
+                        .set_scheme("wss")
                         .map_err(|_| anyhow!("Unable to set scheme"))?;
                 } else {
-                    ws_url.set_scheme("ws")
+                    ws_url
+                        .set_scheme("ws")
                         .map_err(|_| anyhow!("Unable to set scheme"))?;
                 }
 
-
                 Ok(Cluster::Custom(http_url.to_string(), ws_url.to_string()))
             }
-            _ => Err(anyhow::Error::msg(
-                "Cluster must be one of [localnet, testnet, mainnet, devnet] or be an http or https url\n",
-            )),
+            // Only names are looked up in the clusters config, so that a
+            // malformed config doesn't get in the way of other clusters.
+            _ if is_cluster_name(s) => match ClustersConfig::load() {
+                Ok(config) => config.get(s).ok_or_else(unknown_cluster),
+                Err(e) => Err(anyhow!("Unable to look up cluster {}: {}", s, e)),
+            },
+            _ => Err(unknown_cluster()),
         }
     }
 }
 
+fn unknown_cluster() -> anyhow::Error {
+    anyhow::Error::msg(
+        "Cluster must be one of [localnet, testnet, mainnet, devnet], an http or https url, or the name of a cluster added with `anchor cluster add`\n",
+    )
+}
+
 impl std::fmt::Display for Cluster {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let clust_str = match self {
@@ -67,6 +203,7 @@ impl std::fmt::Display for Cluster {
             Cluster::Localnet => "localnet",
             Cluster::Debug => "debug",
             Cluster::Custom(url, _ws_url) => url,
+            Cluster::Named(name, _endpoint) => name,
         };
         write!(f, "{}", clust_str)
     }
@@ -81,6 +218,7 @@ impl Cluster {
             Cluster::Localnet => "http://127.0.0.1:8899",
             Cluster::Debug => "http://34.90.18.145:8899",
             Cluster::Custom(url, _ws_url) => url,
+            Cluster::Named(_name, endpoint) => &endpoint.url,
         }
     }
     pub fn ws_url(&self) -> &str {
@@ -91,13 +229,136 @@ impl Cluster {
             Cluster::Localnet => "ws://127.0.0.1:9000",
             Cluster::Debug => "ws://34.90.18.145:9000",
             Cluster::Custom(_url, ws_url) => ws_url,
+            Cluster::Named(_name, endpoint) => &endpoint.ws_url,
+        }
+    }
+    /// The commitment configured for a named cluster.
+    pub fn commitment(&self) -> Option<CommitmentConfig> {
+        match self {
+            Cluster::Named(_name, endpoint) => endpoint
+                .commitment
+                .as_ref()
+                .and_then(|c| CommitmentConfig::from_str(c).ok()),
+            _ => None,
+        }
+    }
+    /// The HTTP headers configured for a named cluster.
+    pub fn headers(&self) -> Option<&BTreeMap<String, String>> {
+        match self {
+            Cluster::Named(_name, endpoint) => Some(&endpoint.headers),
+            _ => None,
+        }
+    }
+    /// An RPC client of the cluster, sending its headers, if any, with each
+    /// request.
+    pub fn rpc_client(&self, commitment: CommitmentConfig) -> RpcClient {
+        match self.headers() {
+            Some(headers) => rpc_client(self.url(), headers, commitment),
+            None => RpcClient::new_with_commitment(self.url().to_string(), commitment),
+        }
+    }
+}
+
+/// An RPC client of the cluster at `url`, sending `headers` with each
+/// request.
+pub fn rpc_client(
+    url: &str,
+    headers: &BTreeMap<String, String>,
+    commitment: CommitmentConfig,
+) -> RpcClient {
+    if headers.is_empty() {
+        return RpcClient::new_with_commitment(url.to_string(), commitment);
+    }
+    RpcClient::new_sender(
+        HeaderSender::new(url, headers),
+        RpcClientConfig::with_commitment(commitment),
+    )
+}
+
+/// Sends JSON RPC requests over HTTP, like the default sender of the
+/// `RpcClient`, along with a set of headers.
+struct HeaderSender {
+    client: reqwest::blocking::Client,
+    url: String,
+    headers: BTreeMap<String, String>,
+    request_id: AtomicU64,
+    stats: RwLock<RpcTransportStats>,
+}
+
+impl HeaderSender {
+    fn new(url: &str, headers: &BTreeMap<String, String>) -> Self {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .expect("build rpc client");
+        Self {
+            client,
+            url: url.to_string(),
+            headers: headers.clone(),
+            request_id: AtomicU64::new(0),
+            stats: RwLock::new(RpcTransportStats::default()),
+        }
+    }
+}
+
+impl RpcSender for HeaderSender {
+    fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        let start = Instant::now();
+        let id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let mut http_request = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(request.build_request_json(id, params).to_string());
+        for (name, value) in &self.headers {
+            http_request = http_request.header(name.as_str(), value.as_str());
+        }
+        let response = http_request.send().and_then(|r| r.error_for_status());
+        {
+            let mut stats = self.stats.write().unwrap();
+            stats.request_count += 1;
+            stats.elapsed_time += start.elapsed();
+        }
+        let mut json: serde_json::Value = serde_json::from_str(&response?.text()?)?;
+
+        let error = &json["error"];
+        if error.is_object() {
+            let code = error["code"].as_i64().unwrap_or_default();
+            let message = error["message"].as_str().unwrap_or_default().to_string();
+            // Preflight failures carry the simulation's result, e.g., its
+            // logs.
+            let data = match code {
+                JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE => {
+                    serde_json::from_value(error["data"].clone())
+                        .map(RpcResponseErrorData::SendTransactionPreflightFailure)
+                        .unwrap_or(RpcResponseErrorData::Empty)
+                }
+                _ => RpcResponseErrorData::Empty,
+            };
+            return Err(RpcError::RpcResponseError {
+                code,
+                message,
+                data,
+            }
+            .into());
         }
+        Ok(json["result"].take())
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.stats.read().unwrap().clone()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
 
     fn test_cluster(name: &str, cluster: Cluster) {
         assert_eq!(Cluster::from_str(name).unwrap(), cluster);
@@ -119,6 +380,86 @@ mod tests {
         Cluster::from_str(bad_url).unwrap();
     }
 
+    #[test]
+    fn test_clusters_config() {
+        let config = ClustersConfig::from_str(
+            r#"
+            [clusters.my-rpc]
+            url = "https://my-url.com/"
+            ws_url = "wss://my-url.com/ws"
+            commitment = "finalized"
+            headers = { x-api-key = "key" }
+            "#,
+        )
+        .unwrap();
+        let cluster = config.get("my-rpc").unwrap();
+        assert_eq!(cluster.to_string(), "my-rpc");
+        assert_eq!(cluster.url(), "https://my-url.com/");
+        assert_eq!(cluster.ws_url(), "wss://my-url.com/ws");
+        assert_eq!(cluster.commitment(), Some(CommitmentConfig::finalized()));
+        assert_eq!(cluster.headers().unwrap()["x-api-key"], "key");
+        assert!(config.get("other").is_none());
+    }
+
+    #[test]
+    fn test_clusters_config_reserved_names() {
+        let mut config = ClustersConfig::default();
+        let endpoint = ClusterEndpoint {
+            url: "https://my-url.com/".to_string(),
+            ws_url: "wss://my-url.com/".to_string(),
+            ..Default::default()
+        };
+        assert!(config.add("devnet", endpoint.clone()).is_err());
+        assert!(config.add("my/rpc", endpoint.clone()).is_err());
+        let invalid_header = ClusterEndpoint {
+            headers: vec![("x api key".to_string(), "key".to_string())]
+                .into_iter()
+                .collect(),
+            ..endpoint.clone()
+        };
+        assert!(config.add("my-rpc", invalid_header).is_err());
+        assert!(config.add("my-rpc", endpoint).is_ok());
+    }
+
+    #[test]
+    fn test_rpc_client_sends_headers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // Reads the request up to the end of its JSON body.
+            let mut request = vec![];
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"}") {
+                let n = stream.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            let body = r#"{"jsonrpc":"2.0","result":{"solana-core":"1.8.5"},"id":0}"#;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            String::from_utf8(request).unwrap().to_lowercase()
+        });
+
+        let headers = vec![("x-api-key".to_string(), "key".to_string())]
+            .into_iter()
+            .collect();
+        let client = rpc_client(&url, &headers, CommitmentConfig::confirmed());
+        // Unlike most requests, getting the version doesn't first get the
+        // version to pick the commitment.
+        assert_eq!(client.get_version().unwrap().solana_core, "1.8.5");
+        let request = server.join().unwrap();
+        assert!(request.contains("x-api-key: key\r\n"));
+        assert!(request.contains(r#""method":"getversion""#));
+    }
+
     #[test]
     fn test_http_port() {
         let url = "http://my-url.com:7000/";
@@ -1,6 +1,7 @@
 //! `anchor_client` provides an RPC client to send transactions and fetch
 //! deserialized accounts from Solana programs written in `anchor_lang`.
 
+use anchor_lang::error::ErrorCode;
 use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
 use anchor_lang::solana_program::program_error::ProgramError;
 use anchor_lang::solana_program::pubkey::Pubkey;
@@ -10,21 +11,30 @@ use regex::Regex;
 use solana_client::client_error::ClientError as SolanaClientError;
 use solana_client::pubsub_client::{PubsubClient, PubsubClientError, PubsubClientSubscription};
 use solana_client::rpc_client::RpcClient;
-use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use solana_client::rpc_config::{
+    RpcSendTransactionConfig, RpcSimulateTransactionConfig, RpcTransactionLogsConfig,
+    RpcTransactionLogsFilter,
+};
 use solana_client::rpc_response::{Response as RpcResponse, RpcLogsResponse};
 use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::InstructionError;
 use solana_sdk::signature::{Signature, Signer};
-use solana_sdk::transaction::Transaction;
-use std::convert::Into;
+use solana_sdk::signer::SignerError;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use std::collections::BTreeMap;
+use std::convert::{Into, TryFrom};
 use std::rc::Rc;
 use thiserror::Error;
 
 pub use anchor_lang;
-pub use cluster::Cluster;
+pub use cluster::{rpc_client, Cluster, ClusterEndpoint, ClustersConfig};
 pub use solana_client;
 pub use solana_sdk;
+pub use transaction::TransactionBuilder;
 
 mod cluster;
+pub mod migration;
+mod transaction;
 
 /// EventHandle unsubscribes from a program event stream on drop.
 pub type EventHandle = PubsubClientSubscription<RpcResponse<RpcLogsResponse>>;
@@ -40,9 +50,9 @@ impl Client {
     pub fn new(cluster: Cluster, payer: Rc<dyn Signer>) -> Self {
         Self {
             cfg: Config {
+                options: cluster.commitment(),
                 cluster,
                 payer,
-                options: None,
             },
         }
     }
@@ -61,6 +71,17 @@ impl Client {
         }
     }
 
+    /// Returns a builder for batching requests to several programs into as
+    /// few transactions as possible.
+    pub fn transaction(&self) -> TransactionBuilder {
+        TransactionBuilder::from(
+            self.cfg.cluster.url(),
+            self.cfg.payer.clone(),
+            self.cfg.options,
+        )
+        .headers(self.cfg.headers())
+    }
+
     pub fn program(&self, program_id: Pubkey) -> Program {
         Program {
             program_id,
@@ -81,6 +102,13 @@ struct Config {
     options: Option<CommitmentConfig>,
 }
 
+impl Config {
+    // The HTTP headers of the cluster, sent by the builders' RPC clients.
+    fn headers(&self) -> BTreeMap<String, String> {
+        self.cluster.headers().cloned().unwrap_or_default()
+    }
+}
+
 /// Program is the primary client handle to be used to build and send requests.
 #[derive(Debug)]
 pub struct Program {
@@ -102,6 +130,7 @@ impl Program {
             self.cfg.options,
             RequestNamespace::Global,
         )
+        .headers(self.cfg.headers())
     }
 
     /// Returns a request builder for program state.
@@ -113,14 +142,12 @@ impl Program {
             self.cfg.options,
             RequestNamespace::State { new: false },
         )
+        .headers(self.cfg.headers())
     }
 
     /// Returns the account at the given address.
     pub fn account<T: AccountDeserialize>(&self, address: Pubkey) -> Result<T, ClientError> {
-        let rpc_client = RpcClient::new_with_commitment(
-            self.cfg.cluster.url().to_string(),
-            self.cfg.options.unwrap_or_default(),
-        );
+        let rpc_client = self.rpc();
         let account = rpc_client
             .get_account_with_commitment(&address, CommitmentConfig::processed())?
             .value
@@ -134,10 +161,9 @@ impl Program {
     }
 
     pub fn rpc(&self) -> RpcClient {
-        RpcClient::new_with_commitment(
-            self.cfg.cluster.url().to_string(),
-            self.cfg.options.unwrap_or_default(),
-        )
+        self.cfg
+            .cluster
+            .rpc_client(self.cfg.options.unwrap_or_default())
     }
 
     pub fn id(&self) -> Pubkey {
@@ -225,6 +251,10 @@ fn handle_program_log<T: anchor_lang::Event + anchor_lang::AnchorDeserialize>(
             }
         };
 
+        if borsh_bytes.len() < 8 {
+            return Ok((None, None, false));
+        }
+
         let mut slice: &[u8] = &borsh_bytes[..];
         let disc: [u8; 8] = {
             let mut disc = [0; 8];
@@ -318,12 +348,17 @@ pub enum ClientError {
     SolanaClientPubsubError(#[from] PubsubClientError),
     #[error("Unable to parse log: {0}")]
     LogParseError(String),
+    #[error("Transaction of {0} bytes exceeds the packet size limit")]
+    TransactionTooLarge(usize),
+    #[error("{0}")]
+    SignerError(#[from] SignerError),
 }
 
 /// `RequestBuilder` provides a builder interface to create and send
 /// transactions to a cluster.
 pub struct RequestBuilder<'a> {
     cluster: String,
+    headers: BTreeMap<String, String>,
     program_id: Pubkey,
     accounts: Vec<AccountMeta>,
     options: CommitmentConfig,
@@ -358,6 +393,7 @@ impl<'a> RequestBuilder<'a> {
             program_id,
             payer,
             cluster: cluster.to_string(),
+            headers: BTreeMap::new(),
             accounts: Vec::new(),
             options: options.unwrap_or_default(),
             instructions: Vec::new(),
@@ -379,6 +415,14 @@ impl<'a> RequestBuilder<'a> {
         self
     }
 
+    /// Sets the HTTP headers sent with each request to the cluster, e.g.,
+    /// an API key.
+    #[must_use]
+    pub fn headers(mut self, headers: BTreeMap<String, String>) -> Self {
+        self.headers = headers;
+        self
+    }
+
     #[must_use]
     pub fn instruction(mut self, ix: Instruction) -> Self {
         self.instructions.push(ix);
@@ -463,33 +507,214 @@ impl<'a> RequestBuilder<'a> {
         Ok(instructions)
     }
 
-    pub fn send(self) -> Result<Signature, ClientError> {
+    fn signed_transaction(&self, rpc_client: &RpcClient) -> Result<Transaction, ClientError> {
         let instructions = self.instructions()?;
 
-        let mut signers = self.signers;
+        let mut signers = self.signers.clone();
         signers.push(&*self.payer);
 
-        let rpc_client = RpcClient::new_with_commitment(self.cluster, self.options);
+        let (recent_hash, _fee_calc) = rpc_client.get_recent_blockhash()?;
+        Ok(Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&self.payer.pubkey()),
+            &signers,
+            recent_hash,
+        ))
+    }
 
-        let tx = {
-            let (recent_hash, _fee_calc) = rpc_client.get_recent_blockhash()?;
-            Transaction::new_signed_with_payer(
-                &instructions,
-                Some(&self.payer.pubkey()),
-                &signers,
-                recent_hash,
-            )
-        };
+    pub fn send(self) -> Result<Signature, ClientError> {
+        let rpc_client = rpc_client(&self.cluster, &self.headers, self.options);
+        let tx = self.signed_transaction(&rpc_client)?;
 
         rpc_client
             .send_and_confirm_transaction(&tx)
             .map_err(Into::into)
     }
+
+    /// Sends the transaction, displaying a spinner while waiting for
+    /// confirmation. `config` controls preflight checks and the number of
+    /// times the RPC node retries sending the transaction to the leader.
+    pub fn send_with_spinner_and_config(
+        self,
+        config: RpcSendTransactionConfig,
+    ) -> Result<Signature, ClientError> {
+        let rpc_client = rpc_client(&self.cluster, &self.headers, self.options);
+        let tx = self.signed_transaction(&rpc_client)?;
+
+        rpc_client
+            .send_and_confirm_transaction_with_spinner_and_config(&tx, self.options, config)
+            .map_err(Into::into)
+    }
+
+    /// Simulates the transaction against the cluster without submitting it,
+    /// so no fees are paid. The response contains the program logs, the
+    /// compute units consumed by each top level instruction, and the custom
+    /// error returned by the program, if any. Events can be decoded from the
+    /// logs via [`SimulateResponse::events`].
+    pub fn simulate(&self) -> Result<SimulateResponse, ClientError> {
+        let rpc_client = rpc_client(&self.cluster, &self.headers, self.options);
+        let tx = self.signed_transaction(&rpc_client)?;
+
+        let result = rpc_client
+            .simulate_transaction_with_config(
+                &tx,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    commitment: Some(self.options),
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )?
+            .value;
+
+        let logs = result.logs.unwrap_or_default();
+        let units_consumed = parse_compute_units(&logs);
+        let error = result
+            .err
+            .as_ref()
+            .and_then(AnchorError::from_transaction_error);
+
+        Ok(SimulateResponse {
+            program_id: self.program_id,
+            logs,
+            units_consumed,
+            err: result.err,
+            error,
+        })
+    }
+}
+
+/// The result of simulating a transaction with [`RequestBuilder::simulate`].
+#[derive(Debug)]
+pub struct SimulateResponse {
+    program_id: Pubkey,
+    /// Logs emitted during the simulation.
+    pub logs: Vec<String>,
+    /// Compute units consumed by each top level instruction, in order.
+    pub units_consumed: Vec<u64>,
+    /// The raw transaction error, if the simulation failed.
+    pub err: Option<TransactionError>,
+    /// The custom error code returned by a program, if the simulation
+    /// failed with one.
+    pub error: Option<AnchorError>,
+}
+
+impl SimulateResponse {
+    /// Total compute units consumed by the transaction.
+    pub fn total_units_consumed(&self) -> u64 {
+        self.units_consumed.iter().sum()
+    }
+
+    /// Decodes all events of type `T` emitted by the request's program.
+    pub fn events<T: anchor_lang::Event + anchor_lang::AnchorDeserialize>(
+        &self,
+    ) -> Result<Vec<T>, ClientError> {
+        let self_program_str = self.program_id.to_string();
+        let invoke_re = Regex::new(r"^Program (\S+) invoke \[\d+\]$").unwrap();
+        let return_re = Regex::new(r"^Program \S+ (success|failed: .*)$").unwrap();
+        // Programs being executed, innermost last. A transaction may have any
+        // number of top level instructions, so the stack empties in between.
+        let mut stack: Vec<&str> = Vec::new();
+        let mut events = Vec::new();
+        for l in &self.logs {
+            if let Some(c) = invoke_re.captures(l) {
+                stack.push(c.get(1).map(|m| m.as_str()).unwrap_or_default());
+            } else if l.starts_with("Program log:") {
+                if stack.last() == Some(&self_program_str.as_str()) {
+                    if let (Some(e), _, _) = handle_program_log(&self_program_str, l)? {
+                        events.push(e);
+                    }
+                }
+            } else if return_re.is_match(l) {
+                stack.pop();
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// A custom error code returned by an instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnchorError {
+    /// Index of the failed instruction within the transaction.
+    pub instruction_index: u8,
+    /// The `ProgramError::Custom` code.
+    pub code: u32,
+}
+
+impl AnchorError {
+    fn from_transaction_error(err: &TransactionError) -> Option<Self> {
+        match err {
+            TransactionError::InstructionError(idx, InstructionError::Custom(code)) => Some(Self {
+                instruction_index: *idx,
+                code: *code,
+            }),
+            _ => None,
+        }
+    }
+
+    /// True if the error was raised by the framework, e.g., a constraint
+    /// violation, rather than by the program's own `#[error]` enum.
+    pub fn is_framework_error(&self) -> bool {
+        self.code < anchor_lang::__private::ERROR_CODE_OFFSET
+    }
+
+    /// The variant index into the program's `#[error]` enum, if the error
+    /// was defined by the program.
+    pub fn program_error_index(&self) -> Option<u32> {
+        self.code
+            .checked_sub(anchor_lang::__private::ERROR_CODE_OFFSET)
+    }
+
+    /// The framework error with this code, if any. Its `Debug` output is the
+    /// error's name and its `Display` output the error's message.
+    pub fn framework_error(&self) -> Option<ErrorCode> {
+        ErrorCode::try_from(self.code).ok()
+    }
+}
+
+impl std::fmt::Display for AnchorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.framework_error() {
+            Some(e) => write!(
+                f,
+                "Instruction {} failed with {:?} ({}): {}",
+                self.instruction_index, e, self.code, e
+            ),
+            None => write!(
+                f,
+                "Instruction {} failed with custom error {}",
+                self.instruction_index, self.code
+            ),
+        }
+    }
+}
+
+// Returns the compute units consumed by each top level instruction, parsed
+// from lines of the form "Program <id> consumed <n> of <m> compute units".
+fn parse_compute_units(logs: &[String]) -> Vec<u64> {
+    let invoke_re = Regex::new(r"^Program \S+ invoke \[(\d+)\]$").unwrap();
+    let consumed_re = Regex::new(r"^Program \S+ consumed (\d+) of \d+ compute units$").unwrap();
+    let return_re = Regex::new(r"^Program \S+ (success|failed: .*)$").unwrap();
+    let mut depth = 0;
+    let mut units = Vec::new();
+    for l in logs {
+        if let Some(c) = invoke_re.captures(l) {
+            depth = c[1].parse().unwrap_or(depth);
+        } else if let Some(c) = consumed_re.captures(l) {
+            if depth == 1 {
+                units.push(c[1].parse().unwrap_or(0));
+            }
+        } else if return_re.is_match(l) {
+            depth -= 1;
+        }
+    }
+    units
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use anchor_lang::{AnchorDeserialize, AnchorSerialize, Discriminator, Event};
     #[test]
     fn new_execution() {
         let mut logs: &[String] =
@@ -516,4 +741,117 @@ mod tests {
         assert_eq!(program, None);
         assert!(!did_pop);
     }
+
+    #[test]
+    fn parse_compute_units_top_level() {
+        let logs: Vec<String> = [
+            "Program 7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw invoke [1]",
+            "Program log: Instruction: Initialize",
+            "Program 11111111111111111111111111111111 invoke [2]",
+            "Program 11111111111111111111111111111111 success",
+            "Program 7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw consumed 5320 of 200000 compute units",
+            "Program 7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw success",
+            "Program 7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw invoke [1]",
+            "Program 7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw consumed 1200 of 200000 compute units",
+            "Program 7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw failed: custom program error: 0x1770",
+        ]
+        .iter()
+        .map(|l| l.to_string())
+        .collect();
+        assert_eq!(parse_compute_units(&logs), vec![5320, 1200]);
+    }
+
+    #[test]
+    fn parse_compute_units_ignores_program_logs() {
+        let logs: Vec<String> = [
+            "Program 7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw invoke [1]",
+            "Program log: transfer success",
+            "Program log: swap failed: slippage exceeded",
+            "Program 11111111111111111111111111111111 invoke [2]",
+            "Program 11111111111111111111111111111111 success",
+            "Program 7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw consumed 4100 of 200000 compute units",
+            "Program 7Y8VDzehoewALqJfyxZYMgYCnMTCDhWuGfJKUvjYWATw success",
+        ]
+        .iter()
+        .map(|l| l.to_string())
+        .collect();
+        assert_eq!(parse_compute_units(&logs), vec![4100]);
+    }
+
+    #[derive(Debug, PartialEq, AnchorSerialize, AnchorDeserialize)]
+    struct TestEvent {
+        value: u64,
+    }
+
+    impl Discriminator for TestEvent {
+        fn discriminator() -> [u8; 8] {
+            *b"testevnt"
+        }
+    }
+
+    impl Event for TestEvent {
+        fn data(&self) -> Vec<u8> {
+            let mut data = TestEvent::discriminator().to_vec();
+            data.append(&mut self.try_to_vec().unwrap());
+            data
+        }
+    }
+
+    #[test]
+    fn simulate_response_events_multiple_instructions() {
+        let program_id = Pubkey::new_unique();
+        let event_log = |value| {
+            format!(
+                "Program log: {}",
+                anchor_lang::__private::base64::encode(TestEvent { value }.data())
+            )
+        };
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            event_log(1),
+            "Program 11111111111111111111111111111111 invoke [2]".to_string(),
+            // Short or undecodable payloads are skipped.
+            "Program log: AAAA".to_string(),
+            "Program 11111111111111111111111111111111 success".to_string(),
+            format!("Program {} success", program_id),
+            format!("Program {} invoke [1]", program_id),
+            "Program log: AAAA".to_string(),
+            "Program log: Instruction: Update".to_string(),
+            event_log(2),
+            format!("Program {} success", program_id),
+        ];
+        let response = SimulateResponse {
+            program_id,
+            logs,
+            units_consumed: vec![],
+            err: None,
+            error: None,
+        };
+        let events: Vec<TestEvent> = response.events().unwrap();
+        assert_eq!(events, vec![TestEvent { value: 1 }, TestEvent { value: 2 }]);
+    }
+
+    #[test]
+    fn anchor_error_from_transaction_error() {
+        let err = TransactionError::InstructionError(1, InstructionError::Custom(6001));
+        let error = AnchorError::from_transaction_error(&err).unwrap();
+        assert_eq!(error.instruction_index, 1);
+        assert!(!error.is_framework_error());
+        assert_eq!(error.program_error_index(), Some(1));
+        assert!(error.framework_error().is_none());
+    }
+
+    #[test]
+    fn anchor_error_framework_error() {
+        let code = ErrorCode::ConstraintSeeds as u32;
+        let err = TransactionError::InstructionError(0, InstructionError::Custom(code));
+        let error = AnchorError::from_transaction_error(&err).unwrap();
+        assert!(error.is_framework_error());
+        let framework_error = error.framework_error().unwrap();
+        assert_eq!(format!("{:?}", framework_error), "ConstraintSeeds");
+        assert_eq!(
+            error.to_string(),
+            "Instruction 0 failed with ConstraintSeeds (2006): A seeds constraint was violated"
+        );
+    }
 }
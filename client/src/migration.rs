@@ -0,0 +1,270 @@
+//! Migrations run by `anchor migrate`, written in Rust.
+//!
+//! The `migrations/` directory of a workspace is a binary crate registering
+//! numbered migrations, which are run in order against the cluster of the
+//! workspace's Anchor.toml:
+//!
+//! ```ignore
+//! use anchor_client::migration::{Migrations, Provider};
+//!
+//! fn main() -> anyhow::Result<()> {
+//!     Migrations::new()
+//!         .add(1, "initialize", initialize)
+//!         .add(2, "create_pool", create_pool)
+//!         .run()
+//! }
+//!
+//! fn initialize(provider: &Provider) -> anyhow::Result<()> {
+//!     let program = provider.program(my_program::ID);
+//!     // ...
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Executed migrations are recorded per cluster, so that running the binary
+//! again only runs the migrations added since.
+
+use crate::{Client, Cluster, Program};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Signer};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Connection to the cluster migrations run against, configured by
+/// `anchor migrate`.
+pub struct Provider {
+    pub cluster: Cluster,
+    pub payer: Rc<dyn Signer>,
+    pub commitment: CommitmentConfig,
+}
+
+impl Provider {
+    /// Reads the provider from the environment set by `anchor migrate`, i.e.,
+    /// `ANCHOR_PROVIDER_URL`, `ANCHOR_PROVIDER_WS_URL` and `ANCHOR_WALLET`.
+    pub fn env() -> Result<Self> {
+        let url = env_var("ANCHOR_PROVIDER_URL")?;
+        let ws_url = env_var("ANCHOR_PROVIDER_WS_URL")?;
+        let wallet = env_var("ANCHOR_WALLET")?;
+        let payer = read_keypair_file(&wallet)
+            .map_err(|e| anyhow!("Unable to read wallet {}: {}", wallet, e))?;
+        Ok(Self {
+            cluster: Cluster::Custom(url, ws_url),
+            payer: Rc::new(payer),
+            commitment: CommitmentConfig::confirmed(),
+        })
+    }
+
+    pub fn client(&self) -> Client {
+        Client::new_with_options(self.cluster.clone(), self.payer.clone(), self.commitment)
+    }
+
+    pub fn program(&self, program_id: Pubkey) -> Program {
+        self.client().program(program_id)
+    }
+
+    pub fn rpc(&self) -> RpcClient {
+        self.cluster.rpc_client(self.commitment)
+    }
+}
+
+type MigrationFn = Box<dyn Fn(&Provider) -> Result<()>>;
+
+struct Migration {
+    id: u32,
+    name: String,
+    run: MigrationFn,
+}
+
+/// The migrations of a workspace, run in order of their ids.
+#[derive(Default)]
+pub struct Migrations {
+    migrations: Vec<Migration>,
+}
+
+impl Migrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a migration. Ids identify the migrations in the record of
+    /// executed migrations, so they shouldn't change once run.
+    pub fn add(
+        mut self,
+        id: u32,
+        name: &str,
+        run: impl Fn(&Provider) -> Result<()> + 'static,
+    ) -> Self {
+        self.migrations.push(Migration {
+            id,
+            name: name.to_string(),
+            run: Box::new(run),
+        });
+        self
+    }
+
+    /// Runs the pending migrations as configured by `anchor migrate`, i.e.,
+    /// up to `ANCHOR_MIGRATE_TO`, if set, and only printing them if
+    /// `ANCHOR_MIGRATE_DRY_RUN` is set.
+    pub fn run(self) -> Result<()> {
+        let to = match std::env::var("ANCHOR_MIGRATE_TO") {
+            Ok(to) => Some(to.parse()?),
+            Err(_) => None,
+        };
+        let dry_run = std::env::var("ANCHOR_MIGRATE_DRY_RUN").is_ok();
+        let cluster = env_var("ANCHOR_MIGRATIONS_CLUSTER")?;
+        let path = PathBuf::from(env_var("ANCHOR_MIGRATIONS_FILE")?);
+        let provider = Provider::env()?;
+        self.run_with(&provider, &cluster, &path, to, dry_run)
+    }
+
+    /// Runs the migrations not yet recorded as executed on `cluster` in the
+    /// record at `path`, up to and including the id `to`.
+    pub fn run_with(
+        mut self,
+        provider: &Provider,
+        cluster: &str,
+        path: &Path,
+        to: Option<u32>,
+        dry_run: bool,
+    ) -> Result<()> {
+        self.migrations.sort_by_key(|m| m.id);
+        if let Some(pair) = self.migrations.windows(2).find(|w| w[0].id == w[1].id) {
+            return Err(anyhow!("Duplicate migration id {}", pair[0].id));
+        }
+
+        let mut record = MigrationRecord::load(path)?;
+        let pending: Vec<&Migration> = self
+            .migrations
+            .iter()
+            .filter(|m| !record.is_executed(cluster, m.id))
+            .filter(|m| to.map_or(true, |to| m.id <= to))
+            .collect();
+        if pending.is_empty() {
+            println!("No pending migrations on {}", cluster);
+            return Ok(());
+        }
+
+        for migration in pending {
+            if dry_run {
+                println!("Would run migration {} {}", migration.id, migration.name);
+                continue;
+            }
+            println!("Running migration {} {}", migration.id, migration.name);
+            (migration.run)(provider)
+                .map_err(|e| anyhow!("Migration {} failed: {}", migration.id, e))?;
+            // Record each migration as it completes, so that a failure
+            // doesn't rerun the ones before it.
+            record.push(cluster, migration.id, &migration.name);
+            record.save(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Migrations executed on each cluster, stored in `.anchor/migrations.json`.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct MigrationRecord(pub BTreeMap<String, Vec<ExecutedMigration>>);
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutedMigration {
+    pub id: u32,
+    pub name: String,
+    /// Unix timestamp, in seconds.
+    pub executed_at: u64,
+}
+
+impl MigrationRecord {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| anyhow!("Invalid {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn is_executed(&self, cluster: &str, id: u32) -> bool {
+        self.0
+            .get(cluster)
+            .map_or(false, |executed| executed.iter().any(|m| m.id == id))
+    }
+
+    fn push(&mut self, cluster: &str, id: u32, name: &str) {
+        let executed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.0
+            .entry(cluster.to_string())
+            .or_default()
+            .push(ExecutedMigration {
+                id,
+                name: name.to_string(),
+                executed_at,
+            });
+    }
+}
+
+fn env_var(name: &str) -> Result<String> {
+    std::env::var(name).map_err(|_| anyhow!("{} isn't set. Run with `anchor migrate`", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Keypair;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_run_pending_migrations() {
+        let provider = Provider {
+            cluster: Cluster::Localnet,
+            payer: Rc::new(Keypair::new()),
+            commitment: CommitmentConfig::confirmed(),
+        };
+        let path = std::env::temp_dir().join(format!(
+            "anchor-migrations-{}.json",
+            Keypair::new().pubkey()
+        ));
+        let runs = Rc::new(RefCell::new(vec![]));
+        let migrations = || {
+            let mut migrations = Migrations::new();
+            for id in [3, 1, 2] {
+                let runs = runs.clone();
+                migrations = migrations.add(id, "migration", move |_| {
+                    runs.borrow_mut().push(id);
+                    Ok(())
+                });
+            }
+            migrations
+        };
+
+        migrations()
+            .run_with(&provider, "localnet", &path, Some(2), false)
+            .unwrap();
+        migrations()
+            .run_with(&provider, "localnet", &path, None, true)
+            .unwrap();
+        assert_eq!(*runs.borrow(), vec![1, 2]);
+
+        migrations()
+            .run_with(&provider, "localnet", &path, None, false)
+            .unwrap();
+        assert_eq!(*runs.borrow(), vec![1, 2, 3]);
+        let record = MigrationRecord::load(&path).unwrap();
+        assert!(record.is_executed("localnet", 3));
+        assert!(!record.is_executed("devnet", 1));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{rpc_client, ClientError, RequestBuilder};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::message::Message;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::signature::{Signature, Signer};
+use solana_sdk::transaction::Transaction;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// `TransactionBuilder` composes several [`RequestBuilder`]s, potentially
+/// targeting different programs, into as few transactions as possible.
+///
+/// Each request is kept atomic, i.e., all of its instructions land in the
+/// same transaction. Requests are packed greedily, in order, and a new
+/// transaction is started whenever adding the next request would exceed the
+/// packet size limit. Signers are deduplicated by pubkey and each
+/// transaction is only signed by the signers its message requires.
+pub struct TransactionBuilder<'a> {
+    cluster: String,
+    headers: BTreeMap<String, String>,
+    payer: Rc<dyn Signer>,
+    options: CommitmentConfig,
+    requests: Vec<RequestBuilder<'a>>,
+    signers: Vec<&'a dyn Signer>,
+}
+
+impl<'a> TransactionBuilder<'a> {
+    pub fn from(cluster: &str, payer: Rc<dyn Signer>, options: Option<CommitmentConfig>) -> Self {
+        Self {
+            cluster: cluster.to_string(),
+            headers: BTreeMap::new(),
+            payer,
+            options: options.unwrap_or_default(),
+            requests: Vec::new(),
+            signers: Vec::new(),
+        }
+    }
+
+    /// Sets the fee payer for every transaction in the batch. Payers of the
+    /// individual requests are kept as signers, since they may still be
+    /// referenced by the request's accounts.
+    #[must_use]
+    pub fn payer(mut self, payer: Rc<dyn Signer>) -> Self {
+        self.payer = payer;
+        self
+    }
+
+    #[must_use]
+    pub fn cluster(mut self, url: &str) -> Self {
+        self.cluster = url.to_string();
+        self
+    }
+
+    /// Sets the HTTP headers sent with each request to the cluster, e.g.,
+    /// an API key.
+    #[must_use]
+    pub fn headers(mut self, headers: BTreeMap<String, String>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    #[must_use]
+    pub fn options(mut self, options: CommitmentConfig) -> Self {
+        self.options = options;
+        self
+    }
+
+    #[must_use]
+    pub fn request(mut self, request: RequestBuilder<'a>) -> Self {
+        self.requests.push(request);
+        self
+    }
+
+    #[must_use]
+    pub fn signer(mut self, signer: &'a dyn Signer) -> Self {
+        self.signers.push(signer);
+        self
+    }
+
+    /// Returns the instructions of each request, grouped into transactions
+    /// that each fit within the packet size limit.
+    pub fn instructions(&self) -> Result<Vec<Vec<Instruction>>, ClientError> {
+        let payer = self.payer.pubkey();
+        let mut batches: Vec<Vec<Instruction>> = Vec::new();
+        let mut current: Vec<Instruction> = Vec::new();
+        for request in &self.requests {
+            let ixs = request.instructions()?;
+            if ixs.is_empty() {
+                continue;
+            }
+            let size = estimate_size(&ixs, &payer);
+            if size > PACKET_DATA_SIZE {
+                return Err(ClientError::TransactionTooLarge(size));
+            }
+            let mut candidate = current.clone();
+            candidate.extend(ixs.iter().cloned());
+            if current.is_empty() || estimate_size(&candidate, &payer) <= PACKET_DATA_SIZE {
+                current = candidate;
+            } else {
+                batches.push(std::mem::replace(&mut current, ixs));
+            }
+        }
+        if !current.is_empty() {
+            batches.push(current);
+        }
+        Ok(batches)
+    }
+
+    /// Estimated serialized size, in bytes, of each transaction in the batch.
+    pub fn estimate_sizes(&self) -> Result<Vec<usize>, ClientError> {
+        let payer = self.payer.pubkey();
+        Ok(self
+            .instructions()?
+            .iter()
+            .map(|ixs| estimate_size(ixs, &payer))
+            .collect())
+    }
+
+    /// Signs and sends each transaction in order, stopping at the first
+    /// failure. Returns the signatures of the confirmed transactions.
+    pub fn send(self) -> Result<Vec<Signature>, ClientError> {
+        let batches = self.instructions()?;
+        let signers = self.dedup_signers();
+        let rpc_client = rpc_client(&self.cluster, &self.headers, self.options);
+
+        let mut signatures = Vec::with_capacity(batches.len());
+        for instructions in batches {
+            let message = Message::new(&instructions, Some(&self.payer.pubkey()));
+            let (recent_hash, _fee_calc) = rpc_client.get_recent_blockhash()?;
+            let tx = sign(message, &signers, recent_hash)?;
+            signatures.push(rpc_client.send_and_confirm_transaction(&tx)?);
+        }
+        Ok(signatures)
+    }
+
+    // All signers of the batch, with the fee payer first and any duplicate
+    // pubkeys removed.
+    fn dedup_signers(&self) -> Vec<&dyn Signer> {
+        let mut signers: Vec<&dyn Signer> = vec![&*self.payer];
+        let candidates = self
+            .requests
+            .iter()
+            .flat_map(|r| {
+                let mut s = r.signers.clone();
+                s.push(&*r.payer);
+                s
+            })
+            .chain(self.signers.iter().copied());
+        for signer in candidates {
+            if !signers.iter().any(|s| s.pubkey() == signer.pubkey()) {
+                signers.push(signer);
+            }
+        }
+        signers
+    }
+}
+
+// Signs `message` with the subset of `signers` it requires. Fails, rather
+// than panicking, if one of its required signers wasn't given.
+fn sign(
+    message: Message,
+    signers: &[&dyn Signer],
+    recent_hash: Hash,
+) -> Result<Transaction, ClientError> {
+    let required = &message.account_keys[..message.header.num_required_signatures as usize];
+    let tx_signers: Vec<&dyn Signer> = signers
+        .iter()
+        .filter(|s| required.contains(&s.pubkey()))
+        .copied()
+        .collect();
+    let mut tx = Transaction::new_unsigned(message);
+    tx.try_sign(&tx_signers, recent_hash)?;
+    Ok(tx)
+}
+
+// Estimates the serialized size of a transaction containing `instructions`
+// and paid for by `payer`, including the signatures.
+fn estimate_size(instructions: &[Instruction], payer: &Pubkey) -> usize {
+    let message = Message::new(instructions, Some(payer));
+    let num_signatures = message.header.num_required_signatures as usize;
+    short_vec_len(num_signatures) + num_signatures * 64 + message.serialize().len()
+}
+
+// Number of bytes used by the compact-u16 length prefix.
+fn short_vec_len(len: usize) -> usize {
+    match len {
+        0..=0x7f => 1,
+        0x80..=0x3fff => 2,
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RequestNamespace;
+    use anchor_lang::solana_program::instruction::AccountMeta;
+    use solana_sdk::signature::Keypair;
+
+    fn ix(data_len: usize, signer: Pubkey) -> Instruction {
+        Instruction {
+            program_id: Pubkey::new_unique(),
+            accounts: vec![AccountMeta::new(signer, true)],
+            data: vec![0; data_len],
+        }
+    }
+
+    #[test]
+    fn packs_requests_under_packet_limit() {
+        let payer: Rc<dyn Signer> = Rc::new(Keypair::new());
+        let request = |data_len| {
+            RequestBuilder::from(
+                Pubkey::new_unique(),
+                "http://127.0.0.1:8899",
+                payer.clone(),
+                None,
+                RequestNamespace::Global,
+            )
+            .instruction(ix(data_len, payer.pubkey()))
+        };
+        let builder = TransactionBuilder::from("http://127.0.0.1:8899", payer.clone(), None)
+            .request(request(600))
+            .request(request(600))
+            .request(request(10));
+
+        let batches = builder.instructions().unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), 1);
+        assert_eq!(batches[1].len(), 2);
+        for size in builder.estimate_sizes().unwrap() {
+            assert!(size <= PACKET_DATA_SIZE);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_request() {
+        let payer: Rc<dyn Signer> = Rc::new(Keypair::new());
+        let builder = TransactionBuilder::from("http://127.0.0.1:8899", payer.clone(), None)
+            .request(
+                RequestBuilder::from(
+                    Pubkey::new_unique(),
+                    "http://127.0.0.1:8899",
+                    payer.clone(),
+                    None,
+                    RequestNamespace::Global,
+                )
+                .instruction(ix(PACKET_DATA_SIZE, payer.pubkey())),
+            );
+        assert!(matches!(
+            builder.instructions(),
+            Err(ClientError::TransactionTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn sign_requires_every_signer() {
+        let payer = Keypair::new();
+        let other = Keypair::new();
+        let message = Message::new(&[ix(0, other.pubkey())], Some(&payer.pubkey()));
+
+        let tx = sign(message.clone(), &[&payer, &other], Hash::default()).unwrap();
+        assert!(tx.is_signed());
+        assert!(matches!(
+            sign(message, &[&payer], Hash::default()),
+            Err(ClientError::SignerError(_))
+        ));
+    }
+
+    #[test]
+    fn short_vec_boundaries() {
+        assert_eq!(short_vec_len(0x7f), 1);
+        assert_eq!(short_vec_len(0x80), 2);
+        assert_eq!(short_vec_len(0x4000), 3);
+    }
+}
@@ -58,39 +58,69 @@ mod id;
 /// [`Pod`](../bytemuck/trait.Pod.html). Please review the
 /// [`safety`](file:///home/armaniferrante/Documents/code/src/github.com/project-serum/anchor/target/doc/bytemuck/trait.Pod.html#safety)
 /// section before using.
+///
+/// # Versioning
+///
+/// Changing the fields of an account breaks the deserialization of the
+/// accounts already created. Instead, the account can be versioned, keeping
+/// its previous layouts as plain structs that convert into the current one.
+///
+/// ```ignore
+/// #[derive(AnchorSerialize, AnchorDeserialize)]
+/// pub struct CounterV1 {
+///     pub count: u64,
+/// }
+///
+/// #[account(version = 2, migrate_from = CounterV1)]
+/// pub struct Counter {
+///     pub count: u64,
+///     pub authority: Pubkey,
+/// }
+///
+/// impl From<CounterV1> for Counter {
+///     fn from(v1: CounterV1) -> Self {
+///         Self {
+///             count: v1.count,
+///             authority: Pubkey::default(),
+///         }
+///     }
+/// }
+/// ```
+///
+/// Each version has its own discriminator. The first one is the
+/// discriminator of the unversioned account, so accounts created before
+/// versioning are of version 1. `AccountDeserialize` accepts the data of any
+/// version, converting it into the current layout, and
+/// [`Versioned`](./trait.Versioned.html) tells which version some data was
+/// written with.
+///
+/// With more than one previous version, `migrate_from` lists their layouts,
+/// oldest first, e.g., `#[account(version = 3, migrate_from = [CounterV1,
+/// CounterV2])]`, each converting into the current layout.
+///
+/// Accounts are written with the current layout when they're persisted, and
+/// the `migrate` constraint, i.e., `#[account(mut, migrate)]`, rewrites an
+/// account with the current layout before the instruction runs. Since
+/// account data can't be reallocated, the current layout must fit in the
+/// space the account was created with.
 #[proc_macro_attribute]
 pub fn account(
     args: proc_macro::TokenStream,
     input: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    let mut namespace = "".to_string();
-    let mut is_zero_copy = false;
-    let args_str = args.to_string();
-    let args: Vec<&str> = args_str.split(',').collect();
-    if args.len() > 2 {
-        panic!("Only two args are allowed to the account attribute.")
-    }
-    for arg in args {
-        let ns = arg
-            .to_string()
-            .replace('\"', "")
-            .chars()
-            .filter(|c| !c.is_whitespace())
-            .collect();
-        if ns == "zero_copy" {
-            is_zero_copy = true;
-        } else {
-            namespace = ns;
-        }
-    }
+    let args = parse_macro_input!(args as anchor_syn::AccountArgs);
+    let namespace = args.namespace.clone().unwrap_or_default();
+    let is_zero_copy = args.zero_copy;
 
     let account_strct = parse_macro_input!(input as syn::ItemStruct);
     let account_name = &account_strct.ident;
     let (impl_gen, type_gen, where_clause) = account_strct.generics.split_for_impl();
 
-    let discriminator: proc_macro2::TokenStream = {
+    // Versions after the first are suffixed, so that the discriminator of
+    // the first is the one of the unversioned account.
+    let discriminator_of = |version: u8| -> proc_macro2::TokenStream {
         // Namespace the discriminator to prevent collisions.
-        let discriminator_preimage = {
+        let mut discriminator_preimage = {
             // For now, zero copy accounts can't be namespaced.
             if namespace.is_empty() {
                 format!("account:{}", account_name)
@@ -98,6 +128,9 @@ pub fn account(
                 format!("{}:{}", namespace, account_name)
             }
         };
+        if version > 1 {
+            discriminator_preimage.push_str(&format!(":v{}", version));
+        }
 
         let mut discriminator = [0u8; 8];
         discriminator.copy_from_slice(
@@ -105,6 +138,8 @@ pub fn account(
         );
         format!("{:?}", discriminator).parse().unwrap()
     };
+    let version = args.version.unwrap_or(1);
+    let discriminator = discriminator_of(version);
 
     let owner_impl = {
         if namespace.is_empty() {
@@ -121,6 +156,54 @@ pub fn account(
         }
     };
 
+    // Deserializes the data of previous versions into the current layout.
+    let previous_versions = args.previous_versions();
+    let previous_deserialize: Vec<proc_macro2::TokenStream> = previous_versions
+        .iter()
+        .map(|(version, layout)| {
+            let discriminator = discriminator_of(*version);
+            quote! {
+                if &#discriminator == given_disc {
+                    let mut data: &[u8] = &buf[8..];
+                    let account: #layout = AnchorDeserialize::deserialize(&mut data)
+                        .map_err(|_| anchor_lang::__private::ErrorCode::AccountDidNotDeserialize)?;
+                    return Ok(account.into());
+                }
+            }
+        })
+        .collect();
+
+    let versioned_impl = match args.version {
+        None => quote! {},
+        Some(_) => {
+            let versions: Vec<u8> = previous_versions.iter().map(|(v, _)| *v).collect();
+            let discriminators: Vec<proc_macro2::TokenStream> =
+                versions.iter().map(|v| discriminator_of(*v)).collect();
+            quote! {
+                #[automatically_derived]
+                impl #impl_gen anchor_lang::Versioned for #account_name #type_gen #where_clause {
+                    const VERSION: u8 = #version;
+
+                    fn data_version(data: &[u8]) -> Option<u8> {
+                        if data.len() < 8 {
+                            return None;
+                        }
+                        let given_disc = &data[..8];
+                        if &#discriminator == given_disc {
+                            return Some(#version);
+                        }
+                        #(
+                            if &#discriminators == given_disc {
+                                return Some(#versions);
+                            }
+                        )*
+                        None
+                    }
+                }
+            }
+        }
+    };
+
     proc_macro::TokenStream::from({
         if is_zero_copy {
             quote! {
@@ -193,6 +276,7 @@ pub fn account(
                             return Err(anchor_lang::__private::ErrorCode::AccountDiscriminatorNotFound.into());
                         }
                         let given_disc = &buf[..8];
+                        #(#previous_deserialize)*
                         if &#discriminator != given_disc {
                             return Err(anchor_lang::__private::ErrorCode::AccountDiscriminatorMismatch.into());
                         }
@@ -213,12 +297,19 @@ pub fn account(
                     }
                 }
 
+                #versioned_impl
+
                 #owner_impl
             }
         }
     })
 }
 
+/// Generates getters and setters for fields annotated with `#[accessor(T)]`.
+///
+/// Deprecated: `Pubkey` and other `Pod` types can be used directly as fields
+/// of `#[zero_copy]` structs, so the `[u8; 32]` + `#[accessor(Pubkey)]`
+/// workaround is no longer needed. It's kept for backwards compatibility.
 #[proc_macro_derive(ZeroCopyAccessor, attributes(accessor))]
 pub fn derive_zero_copy_accessor(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let account_strct = parse_macro_input!(item as syn::ItemStruct);
@@ -281,20 +372,174 @@ pub fn derive_zero_copy_accessor(item: proc_macro::TokenStream) -> proc_macro::T
 ///
 /// ```ignore
 /// #[derive(Copy, Clone)]
-/// #[repr(packed)]
+/// #[repr(C)]
 /// struct MyStruct {...}
 /// ```
+///
+/// Fields can be any `Pod` type, including `Pubkey`, other `#[zero_copy]`
+/// structs, and `#[zero_copy]` enums wrapped in a `ZeroCopyEnum`. The layout
+/// is checked at compile time: the struct must not contain any padding bytes,
+/// since they would be uninitialized memory. The layout checked is the one on
+/// BPF, where no field is aligned to more than 8 bytes, so the check passes or
+/// fails the same way on every target. On BPF, the alignment of the struct
+/// must also be at most 8 bytes, since account data is only guaranteed to be
+/// 8 byte aligned. A violation is reported as an array
+/// length mismatch, where the length is the number of padding bytes found.
+/// Reorder the fields or add explicit `_padding: [u8; N]` fields to fix it.
+/// `RingBuffer` and `Slab` fields are checked as well, since padding within
+/// them isn't visible from their size.
+///
+/// # Enums
+///
+/// Fieldless enums marked with `#[zero_copy]` are represented as a single
+/// `u8`, in declaration order starting from zero. Since not every byte is a
+/// valid variant, they're stored in zero copy structs as a `ZeroCopyEnum`,
+/// which checks the variant when read.
+///
+/// ```ignore
+/// #[zero_copy]
+/// pub enum Side {
+///     Bid,
+///     Ask,
+/// }
+///
+/// #[zero_copy]
+/// pub struct Order {
+///     pub side: ZeroCopyEnum<Side>,
+///     pub _padding: [u8; 7],
+///     pub price: u64,
+/// }
+/// ```
 #[proc_macro_attribute]
 pub fn zero_copy(
     _args: proc_macro::TokenStream,
     item: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-    let account_strct = parse_macro_input!(item as syn::ItemStruct);
+    let item = parse_macro_input!(item as syn::Item);
+    match item {
+        syn::Item::Struct(account_strct) => zero_copy_struct(account_strct),
+        syn::Item::Enum(account_enum) => zero_copy_enum(account_enum),
+        _ => panic!("#[zero_copy] can only be used on structs and enums"),
+    }
+}
+
+fn zero_copy_struct(account_strct: syn::ItemStruct) -> proc_macro::TokenStream {
+    let name = &account_strct.ident;
+
+    // Layout checks can't be expressed for generic structs in a const context.
+    let layout_checks = match account_strct.generics.params.is_empty() {
+        false => quote! {},
+        true => {
+            let field_tys: Vec<&syn::Type> = account_strct.fields.iter().map(|f| &f.ty).collect();
+            let mut container_tys = Vec::new();
+            for ty in &field_tys {
+                containers(ty, &mut container_tys);
+            }
+            quote! {
+                #[allow(non_upper_case_globals)]
+                const _: () = {
+                    // The padding bytes of the `#[repr(C)]` layout on BPF,
+                    // computed field by field rather than from the size of
+                    // the struct on the target being compiled.
+                    const _padding: usize = {
+                        let mut offset = 0;
+                        let mut padding = 0;
+                        let mut max_align = 1;
+                        #(
+                            let align = anchor_lang::__private::bpf_align_of::<#field_tys>();
+                            padding += (align - offset % align) % align;
+                            offset += (align - offset % align) % align
+                                + std::mem::size_of::<#field_tys>();
+                            if align > max_align {
+                                max_align = align;
+                            }
+                        )*
+                        padding + (max_align - offset % max_align) % max_align
+                    };
+                    // Fails to compile if the struct contains padding.
+                    const _padding_bytes: [(); 0] = [(); _padding];
+                    // Fails to compile if the struct requires an alignment
+                    // greater than the one of account data.
+                    #[cfg(target_arch = "bpf")]
+                    const _alignment_greater_than_8: [(); 0] =
+                        [(); (std::mem::align_of::<#name>() > 8) as usize];
+                    // Fails to compile if a container can't be laid out
+                    // without padding, which its size doesn't reveal.
+                    #(
+                        const _: usize = <#container_tys>::CAPACITY;
+                    )*
+                };
+            }
+        }
+    };
 
     proc_macro::TokenStream::from(quote! {
         #[derive(anchor_lang::__private::ZeroCopyAccessor, Copy, Clone)]
         #[repr(C)]
         #account_strct
+
+        #layout_checks
+    })
+}
+
+// Collects the `RingBuffer` and `Slab` types within `ty`, including the
+// items of arrays.
+fn containers<'a>(ty: &'a syn::Type, tys: &mut Vec<&'a syn::Type>) {
+    match ty {
+        syn::Type::Array(array) => containers(&array.elem, tys),
+        syn::Type::Group(group) => containers(&group.elem, tys),
+        syn::Type::Paren(paren) => containers(&paren.elem, tys),
+        syn::Type::Path(path) => {
+            if let Some(segment) = path.path.segments.last() {
+                let is_container = segment.ident == "RingBuffer" || segment.ident == "Slab";
+                if is_container && !segment.arguments.is_empty() {
+                    tys.push(ty);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn zero_copy_enum(account_enum: syn::ItemEnum) -> proc_macro::TokenStream {
+    let name = &account_enum.ident;
+    if account_enum.variants.is_empty() || account_enum.variants.len() > 256 {
+        panic!("#[zero_copy] enums must have between 1 and 256 variants");
+    }
+    for variant in &account_enum.variants {
+        if !matches!(variant.fields, syn::Fields::Unit) {
+            panic!("#[zero_copy] enums can't have fields");
+        }
+        if variant.discriminant.is_some() {
+            panic!("#[zero_copy] enums can't have explicit discriminants");
+        }
+    }
+    let variants: Vec<&syn::Ident> = account_enum.variants.iter().map(|v| &v.ident).collect();
+    let discriminants: Vec<u8> = (0..variants.len()).map(|d| d as u8).collect();
+
+    proc_macro::TokenStream::from(quote! {
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        #[repr(u8)]
+        #account_enum
+
+        #[automatically_derived]
+        impl std::convert::TryFrom<u8> for #name {
+            type Error = anchor_lang::solana_program::program_error::ProgramError;
+
+            fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
+                match value {
+                    #(#discriminants => Ok(#name::#variants),)*
+                    _ => Err(anchor_lang::solana_program::program_error::ProgramError::InvalidAccountData),
+                }
+            }
+        }
+
+        #[automatically_derived]
+        impl From<#name> for u8 {
+            fn from(value: #name) -> u8 {
+                value as u8
+            }
+        }
     })
 }
 
@@ -43,6 +43,7 @@ use syn::parse_macro_input;
 /// | `#[account(init_if_needed)]` | On `Account` structs. | Same as `init` but skip if already initialized. |
 /// | `#[account(zero)]` | On `Account` structs. | Asserts the account discriminator is zero. |
 /// | `#[account(close = <target>)]` | On `Account` and `AccountLoader` structs. | Marks the account as being closed at the end of the instruction's execution, sending the rent exemption lamports to the specified <target>. |
+/// | `#[account(migrate)]` | On `Account` structs of versioned accounts. | Rewrites the account with the current layout of its type, if it was written with a previous version. `mut` must be provided before it, and the current layout must fit in the account's data. |
 /// | `#[account(has_one = <target>)]`<br><br>`#[account(has_one = <target> @ <custom_error>)]` | On `Account` or `CpiAccount` structs | Checks the `target` field on the account matches the `target` field in the struct deriving `Accounts`. Custom errors are supported via `@`. |
 /// | `#[account(seeds = [<seeds>], bump? = <target>, payer? = <target>, space? = <target>, owner? = <target>)]` | On `AccountInfo` structs | Seeds for the program derived address an `AccountInfo` struct represents. If bump is provided, then appends it to the seeds. On initialization, validates the given bump is the bump provided by `Pubkey::find_program_address`.|
 /// | `#[account(constraint = <expression>)]`<br><br>`#[account(constraint = <expression> @ <custom_error>)]` | On any type deriving `Accounts` | Executes the given code as a constraint. The expression should evaluate to a boolean. Custom errors are supported via `@`. |
@@ -0,0 +1,483 @@
+//! Fixed capacity containers that can be used as fields of zero copy
+//! accounts, i.e., structs marked with `#[account(zero_copy)]` or
+//! `#[zero_copy]`.
+//!
+//! All containers are valid when zero initialized, so a freshly created
+//! account loaded with `AccountLoader::load_init` starts out empty.
+//!
+//! Because zero copy data can't contain padding bytes, `N * size_of::<T>()`
+//! must be a multiple of 8 for `RingBuffer<T, N>` and `Slab<T, N>`, and `T`
+//! can't be aligned to more than 8 bytes on any target. E.g., `u128`, which
+//! is aligned to 16 bytes on some hosts, can't be used as an item. These
+//! conditions are checked at compile time, by the layout check of the
+//! `#[zero_copy]` struct containing the container, and when the container
+//! is first used.
+
+use bytemuck::{Pod, Zeroable};
+use solana_program::program_error::ProgramError;
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+
+// Evaluates to `N`, or fails to compile if `N` items of type `T`, following a
+// header of `u64`s, would leave padding in a container. Items aligned to at
+// most 8 bytes always start right after the header, so only the end of the
+// container can be padded. Over aligned items would be padded on any target:
+// they'd break the layout of the account on the host, and account data is only
+// guaranteed to be 8 byte aligned on BPF.
+const fn checked_capacity<T>(n: usize) -> usize {
+    let padding = (8 - n * size_of::<T>() % 8) % 8;
+    let over_aligned = align_of::<T>() > 8;
+    let invalid = padding + over_aligned as usize + (n == 0) as usize;
+    [n][invalid]
+}
+
+/// A FIFO queue with a fixed capacity of `N` items.
+///
+/// ```ignore
+/// #[account(zero_copy)]
+/// pub struct EventQueue {
+///     pub events: RingBuffer<Event, 64>,
+/// }
+/// ```
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct RingBuffer<T: Pod, const N: usize> {
+    head: u64,
+    len: u64,
+    items: [T; N],
+}
+
+unsafe impl<T: Pod, const N: usize> Zeroable for RingBuffer<T, N> {}
+unsafe impl<T: Pod, const N: usize> Pod for RingBuffer<T, N> {}
+
+impl<T: Pod, const N: usize> RingBuffer<T, N> {
+    /// The capacity, `N`. Evaluating it fails to compile if the container
+    /// can't be laid out without padding.
+    pub const CAPACITY: usize = checked_capacity::<T>(N);
+
+    pub fn capacity(&self) -> usize {
+        Self::CAPACITY
+    }
+
+    pub fn len(&self) -> usize {
+        (self.len as usize).min(Self::CAPACITY)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == Self::CAPACITY
+    }
+
+    /// Appends an item to the back of the queue. Returns the item back if
+    /// the queue is full.
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(item);
+        }
+        let idx = self.physical_index(self.len());
+        self.items[idx] = item;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Appends an item to the back of the queue, evicting and returning the
+    /// front item if the queue is full.
+    pub fn push_overwrite(&mut self, item: T) -> Option<T> {
+        let evicted = match self.is_full() {
+            true => self.pop(),
+            false => None,
+        };
+        // Can't fail, since there's at least one free slot.
+        let _ = self.push(item);
+        evicted
+    }
+
+    /// Removes and returns the item at the front of the queue.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let idx = self.physical_index(0);
+        let item = self.items[idx];
+        self.head = ((idx + 1) % Self::CAPACITY) as u64;
+        self.len = self.len() as u64 - 1;
+        Some(item)
+    }
+
+    /// Returns the item at the front of the queue.
+    pub fn peek(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    /// Returns the `i`-th item, counting from the front of the queue.
+    pub fn get(&self, i: usize) -> Option<&T> {
+        if i >= self.len() {
+            return None;
+        }
+        Some(&self.items[self.physical_index(i)])
+    }
+
+    pub fn get_mut(&mut self, i: usize) -> Option<&mut T> {
+        if i >= self.len() {
+            return None;
+        }
+        let idx = self.physical_index(i);
+        Some(&mut self.items[idx])
+    }
+
+    /// Iterates over the items from front to back.
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        (0..self.len()).map(move |i| &self.items[self.physical_index(i)])
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    fn physical_index(&self, i: usize) -> usize {
+        (self.head as usize + i) % Self::CAPACITY
+    }
+}
+
+/// A fixed capacity allocator of `N` items, addressed by stable indices.
+///
+/// Removed slots are kept on a free list and reused by later insertions.
+///
+/// ```ignore
+/// #[account(zero_copy)]
+/// pub struct OrderBook {
+///     pub orders: Slab<Order, 128>,
+/// }
+/// ```
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Slab<T: Pod, const N: usize> {
+    len: u64,
+    // Number of slots that were ever handed out. Slots at or above it are
+    // free without being on the free list, which makes zeroed data valid.
+    bump: u64,
+    // Index of the first free slot, plus one. Zero if the list is empty.
+    free_head: u64,
+    // For occupied slots, `OCCUPIED`. For free slots, the index of the next
+    // free slot plus one, or zero at the end of the list.
+    next: [u64; N],
+    items: [T; N],
+}
+
+unsafe impl<T: Pod, const N: usize> Zeroable for Slab<T, N> {}
+unsafe impl<T: Pod, const N: usize> Pod for Slab<T, N> {}
+
+impl<T: Pod, const N: usize> Slab<T, N> {
+    /// The capacity, `N`. Evaluating it fails to compile if the container
+    /// can't be laid out without padding.
+    pub const CAPACITY: usize = checked_capacity::<T>(N);
+    const OCCUPIED: u64 = u64::MAX;
+
+    pub fn capacity(&self) -> usize {
+        Self::CAPACITY
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == Self::CAPACITY
+    }
+
+    /// Stores an item in a free slot and returns its index. Returns the item
+    /// back if the slab is full.
+    pub fn insert(&mut self, item: T) -> Result<usize, T> {
+        let idx = if self.free_head != 0 {
+            let idx = self.free_head as usize - 1;
+            self.free_head = self.next[idx];
+            idx
+        } else if (self.bump as usize) < Self::CAPACITY {
+            self.bump += 1;
+            self.bump as usize - 1
+        } else {
+            return Err(item);
+        };
+        self.next[idx] = Self::OCCUPIED;
+        self.items[idx] = item;
+        self.len += 1;
+        Ok(idx)
+    }
+
+    /// Removes and returns the item at `idx`, freeing its slot.
+    pub fn remove(&mut self, idx: usize) -> Option<T> {
+        if !self.contains(idx) {
+            return None;
+        }
+        self.next[idx] = self.free_head;
+        self.free_head = idx as u64 + 1;
+        self.len -= 1;
+        Some(self.items[idx])
+    }
+
+    pub fn contains(&self, idx: usize) -> bool {
+        idx < self.bump as usize && idx < Self::CAPACITY && self.next[idx] == Self::OCCUPIED
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        match self.contains(idx) {
+            true => Some(&self.items[idx]),
+            false => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, idx: usize) -> Option<&mut T> {
+        match self.contains(idx) {
+            true => Some(&mut self.items[idx]),
+            false => None,
+        }
+    }
+
+    /// Iterates over the occupied slots, in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> + '_ {
+        let end = (self.bump as usize).min(Self::CAPACITY);
+        (0..end)
+            .filter(move |idx| self.next[*idx] == Self::OCCUPIED)
+            .map(move |idx| (idx, &self.items[idx]))
+    }
+}
+
+/// A fixed size set of bits, stored in `W` 64-bit words, i.e., with room for
+/// `W * 64` bits.
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct BitMap<const W: usize> {
+    words: [u64; W],
+}
+
+unsafe impl<const W: usize> Zeroable for BitMap<W> {}
+unsafe impl<const W: usize> Pod for BitMap<W> {}
+
+impl<const W: usize> BitMap<W> {
+    pub const BITS: usize = W * 64;
+
+    /// Returns whether bit `i` is set. Out of range bits are never set.
+    pub fn contains(&self, i: usize) -> bool {
+        i < Self::BITS && self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    /// Sets bit `i`, returning whether it was previously unset.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of range.
+    pub fn insert(&mut self, i: usize) -> bool {
+        let was_set = self.contains(i);
+        self.words[i / 64] |= 1 << (i % 64);
+        !was_set
+    }
+
+    /// Clears bit `i`, returning whether it was previously set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of range.
+    pub fn remove(&mut self, i: usize) -> bool {
+        let was_set = self.contains(i);
+        self.words[i / 64] &= !(1 << (i % 64));
+        was_set
+    }
+
+    /// Number of set bits.
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Index of the first unset bit, if any.
+    pub fn first_unset(&self) -> Option<usize> {
+        self.words
+            .iter()
+            .enumerate()
+            .find(|(_, w)| **w != u64::MAX)
+            .map(|(idx, w)| idx * 64 + w.trailing_ones() as usize)
+    }
+
+    pub fn clear(&mut self) {
+        self.words = [0; W];
+    }
+
+    /// Iterates over the indices of the set bits, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..Self::BITS).filter(move |i| self.contains(*i))
+    }
+}
+
+/// A `#[zero_copy]` enum, stored as its `u8` discriminant. Any byte is a valid
+/// `ZeroCopyEnum`, so account data can be cast to it safely, and the variant
+/// is checked when the enum is read.
+///
+/// ```ignore
+/// #[zero_copy]
+/// pub enum Side {
+///     Bid,
+///     Ask,
+/// }
+///
+/// #[account(zero_copy)]
+/// pub struct Order {
+///     pub side: ZeroCopyEnum<Side>,
+///     pub _padding: [u8; 7],
+///     pub price: u64,
+/// }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct ZeroCopyEnum<T> {
+    value: u8,
+    ty: PhantomData<T>,
+}
+
+// A zeroed `ZeroCopyEnum` is the first variant.
+unsafe impl<T: Copy + 'static> Zeroable for ZeroCopyEnum<T> {}
+unsafe impl<T: Copy + 'static> Pod for ZeroCopyEnum<T> {}
+
+impl<T> ZeroCopyEnum<T>
+where
+    T: Copy + Into<u8> + TryFrom<u8, Error = ProgramError>,
+{
+    pub fn new(value: T) -> Self {
+        Self {
+            value: value.into(),
+            ty: PhantomData,
+        }
+    }
+
+    /// Returns the variant, or `InvalidAccountData` if the stored byte isn't
+    /// one.
+    pub fn get(&self) -> Result<T, ProgramError> {
+        T::try_from(self.value)
+    }
+
+    pub fn set(&mut self, value: T) {
+        self.value = value.into();
+    }
+
+    /// Returns the stored discriminant, which may not be a valid variant.
+    pub fn to_u8(&self) -> u8 {
+        self.value
+    }
+}
+
+impl<T> From<T> for ZeroCopyEnum<T>
+where
+    T: Copy + Into<u8> + TryFrom<u8, Error = ProgramError>,
+{
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_wraps_around() {
+        let mut rb: RingBuffer<u64, 3> = Zeroable::zeroed();
+        assert!(rb.is_empty());
+        rb.push(1).unwrap();
+        rb.push(2).unwrap();
+        rb.push(3).unwrap();
+        assert_eq!(rb.push(4), Err(4));
+        assert_eq!(rb.pop(), Some(1));
+        rb.push(4).unwrap();
+        assert_eq!(rb.push_overwrite(5), Some(2));
+        assert_eq!(rb.iter().copied().collect::<Vec<_>>(), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn slab_reuses_freed_slots() {
+        let mut slab: Slab<u64, 2> = Zeroable::zeroed();
+        assert_eq!(slab.insert(10), Ok(0));
+        assert_eq!(slab.insert(11), Ok(1));
+        assert_eq!(slab.insert(12), Err(12));
+        assert_eq!(slab.remove(0), Some(10));
+        assert_eq!(slab.remove(0), None);
+        assert_eq!(slab.get(0), None);
+        assert_eq!(slab.insert(13), Ok(0));
+        assert_eq!(slab.iter().collect::<Vec<_>>(), vec![(0, &13), (1, &11)]);
+        assert_eq!(slab.len(), 2);
+    }
+
+    #[test]
+    fn container_layouts_have_no_padding() {
+        #[derive(Copy, Clone)]
+        #[repr(C)]
+        struct Item {
+            a: u32,
+            b: u16,
+            c: u16,
+        }
+        unsafe impl Zeroable for Item {}
+        unsafe impl Pod for Item {}
+
+        assert_eq!(RingBuffer::<u8, 8>::CAPACITY, 8);
+        assert_eq!(size_of::<RingBuffer<u8, 8>>(), 16 + 8);
+        assert_eq!(Slab::<Item, 3>::CAPACITY, 3);
+        assert_eq!(size_of::<Slab<Item, 3>>(), 24 + 3 * 8 + 3 * 8);
+        assert_eq!(align_of::<Slab<Item, 3>>(), 8);
+    }
+
+    #[test]
+    fn bit_map() {
+        let mut bm: BitMap<2> = Zeroable::zeroed();
+        assert!(bm.insert(0));
+        assert!(bm.insert(64));
+        assert!(!bm.insert(64));
+        assert_eq!(bm.first_unset(), Some(1));
+        assert!(bm.remove(0));
+        assert!(!bm.contains(128));
+        assert_eq!(bm.iter().collect::<Vec<_>>(), vec![64]);
+        assert_eq!(bm.count(), 1);
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum Side {
+        Bid,
+        Ask,
+    }
+
+    impl TryFrom<u8> for Side {
+        type Error = ProgramError;
+
+        fn try_from(value: u8) -> Result<Self, Self::Error> {
+            match value {
+                0 => Ok(Side::Bid),
+                1 => Ok(Side::Ask),
+                _ => Err(ProgramError::InvalidAccountData),
+            }
+        }
+    }
+
+    impl From<Side> for u8 {
+        fn from(value: Side) -> u8 {
+            value as u8
+        }
+    }
+
+    #[test]
+    fn zero_copy_enum_checks_variant() {
+        let mut side: ZeroCopyEnum<Side> = Zeroable::zeroed();
+        assert_eq!(side.get(), Ok(Side::Bid));
+        side.set(Side::Ask);
+        assert_eq!(bytemuck::bytes_of(&side), &[1]);
+
+        let invalid: &ZeroCopyEnum<Side> = bytemuck::from_bytes(&[2]);
+        assert_eq!(invalid.get(), Err(ProgramError::InvalidAccountData));
+        assert_eq!(invalid.to_u8(), 2);
+    }
+}
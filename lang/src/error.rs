@@ -26,6 +26,15 @@ pub enum ErrorCode {
     /// 103 - The program could not serialize the given instruction
     #[msg("The program could not serialize the given instruction")]
     InstructionDidNotSerialize,
+    /// 104 - No instruction was found at the given index of the transaction
+    #[msg("No instruction was found at the given index of the transaction")]
+    InstructionNotFound,
+    /// 105 - The instruction targets a different program than expected
+    #[msg("The instruction targets a different program than expected")]
+    InstructionProgramMismatch,
+    /// 106 - 8 byte instruction identifier did not match what was expected
+    #[msg("8 byte instruction identifier did not match what was expected")]
+    InstructionDiscriminatorMismatch,
 
     // IDL instructions
     /// 1000 - The program was compiled without idl instructions
@@ -98,6 +107,9 @@ pub enum ErrorCode {
     /// 2019 - A space constraint was violated
     #[msg("A space constraint was violated")]
     ConstraintSpace,
+    /// 2020 - A migrate constraint was violated
+    #[msg("A migrate constraint was violated")]
+    ConstraintMigrate,
 
     // Accounts.
     /// 3000 - The account discriminator was already set on this account
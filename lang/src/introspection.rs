@@ -0,0 +1,329 @@
+//! Helpers for inspecting the other instructions of the currently executing
+//! transaction, via the instructions sysvar.
+//!
+//! To use them, add the sysvar to the instruction's accounts, e.g.,
+//!
+//! ```ignore
+//! #[derive(Accounts)]
+//! pub struct Borrow<'info> {
+//!     #[account(address = sysvar::instructions::ID)]
+//!     pub instructions: UncheckedAccount<'info>,
+//! }
+//! ```
+//!
+//! and then, for example, require the transaction to repay a flash loan:
+//!
+//! ```ignore
+//! let ixs = Introspector::new(&ctx.accounts.instructions)?;
+//! let repay: instruction::Repay = ixs.require_next(ctx.program_id)?;
+//! require!(repay.amount >= amount, ErrorCode::InsufficientRepayment);
+//! ```
+
+use crate::error::ErrorCode;
+use crate::{AnchorDeserialize, Discriminator};
+use solana_program::account_info::AccountInfo;
+use solana_program::instruction::Instruction;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::sysvar::instructions;
+
+/// Reads instructions of the current transaction from the instructions
+/// sysvar.
+pub struct Introspector<'a, 'info> {
+    sysvar: &'a AccountInfo<'info>,
+    current_index: usize,
+    len: usize,
+}
+
+impl<'a, 'info> Introspector<'a, 'info> {
+    /// Fails with `UnsupportedSysvar` if `sysvar` isn't the instructions
+    /// sysvar.
+    pub fn new(sysvar: &'a AccountInfo<'info>) -> Result<Self, ProgramError> {
+        let current_index = instructions::load_current_index_checked(sysvar)? as usize;
+        // The sysvar data starts with the number of instructions, as a u16.
+        let len = {
+            let data = sysvar.try_borrow_data()?;
+            if data.len() < 2 {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            u16::from_le_bytes([data[0], data[1]]) as usize
+        };
+        Ok(Self {
+            sysvar,
+            current_index,
+            len,
+        })
+    }
+
+    /// Index of the currently executing instruction.
+    pub fn current_index(&self) -> usize {
+        self.current_index
+    }
+
+    /// Number of instructions in the transaction.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Loads the instruction at `index`.
+    pub fn get(&self, index: usize) -> Result<Instruction, ProgramError> {
+        if index >= self.len {
+            return Err(ErrorCode::InstructionNotFound.into());
+        }
+        instructions::load_instruction_at_checked(index, self.sysvar)
+    }
+
+    /// Loads the instruction `offset` positions away from the current one,
+    /// e.g., `1` for the next instruction and `-1` for the previous one.
+    pub fn get_relative(&self, offset: i64) -> Result<Instruction, ProgramError> {
+        let index = self.current_index as i64 + offset;
+        if index < 0 {
+            return Err(ErrorCode::InstructionNotFound.into());
+        }
+        self.get(index as usize)
+    }
+
+    /// Iterates over all the instructions of the transaction, including the
+    /// current one, along with their index.
+    pub fn iter(&self) -> Iter<'_, 'a, 'info> {
+        Iter {
+            introspector: self,
+            index: 0,
+        }
+    }
+
+    /// Requires the next instruction to be `T` on `program_id`, returning its
+    /// decoded arguments.
+    pub fn require_next<T: Discriminator + AnchorDeserialize>(
+        &self,
+        program_id: &Pubkey,
+    ) -> Result<T, ProgramError> {
+        self.require_at(self.current_index + 1, program_id)
+    }
+
+    /// Requires the previous instruction to be `T` on `program_id`, returning
+    /// its decoded arguments.
+    pub fn require_previous<T: Discriminator + AnchorDeserialize>(
+        &self,
+        program_id: &Pubkey,
+    ) -> Result<T, ProgramError> {
+        let index = self
+            .current_index
+            .checked_sub(1)
+            .ok_or(ErrorCode::InstructionNotFound)?;
+        self.require_at(index, program_id)
+    }
+
+    /// Requires the instruction at `index` to be `T` on `program_id`,
+    /// returning its decoded arguments.
+    pub fn require_at<T: Discriminator + AnchorDeserialize>(
+        &self,
+        index: usize,
+        program_id: &Pubkey,
+    ) -> Result<T, ProgramError> {
+        let ix = self.get(index)?;
+        if &ix.program_id != program_id {
+            return Err(ErrorCode::InstructionProgramMismatch.into());
+        }
+        decode(&ix)
+    }
+
+    /// Finds the first instruction in the transaction that is `T` on
+    /// `program_id`, returning its index and decoded arguments.
+    pub fn find<T: Discriminator + AnchorDeserialize>(
+        &self,
+        program_id: &Pubkey,
+    ) -> Result<Option<(usize, T)>, ProgramError> {
+        for (idx, ix) in self.iter() {
+            let ix = ix?;
+            if &ix.program_id == program_id && is_instruction::<T>(&ix) {
+                return Ok(Some((idx, decode(&ix)?)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Iterator over the instructions of the transaction, returned by
+/// [`Introspector::iter`].
+pub struct Iter<'s, 'a, 'info> {
+    introspector: &'s Introspector<'a, 'info>,
+    index: usize,
+}
+
+impl<'s, 'a, 'info> Iterator for Iter<'s, 'a, 'info> {
+    type Item = (usize, Result<Instruction, ProgramError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.introspector.len {
+            return None;
+        }
+        let index = self.index;
+        self.index += 1;
+        Some((index, self.introspector.get(index)))
+    }
+}
+
+/// Returns whether the data of `ix` starts with the discriminator of `T`.
+/// The program id isn't checked.
+pub fn is_instruction<T: Discriminator>(ix: &Instruction) -> bool {
+    ix.data.len() >= 8 && ix.data[..8] == T::discriminator()
+}
+
+/// Decodes the arguments of `ix` into an Anchor generated instruction struct,
+/// e.g., `my_program::instruction::Repay`. The program id isn't checked.
+pub fn decode<T: Discriminator + AnchorDeserialize>(ix: &Instruction) -> Result<T, ProgramError> {
+    if !is_instruction::<T>(ix) {
+        return Err(ErrorCode::InstructionDiscriminatorMismatch.into());
+    }
+    T::deserialize(&mut &ix.data[8..]).map_err(|_| ErrorCode::InstructionDidNotDeserialize.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AnchorSerialize;
+    use solana_program::instruction::AccountMeta;
+
+    #[derive(AnchorSerialize, AnchorDeserialize)]
+    struct Repay {
+        amount: u64,
+    }
+
+    impl Discriminator for Repay {
+        fn discriminator() -> [u8; 8] {
+            [1, 2, 3, 4, 5, 6, 7, 8]
+        }
+    }
+
+    #[test]
+    fn decode_checks_discriminator() {
+        let mut data = Repay::discriminator().to_vec();
+        data.extend(Repay { amount: 42 }.try_to_vec().unwrap());
+        let mut ix = Instruction {
+            program_id: Pubkey::new_unique(),
+            accounts: vec![],
+            data,
+        };
+        assert_eq!(decode::<Repay>(&ix).unwrap().amount, 42);
+
+        ix.data[0] = 0;
+        assert!(!is_instruction::<Repay>(&ix));
+        assert_eq!(
+            decode::<Repay>(&ix).err(),
+            Some(ErrorCode::InstructionDiscriminatorMismatch.into())
+        );
+    }
+
+    fn repay(program_id: Pubkey, amount: u64) -> Instruction {
+        let mut data = Repay::discriminator().to_vec();
+        data.extend(Repay { amount }.try_to_vec().unwrap());
+        Instruction {
+            program_id,
+            accounts: vec![AccountMeta::new(Pubkey::new_unique(), true)],
+            data,
+        }
+    }
+
+    // Serializes `ixs` the way the runtime lays out the instructions sysvar,
+    // followed by the index of the current instruction.
+    fn sysvar_data(ixs: &[Instruction], current_index: u16) -> Vec<u8> {
+        let mut data = (ixs.len() as u16).to_le_bytes().to_vec();
+        data.resize(2 + 2 * ixs.len(), 0);
+        for (idx, ix) in ixs.iter().enumerate() {
+            let offset = (data.len() as u16).to_le_bytes();
+            data[2 + 2 * idx..4 + 2 * idx].copy_from_slice(&offset);
+            data.extend_from_slice(&(ix.accounts.len() as u16).to_le_bytes());
+            for meta in &ix.accounts {
+                data.push(meta.is_signer as u8 | (meta.is_writable as u8) << 1);
+                data.extend(meta.pubkey.as_ref());
+            }
+            data.extend(ix.program_id.as_ref());
+            data.extend_from_slice(&(ix.data.len() as u16).to_le_bytes());
+            data.extend(&ix.data);
+        }
+        data.extend_from_slice(&current_index.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn introspector_loads_instructions() {
+        let program_id = Pubkey::new_unique();
+        let other = Instruction {
+            program_id: Pubkey::new_unique(),
+            accounts: vec![],
+            data: vec![9; 4],
+        };
+        let ixs = vec![repay(program_id, 1), other.clone(), repay(program_id, 2)];
+        let mut data = sysvar_data(&ixs, 1);
+        let mut lamports = 0;
+        let owner = Pubkey::default();
+        let sysvar = AccountInfo::new(
+            &instructions::ID,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        let introspector = Introspector::new(&sysvar).unwrap();
+        assert_eq!(introspector.current_index(), 1);
+        assert_eq!(introspector.len(), 3);
+
+        assert_eq!(introspector.get(0).unwrap(), ixs[0]);
+        assert_eq!(introspector.get_relative(0).unwrap(), other);
+        assert_eq!(introspector.get_relative(1).unwrap(), ixs[2]);
+        assert_eq!(
+            introspector.get(3).err(),
+            Some(ErrorCode::InstructionNotFound.into())
+        );
+        assert_eq!(
+            introspector.get_relative(-2).err(),
+            Some(ErrorCode::InstructionNotFound.into())
+        );
+
+        let next: Repay = introspector.require_next(&program_id).unwrap();
+        assert_eq!(next.amount, 2);
+        let previous: Repay = introspector.require_previous(&program_id).unwrap();
+        assert_eq!(previous.amount, 1);
+        assert_eq!(
+            introspector
+                .require_next::<Repay>(&Pubkey::new_unique())
+                .err(),
+            Some(ErrorCode::InstructionProgramMismatch.into())
+        );
+
+        let indices: Vec<usize> = introspector.iter().map(|(idx, _)| idx).collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+        let (idx, found) = introspector.find::<Repay>(&program_id).unwrap().unwrap();
+        assert_eq!((idx, found.amount), (0, 1));
+    }
+
+    #[test]
+    fn introspector_requires_instructions_sysvar() {
+        let mut data = sysvar_data(&[], 0);
+        let mut lamports = 0;
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::default();
+        let account = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        assert_eq!(
+            Introspector::new(&account).err(),
+            Some(ProgramError::UnsupportedSysvar)
+        );
+    }
+}
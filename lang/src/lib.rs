@@ -35,11 +35,13 @@ mod account_meta;
 mod accounts;
 mod bpf_upgradeable_state;
 mod common;
+mod containers;
 mod context;
 mod ctor;
-mod error;
+pub mod error;
 #[doc(hidden)]
 pub mod idl;
+pub mod introspection;
 mod system_program;
 
 pub use crate::accounts::account::Account;
@@ -63,6 +65,7 @@ pub use crate::accounts::state::ProgramState;
 pub use crate::accounts::system_account::SystemAccount;
 pub use crate::accounts::sysvar::Sysvar;
 pub use crate::accounts::unchecked_account::UncheckedAccount;
+pub use crate::containers::{BitMap, RingBuffer, Slab, ZeroCopyEnum};
 pub use crate::system_program::System;
 mod vec;
 pub use crate::bpf_upgradeable_state::*;
@@ -214,6 +217,19 @@ pub trait Discriminator {
     fn discriminator() -> [u8; 8];
 }
 
+/// An account with a versioned layout, declared with
+/// `#[account(version = <n>, migrate_from = <PreviousLayout>)]`. Data written
+/// with a previous layout has the discriminator of that version, and is
+/// deserialized into the current layout.
+pub trait Versioned: Discriminator {
+    /// Version of the current layout.
+    const VERSION: u8;
+
+    /// Version of the layout `data` was written with, or `None` if it isn't
+    /// the data of this account.
+    fn data_version(data: &[u8]) -> Option<u8>;
+}
+
 /// Bump seed for program derived addresses.
 pub trait Bump {
     fn seed(&self) -> u8;
@@ -247,9 +263,9 @@ pub mod prelude {
         access_control, account, constant, declare_id, emit, error, event, interface, program,
         require, solana_program::bpf_loader_upgradeable::UpgradeableLoaderState, state, zero_copy,
         Account, AccountDeserialize, AccountLoader, AccountSerialize, Accounts, AccountsExit,
-        AnchorDeserialize, AnchorSerialize, Context, CpiContext, Id, Key, Owner, Program,
-        ProgramData, Signer, System, SystemAccount, Sysvar, ToAccountInfo, ToAccountInfos,
-        ToAccountMetas, UncheckedAccount,
+        AnchorDeserialize, AnchorSerialize, BitMap, Context, CpiContext, Id, Key, Owner, Program,
+        ProgramData, RingBuffer, Signer, Slab, System, SystemAccount, Sysvar, ToAccountInfo,
+        ToAccountInfos, ToAccountMetas, UncheckedAccount, ZeroCopyEnum,
     };
 
     #[allow(deprecated)]
@@ -310,6 +326,19 @@ pub mod __private {
     /// The starting point for user defined error codes.
     pub const ERROR_CODE_OFFSET: u32 = 6000;
 
+    // Alignment of `T` on BPF, where no type is aligned to more than 8 bytes
+    // (e.g., `u128` is aligned to 16 bytes on some hosts, but to 8 on BPF).
+    // Used to check zero copy layouts the same way on every target.
+    #[doc(hidden)]
+    pub const fn bpf_align_of<T>() -> usize {
+        let align = std::mem::align_of::<T>();
+        if align > 8 {
+            8
+        } else {
+            align
+        }
+    }
+
     // Calculates the size of an account, which may be larger than the deserialized
     // data in it. This trait is currently only used for `#[state]` accounts.
     #[doc(hidden)]
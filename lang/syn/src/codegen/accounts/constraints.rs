@@ -57,6 +57,7 @@ pub fn linearize(c_group: &ConstraintGroup) -> Vec<Constraint> {
         close,
         address,
         associated_token,
+        migrate,
     } = c_group.clone();
 
     let mut constraints = Vec::new();
@@ -100,6 +101,10 @@ pub fn linearize(c_group: &ConstraintGroup) -> Vec<Constraint> {
     if let Some(c) = address {
         constraints.push(Constraint::Address(c));
     }
+    // Last, so the account is only rewritten once all other checks passed.
+    if let Some(c) = migrate {
+        constraints.push(Constraint::Migrate(c));
+    }
     constraints
 }
 
@@ -120,6 +125,7 @@ fn generate_constraint(f: &Field, c: &Constraint) -> proc_macro2::TokenStream {
         Constraint::Close(c) => generate_constraint_close(f, c),
         Constraint::Address(c) => generate_constraint_address(f, c),
         Constraint::AssociatedToken(c) => generate_constraint_associated_token(f, c),
+        Constraint::Migrate(c) => generate_constraint_migrate(f, c),
     }
 }
 
@@ -174,6 +180,37 @@ pub fn generate_constraint_close(f: &Field, c: &ConstraintClose) -> proc_macro2:
     }
 }
 
+// Rewrites an account written with a previous layout with the current one.
+// The deserialized account was already migrated in memory by `#[account]`.
+pub fn generate_constraint_migrate(f: &Field, _c: &ConstraintMigrate) -> proc_macro2::TokenStream {
+    let field = &f.ident;
+    let account_ty = f.account_ty();
+    quote! {
+        {
+            let __info = #field.to_account_info();
+            let __version = {
+                let __data = __info.try_borrow_data()?;
+                <#account_ty as anchor_lang::Versioned>::data_version(&__data)
+            };
+            if __version != Some(<#account_ty as anchor_lang::Versioned>::VERSION) {
+                let mut __migrated: Vec<u8> = Vec::new();
+                #field.try_serialize(&mut __migrated)?;
+                let mut __data = __info.try_borrow_mut_data()?;
+                // Account data can't be reallocated, so the current layout
+                // must fit in the account.
+                if __migrated.len() > __data.len() {
+                    return Err(anchor_lang::__private::ErrorCode::ConstraintMigrate.into());
+                }
+                __data[..__migrated.len()].copy_from_slice(&__migrated);
+                // Clears what's left of the previous layout, if it was larger.
+                for __byte in __data[__migrated.len()..].iter_mut() {
+                    *__byte = 0;
+                }
+            }
+        }
+    }
+}
+
 pub fn generate_constraint_mut(f: &Field, c: &ConstraintMut) -> proc_macro2::TokenStream {
     let ident = &f.ident;
     let error = generate_custom_error(&c.error, quote! { ConstraintMut });
@@ -32,6 +32,22 @@ pub fn generate(error: Error) -> proc_macro2::TokenStream {
         })
         .collect();
 
+    // Each arm of the `TryFrom<u32>` implementation, mapping an error code
+    // back to its variant.
+    let variant_codes: Vec<proc_macro2::TokenStream> = error
+        .raw_enum
+        .variants
+        .iter()
+        .map(|variant| {
+            let ident = &variant.ident;
+            quote! {
+                if code == #enum_name::#ident as u32 + offset {
+                    return Ok(#enum_name::#ident);
+                }
+            }
+        })
+        .collect();
+
     let offset = match error.args {
         None => quote! { anchor_lang::__private::ERROR_CODE_OFFSET},
         Some(args) => {
@@ -71,6 +87,18 @@ pub fn generate(error: Error) -> proc_macro2::TokenStream {
 
         impl std::error::Error for #enum_name {}
 
+        /// Maps a `ProgramError::Custom` code back to its variant, returning
+        /// the code itself if no variant has it.
+        impl std::convert::TryFrom<u32> for #enum_name {
+            type Error = u32;
+
+            fn try_from(code: u32) -> std::result::Result<Self, u32> {
+                let offset: u32 = #offset;
+                #(#variant_codes)*
+                Err(code)
+            }
+        }
+
         impl std::convert::From<Error> for anchor_lang::solana_program::program_error::ProgramError {
             fn from(e: Error) -> anchor_lang::solana_program::program_error::ProgramError {
                 match e {
@@ -82,6 +82,12 @@ pub fn generate(program: &Program) -> proc_macro2::TokenStream {
                             let sighash_tts: proc_macro2::TokenStream =
                                 format!("{:?}", sighash_arr).parse().unwrap();
                             quote! {
+                                impl anchor_lang::Discriminator for #ix_name_camel {
+                                    fn discriminator() -> [u8; 8] {
+                                        #sighash_tts
+                                    }
+                                }
+
                                 impl anchor_lang::InstructionData for #ix_name_camel {
                                     fn data(&self) -> Vec<u8> {
                                         let mut d = #sighash_tts.to_vec();
@@ -138,6 +144,12 @@ pub fn generate(program: &Program) -> proc_macro2::TokenStream {
                 let sighash_tts: proc_macro2::TokenStream =
                     format!("{:?}", sighash_arr).parse().unwrap();
                 quote! {
+                    impl anchor_lang::Discriminator for #ix_name_camel {
+                        fn discriminator() -> [u8; 8] {
+                            #sighash_tts
+                        }
+                    }
+
                     impl anchor_lang::InstructionData for #ix_name_camel {
                         fn data(&self) -> Vec<u8> {
                             let mut d = #sighash_tts.to_vec();
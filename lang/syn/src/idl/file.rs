@@ -8,6 +8,7 @@ use heck::MixedCase;
 use quote::ToTokens;
 use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use syn::punctuated::Punctuated;
 
 const DERIVE_NAME: &str = "Accounts";
 // TODO: sharee this with `anchor_lang` crate.
@@ -52,7 +53,7 @@ pub fn parse(filename: impl AsRef<Path>, version: String) -> Result<Option<Idl>>
                                     .collect::<Vec<_>>();
                                 let accounts_strct =
                                     accs.get(&method.anchor_ident.to_string()).unwrap();
-                                let accounts = idl_accounts(accounts_strct, &accs);
+                                let accounts = idl_accounts(accounts_strct, &accs, &args);
                                 IdlInstruction {
                                     name,
                                     accounts,
@@ -89,9 +90,9 @@ pub fn parse(filename: impl AsRef<Path>, version: String) -> Result<Option<Idl>>
                             }
                             _ => panic!("Invalid syntax"),
                         })
-                        .collect();
+                        .collect::<Vec<IdlField>>();
                     let accounts_strct = accs.get(&anchor_ident.to_string()).unwrap();
-                    let accounts = idl_accounts(accounts_strct, &accs);
+                    let accounts = idl_accounts(accounts_strct, &accs, &args);
                     IdlInstruction {
                         name,
                         accounts,
@@ -121,6 +122,9 @@ pub fn parse(filename: impl AsRef<Path>, version: String) -> Result<Option<Idl>>
                     IdlTypeDefinition {
                         name: state.name,
                         ty: IdlTypeDefinitionTy::Struct { fields },
+                        serialization: IdlSerialization::Borsh,
+                        version: None,
+                        versions: vec![],
                     }
                 };
 
@@ -159,7 +163,7 @@ pub fn parse(filename: impl AsRef<Path>, version: String) -> Result<Option<Idl>>
                 .collect::<Vec<_>>();
             // todo: don't unwrap
             let accounts_strct = accs.get(&ix.anchor_ident.to_string()).unwrap();
-            let accounts = idl_accounts(accounts_strct, &accs);
+            let accounts = idl_accounts(accounts_strct, &accs, &args);
             IdlInstruction {
                 name: ix.ident.to_string().to_mixed_case(),
                 accounts,
@@ -211,11 +215,42 @@ pub fn parse(filename: impl AsRef<Path>, version: String) -> Result<Option<Idl>>
 
     let error_name = error.map(|e| e.name).unwrap_or_else(|| "".to_string());
 
+    // Versioned accounts list the layouts of their previous versions.
+    let mut versions = HashMap::new();
+    for strct in &account_structs {
+        let args = match account_args(&strct.attrs) {
+            None => continue,
+            Some(args) => args,
+        };
+        let mut layouts = vec![];
+        for (version, layout) in args.previous_versions() {
+            let name = layout.path.segments.last().unwrap().ident.to_string();
+            let ty_def = ty_defs
+                .iter()
+                .find(|ty_def| ty_def.name == name)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Layout {} of account {} must be a public struct deriving AnchorSerialize and AnchorDeserialize",
+                        name,
+                        strct.ident
+                    )
+                })?;
+            layouts.push(IdlAccountVersion {
+                version,
+                name,
+                ty: ty_def.ty.clone(),
+            });
+        }
+        versions.insert(strct.ident.to_string(), layouts);
+    }
+
     // All types that aren't in the accounts section, are in the types section.
     for ty_def in ty_defs {
         // Don't add the error type to the types or accounts sections.
         if ty_def.name != error_name {
             if account_names.contains(&ty_def.name) {
+                let mut ty_def = ty_def;
+                ty_def.versions = versions.remove(&ty_def.name).unwrap_or_default();
                 accounts.push(ty_def);
             } else if !events.iter().any(|e| e.name == ty_def.name) {
                 types.push(ty_def);
@@ -400,11 +435,24 @@ fn parse_ty_defs(ctx: &CrateContext) -> Result<Vec<IdlTypeDefinition>> {
                     .named
                     .iter()
                     .map(|f: &syn::Field| {
+                        // Fields using the deprecated `#[accessor(Pubkey)]`
+                        // api are stored as `[u8; 32]`, which has the same
+                        // layout as a `Pubkey`.
+                        if is_pubkey_accessor(f) {
+                            return Ok(IdlField {
+                                name: f.ident.as_ref().unwrap().to_string().to_mixed_case(),
+                                ty: IdlType::PublicKey,
+                            });
+                        }
                         let mut tts = proc_macro2::TokenStream::new();
                         f.ty.to_tokens(&mut tts);
-                        // Handle array sizes that are constants
+                        // Handle array and container sizes that are constants
                         let mut tts_string = tts.to_string();
-                        if tts_string.starts_with('[') {
+                        if tts_string.starts_with('[')
+                            || tts_string.starts_with("RingBuffer")
+                            || tts_string.starts_with("Slab")
+                            || tts_string.starts_with("BitMap")
+                        {
                             tts_string = resolve_variable_array_length(ctx, tts_string);
                         }
                         Ok(IdlField {
@@ -417,9 +465,17 @@ fn parse_ty_defs(ctx: &CrateContext) -> Result<Vec<IdlTypeDefinition>> {
                 _ => panic!("Empty structs are allowed."),
             };
 
+            let serialization = match is_zero_copy(&item_strct.attrs) {
+                true => IdlSerialization::Bytemuck,
+                false => IdlSerialization::Borsh,
+            };
+
             Some(fields.map(|fields| IdlTypeDefinition {
                 name,
                 ty: IdlTypeDefinitionTy::Struct { fields },
+                serialization,
+                version: account_args(&item_strct.attrs).and_then(|args| args.version),
+                versions: vec![],
             }))
         })
         .chain(ctx.enums().map(|enm| {
@@ -452,14 +508,48 @@ fn parse_ty_defs(ctx: &CrateContext) -> Result<Vec<IdlTypeDefinition>> {
                     IdlEnumVariant { name, fields }
                 })
                 .collect::<Vec<IdlEnumVariant>>();
+            let serialization = match is_zero_copy(&enm.attrs) {
+                true => IdlSerialization::Bytemuck,
+                false => IdlSerialization::Borsh,
+            };
             Ok(IdlTypeDefinition {
                 name,
                 ty: IdlTypeDefinitionTy::Enum { variants },
+                serialization,
+                version: None,
+                versions: vec![],
             })
         }))
         .collect()
 }
 
+// Arguments of the item's `#[account]` attribute, if it has one.
+fn account_args(attrs: &[syn::Attribute]) -> Option<crate::AccountArgs> {
+    let attr = attrs
+        .iter()
+        .find(|attr| attr.path.segments.last().unwrap().ident == "account")?;
+    match attr.tokens.is_empty() {
+        true => Some(crate::AccountArgs::default()),
+        false => attr.parse_args().ok(),
+    }
+}
+
+// True if the item is marked with `#[zero_copy]` or `#[account(zero_copy)]`.
+fn is_zero_copy(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        let attr_name = attr.path.segments.last().unwrap().ident.to_string();
+        attr_name == "zero_copy"
+            || (attr_name == "account" && attr.tokens.to_string().contains("zero_copy"))
+    })
+}
+
+fn is_pubkey_accessor(f: &syn::Field) -> bool {
+    f.attrs.iter().any(|attr| {
+        parser::tts_to_string(&attr.path) == "accessor"
+            && attr.tokens.to_string().contains("Pubkey")
+    })
+}
+
 // Replace variable array lengths with values
 fn resolve_variable_array_length(ctx: &CrateContext, tts_string: String) -> String {
     for constant in ctx.consts() {
@@ -496,6 +586,7 @@ fn to_idl_type(f: &syn::Field) -> IdlType {
 fn idl_accounts(
     accounts: &AccountsStruct,
     global_accs: &HashMap<String, AccountsStruct>,
+    args: &[IdlField],
 ) -> Vec<IdlAccountItem> {
     accounts
         .fields
@@ -505,7 +596,7 @@ fn idl_accounts(
                 let accs_strct = global_accs
                     .get(&comp_f.symbol)
                     .expect("Could not resolve Accounts symbol");
-                let accounts = idl_accounts(accs_strct, global_accs);
+                let accounts = idl_accounts(accs_strct, global_accs, args);
                 IdlAccountItem::IdlAccounts(IdlAccounts {
                     name: comp_f.ident.to_string().to_mixed_case(),
                     accounts,
@@ -518,7 +609,154 @@ fn idl_accounts(
                     Ty::Signer => true,
                     _ => acc.constraints.is_signer(),
                 },
+                pda: acc
+                    .constraints
+                    .seeds()
+                    .and_then(|seeds| idl_pda(&seeds.seeds, accounts, args)),
             }),
         })
         .collect::<Vec<_>>()
 }
+
+// Seeds of a PDA, if they're all constants, arguments or accounts.
+fn idl_pda(
+    seeds: &Punctuated<syn::Expr, syn::Token![,]>,
+    accounts: &AccountsStruct,
+    args: &[IdlField],
+) -> Option<IdlPda> {
+    let seeds = seeds
+        .iter()
+        .map(|seed| idl_seed(seed, accounts, args))
+        .collect::<Option<Vec<_>>>()?;
+    Some(IdlPda { seeds })
+}
+
+fn idl_seed(seed: &syn::Expr, accounts: &AccountsStruct, args: &[IdlField]) -> Option<IdlSeed> {
+    match seed {
+        syn::Expr::Lit(lit) => match &lit.lit {
+            syn::Lit::ByteStr(b) => Some(IdlSeed::Const { value: b.value() }),
+            syn::Lit::Str(s) => Some(IdlSeed::Const {
+                value: s.value().into_bytes(),
+            }),
+            _ => None,
+        },
+        syn::Expr::Reference(r) => idl_seed(&r.expr, accounts, args),
+        syn::Expr::Paren(p) => idl_seed(&p.expr, accounts, args),
+        // Conversions to bytes, e.g., `authority.key().as_ref()`.
+        syn::Expr::MethodCall(call) if call.args.is_empty() => {
+            match call.method.to_string().as_str() {
+                "as_ref" | "as_bytes" | "to_le_bytes" | "to_bytes" | "key" => {
+                    idl_seed(&call.receiver, accounts, args)
+                }
+                _ => None,
+            }
+        }
+        syn::Expr::Field(field) => match &field.member {
+            syn::Member::Named(member) if member == "key" => idl_seed(&field.base, accounts, args),
+            _ => None,
+        },
+        syn::Expr::Path(path) => {
+            let ident = path.path.get_ident()?.to_string();
+            let is_account = accounts.fields.iter().any(|f| match f {
+                AccountField::Field(f) => f.ident == ident,
+                AccountField::CompositeField(_) => false,
+            });
+            let name = ident.to_mixed_case();
+            if is_account {
+                return Some(IdlSeed::Account { path: name });
+            }
+            args.iter()
+                .find(|arg| arg.name == name)
+                .map(|arg| IdlSeed::Arg {
+                    ty: arg.ty.clone(),
+                    path: name,
+                })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The seeds of each account of the accounts struct, by name.
+    fn pdas(accounts: &str, args: &[IdlField]) -> HashMap<String, Option<IdlPda>> {
+        let accounts: AccountsStruct = syn::parse_str(accounts).unwrap();
+        idl_accounts(&accounts, &HashMap::new(), args)
+            .into_iter()
+            .map(|item| match item {
+                IdlAccountItem::IdlAccount(acc) => (acc.name, acc.pda),
+                IdlAccountItem::IdlAccounts(_) => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pda_seeds() {
+        let args = vec![
+            IdlField {
+                name: "amount".to_string(),
+                ty: IdlType::U64,
+            },
+            IdlField {
+                name: "name".to_string(),
+                ty: IdlType::String,
+            },
+        ];
+        let pdas = pdas(
+            r#"
+            #[derive(Accounts)]
+            #[instruction(amount: u64, name: String)]
+            pub struct Initialize<'info> {
+                pub authority: Signer<'info>,
+                #[account(seeds = [b"vault", authority.key().as_ref()], bump)]
+                pub vault: AccountInfo<'info>,
+                #[account(seeds = [amount.to_le_bytes().as_ref(), name.as_bytes()], bump)]
+                pub by_args: AccountInfo<'info>,
+                #[account(seeds = [b"state", vault.owner.as_ref()], bump)]
+                pub unsupported_field: AccountInfo<'info>,
+                #[account(seeds = [b"state", seed().as_ref()], bump)]
+                pub unsupported_call: AccountInfo<'info>,
+                #[account(seeds = [b"state", unknown.as_ref()], bump)]
+                pub unknown_path: AccountInfo<'info>,
+            }
+            "#,
+            &args,
+        );
+
+        assert_eq!(pdas["authority"], None);
+        assert_eq!(
+            pdas["vault"],
+            Some(IdlPda {
+                seeds: vec![
+                    IdlSeed::Const {
+                        value: b"vault".to_vec()
+                    },
+                    IdlSeed::Account {
+                        path: "authority".to_string()
+                    },
+                ]
+            })
+        );
+        assert_eq!(
+            pdas["byArgs"],
+            Some(IdlPda {
+                seeds: vec![
+                    IdlSeed::Arg {
+                        ty: IdlType::U64,
+                        path: "amount".to_string()
+                    },
+                    IdlSeed::Arg {
+                        ty: IdlType::String,
+                        path: "name".to_string()
+                    },
+                ]
+            })
+        );
+        // Seeds the IDL can't express leave the account without a PDA.
+        assert_eq!(pdas["unsupportedField"], None);
+        assert_eq!(pdas["unsupportedCall"], None);
+        assert_eq!(pdas["unknownPath"], None);
+    }
+}
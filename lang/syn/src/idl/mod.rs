@@ -66,6 +66,35 @@ pub struct IdlAccount {
     pub name: String,
     pub is_mut: bool,
     pub is_signer: bool,
+    // Seeds of the account, if it's a PDA of the program with seeds the IDL
+    // can express.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub pda: Option<IdlPda>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdlPda {
+    pub seeds: Vec<IdlSeed>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum IdlSeed {
+    // Constant bytes, e.g., `b"vault"`.
+    Const {
+        value: Vec<u8>,
+    },
+    // An instruction argument, as its little endian bytes, or as is for
+    // strings.
+    Arg {
+        #[serde(rename = "type")]
+        ty: IdlType,
+        path: String,
+    },
+    // The address of another account of the same accounts struct.
+    Account {
+        path: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -94,6 +123,44 @@ pub struct IdlTypeDefinition {
     pub name: String,
     #[serde(rename = "type")]
     pub ty: IdlTypeDefinitionTy,
+    #[serde(skip_serializing_if = "IdlSerialization::is_borsh", default)]
+    pub serialization: IdlSerialization,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub version: Option<u8>,
+    // Layouts of the previous versions of a versioned account, oldest first.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub versions: Vec<IdlAccountVersion>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdlAccountVersion {
+    pub version: u8,
+    // Name of the struct of the layout, e.g., `CounterV1`.
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: IdlTypeDefinitionTy,
+}
+
+// Layout of a type definition's data. Zero copy types are laid out in memory
+// with `#[repr(C)]` (or `#[repr(u8)]` for enums) and cast with bytemuck, so
+// clients need to know not to assume borsh when decoding them.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdlSerialization {
+    Borsh,
+    Bytemuck,
+}
+
+impl IdlSerialization {
+    pub fn is_borsh(&self) -> bool {
+        *self == IdlSerialization::Borsh
+    }
+}
+
+impl Default for IdlSerialization {
+    fn default() -> Self {
+        IdlSerialization::Borsh
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -138,6 +205,9 @@ pub enum IdlType {
     Option(Box<IdlType>),
     Vec(Box<IdlType>),
     Array(Box<IdlType>, usize),
+    RingBuffer(Box<IdlType>, usize),
+    Slab(Box<IdlType>, usize),
+    BitMap(usize),
 }
 
 impl std::str::FromStr for IdlType {
@@ -156,6 +226,16 @@ impl std::str::FromStr for IdlType {
                 Some(nested_inner) => array_from_str(&nested_inner[1..]),
             }
         }
+        // Parses the `T,N>` generics of a zero copy container.
+        fn container_from_str(inner: &str) -> Result<(IdlType, usize), anyhow::Error> {
+            let (raw_type, raw_length) = inner
+                .strip_suffix('>')
+                .and_then(|inner| inner.rsplit_once(','))
+                .ok_or_else(|| anyhow::anyhow!("Invalid container"))?;
+            let ty = IdlType::from_str(raw_type)?;
+            let len = raw_length.replace('_', "").parse::<usize>()?;
+            Ok((ty, len))
+        }
         s.retain(|c| !c.is_whitespace());
         let r = match s.as_str() {
             "bool" => IdlType::Bool,
@@ -172,6 +252,30 @@ impl std::str::FromStr for IdlType {
             "Vec<u8>" => IdlType::Bytes,
             "String" => IdlType::String,
             "Pubkey" => IdlType::PublicKey,
+            _ if s.starts_with("RingBuffer<") => {
+                let (ty, len) = container_from_str(&s["RingBuffer<".len()..])?;
+                IdlType::RingBuffer(Box::new(ty), len)
+            }
+            _ if s.starts_with("Slab<") => {
+                let (ty, len) = container_from_str(&s["Slab<".len()..])?;
+                IdlType::Slab(Box::new(ty), len)
+            }
+            // Stored as the enum's `u8` discriminant, i.e., laid out as the
+            // enum itself.
+            _ if s.starts_with("ZeroCopyEnum<") => {
+                let inner = s["ZeroCopyEnum<".len()..]
+                    .strip_suffix('>')
+                    .ok_or_else(|| anyhow::anyhow!("Invalid zero copy enum"))?;
+                IdlType::from_str(inner)?
+            }
+            _ if s.starts_with("BitMap<") => {
+                let len = s["BitMap<".len()..]
+                    .strip_suffix('>')
+                    .ok_or_else(|| anyhow::anyhow!("Invalid bit map"))?
+                    .replace('_', "")
+                    .parse::<usize>()?;
+                IdlType::BitMap(len)
+            }
             _ => match s.to_string().strip_prefix("Option<") {
                 None => match s.to_string().strip_prefix("Vec<") {
                     None => {
@@ -214,7 +318,7 @@ pub struct IdlErrorCode {
 
 #[cfg(test)]
 mod tests {
-    use crate::idl::IdlType;
+    use crate::idl::{IdlType, IdlTypeDefinition};
     use std::str::FromStr;
 
     #[test]
@@ -256,4 +360,36 @@ mod tests {
             IdlType::Vec(Box::new(IdlType::Bool))
         )
     }
+
+    #[test]
+    fn zero_copy_containers() {
+        assert_eq!(
+            IdlType::from_str("RingBuffer<[u8; 32], 1_024>").unwrap(),
+            IdlType::RingBuffer(Box::new(IdlType::Array(Box::new(IdlType::U8), 32)), 1024)
+        );
+        assert_eq!(
+            IdlType::from_str("Slab<Order, 16>").unwrap(),
+            IdlType::Slab(Box::new(IdlType::Defined("Order".to_string())), 16)
+        );
+        assert_eq!(IdlType::from_str("BitMap<4>").unwrap(), IdlType::BitMap(4));
+        assert_eq!(
+            IdlType::from_str("ZeroCopyEnum<Side>").unwrap(),
+            IdlType::Defined("Side".to_string())
+        );
+    }
+
+    #[test]
+    fn account_versions() {
+        let json = r#"{"name":"Counter","type":{"kind":"struct","fields":[]}}"#;
+        let ty_def: IdlTypeDefinition = serde_json::from_str(json).unwrap();
+        assert_eq!(ty_def.version, None);
+        assert!(ty_def.versions.is_empty());
+        assert_eq!(serde_json::to_string(&ty_def).unwrap(), json);
+
+        let json = r#"{"name":"Counter","type":{"kind":"struct","fields":[]},"version":2,"versions":[{"version":1,"name":"CounterV1","type":{"kind":"struct","fields":[]}}]}"#;
+        let ty_def: IdlTypeDefinition = serde_json::from_str(json).unwrap();
+        assert_eq!(ty_def.version, Some(2));
+        assert_eq!(ty_def.versions[0].name, "CounterV1");
+        assert_eq!(serde_json::to_string(&ty_def).unwrap(), json);
+    }
 }
@@ -505,6 +505,98 @@ impl Parse for ErrorArgs {
     }
 }
 
+// Arguments of the `#[account]` attribute on an account struct, e.g.,
+// `#[account(version = 2, migrate_from = CounterV1)]`.
+#[derive(Debug, Default)]
+pub struct AccountArgs {
+    pub namespace: Option<String>,
+    pub zero_copy: bool,
+    // Version of the account's layout, if it's versioned.
+    pub version: Option<u8>,
+    // Previous layouts, oldest first, i.e., the last one is the layout of
+    // the previous version.
+    pub migrate_from: Vec<TypePath>,
+}
+
+impl AccountArgs {
+    // The previous layouts, along with their versions.
+    pub fn previous_versions(&self) -> Vec<(u8, &TypePath)> {
+        let version = self.version.unwrap_or(1);
+        let first = version - self.migrate_from.len() as u8;
+        self.migrate_from
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| (first + i as u8, ty))
+            .collect()
+    }
+}
+
+impl Parse for AccountArgs {
+    fn parse(stream: ParseStream) -> ParseResult<Self> {
+        let mut args = AccountArgs::default();
+        while !stream.is_empty() {
+            if stream.peek(LitStr) {
+                args.namespace = Some(stream.parse::<LitStr>()?.value());
+            } else {
+                let ident = stream.call(Ident::parse_any)?;
+                match ident.to_string().as_str() {
+                    "zero_copy" => args.zero_copy = true,
+                    "version" => {
+                        stream.parse::<Token![=]>()?;
+                        let version: LitInt = stream.parse()?;
+                        let value = version.base10_parse::<u8>()?;
+                        if value == 0 {
+                            return Err(ParseError::new(version.span(), "versions start at 1"));
+                        }
+                        args.version = Some(value);
+                    }
+                    "migrate_from" => {
+                        stream.parse::<Token![=]>()?;
+                        args.migrate_from = if stream.peek(syn::token::Bracket) {
+                            let content;
+                            syn::bracketed!(content in stream);
+                            content
+                                .parse_terminated::<TypePath, Token![,]>(TypePath::parse)?
+                                .into_iter()
+                                .collect()
+                        } else {
+                            vec![stream.parse()?]
+                        };
+                    }
+                    // Any other identifier is a namespace, as with a string.
+                    namespace => args.namespace = Some(namespace.to_string()),
+                }
+            }
+            if !stream.is_empty() {
+                stream.parse::<Token![,]>()?;
+            }
+        }
+
+        if args.version.is_some() && args.zero_copy {
+            return Err(ParseError::new(
+                stream.span(),
+                "zero copy accounts can't be versioned",
+            ));
+        }
+        match args.version {
+            None if !args.migrate_from.is_empty() => {
+                return Err(ParseError::new(
+                    stream.span(),
+                    "version must be provided with migrate_from",
+                ))
+            }
+            Some(version) if args.migrate_from.len() >= version as usize => {
+                return Err(ParseError::new(
+                    stream.span(),
+                    "migrate_from has more layouts than previous versions",
+                ))
+            }
+            _ => {}
+        }
+        Ok(args)
+    }
+}
+
 #[derive(Debug)]
 pub struct ErrorCode {
     pub id: u32,
@@ -530,6 +622,7 @@ pub struct ConstraintGroup {
     close: Option<ConstraintClose>,
     address: Option<ConstraintAddress>,
     associated_token: Option<ConstraintAssociatedToken>,
+    migrate: Option<ConstraintMigrate>,
 }
 
 impl ConstraintGroup {
@@ -548,6 +641,10 @@ impl ConstraintGroup {
     pub fn is_close(&self) -> bool {
         self.close.is_some()
     }
+
+    pub fn seeds(&self) -> Option<&ConstraintSeedsGroup> {
+        self.seeds.as_ref()
+    }
 }
 
 // A single account constraint *after* merging all tokens into a well formed
@@ -571,6 +668,7 @@ pub enum Constraint {
     State(ConstraintState),
     Close(ConstraintClose),
     Address(ConstraintAddress),
+    Migrate(ConstraintMigrate),
 }
 
 // Constraint token is a single keyword in a `#[account(<TOKEN>)]` attribute.
@@ -601,6 +699,7 @@ pub enum ConstraintToken {
     MintFreezeAuthority(Context<ConstraintMintFreezeAuthority>),
     MintDecimals(Context<ConstraintMintDecimals>),
     Bump(Context<ConstraintTokenBump>),
+    Migrate(Context<ConstraintMigrate>),
 }
 
 impl Parse for ConstraintToken {
@@ -732,6 +831,9 @@ pub struct ConstraintClose {
     pub sol_dest: Ident,
 }
 
+#[derive(Debug, Clone)]
+pub struct ConstraintMigrate {}
+
 #[derive(Debug, Clone)]
 pub struct ConstraintTokenMint {
     mint: Expr,
@@ -798,3 +900,74 @@ impl<T> Spanned for Context<T> {
         self.span
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(args: &str) -> String {
+        syn::parse_str::<AccountArgs>(args).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn account_args() {
+        let args: AccountArgs = syn::parse_str("\"my-namespace\"").unwrap();
+        assert_eq!(args.namespace.as_deref(), Some("my-namespace"));
+        assert_eq!(args.version, None);
+
+        let args: AccountArgs =
+            syn::parse_str("version = 3, migrate_from = [CounterV1, v2::Counter]").unwrap();
+        let previous: Vec<(u8, String)> = args
+            .previous_versions()
+            .into_iter()
+            .map(|(version, ty)| (version, ty.to_token_stream().to_string()))
+            .collect();
+        assert_eq!(
+            previous,
+            vec![
+                (1, "CounterV1".to_string()),
+                (2, "v2 :: Counter".to_string())
+            ]
+        );
+
+        // The layouts of the last versions may be given alone.
+        let args: AccountArgs = syn::parse_str("version = 3, migrate_from = CounterV2").unwrap();
+        assert_eq!(args.previous_versions()[0].0, 2);
+    }
+
+    #[test]
+    fn account_args_errors() {
+        assert_eq!(parse_error("version = 0"), "versions start at 1");
+        assert_eq!(
+            parse_error("version = 2, migrate_from = [CounterV0, CounterV1]"),
+            "migrate_from has more layouts than previous versions"
+        );
+        assert_eq!(
+            parse_error("zero_copy, version = 2"),
+            "zero copy accounts can't be versioned"
+        );
+        assert_eq!(
+            parse_error("migrate_from = CounterV1"),
+            "version must be provided with migrate_from"
+        );
+        assert!(syn::parse_str::<AccountArgs>("version = 256").is_err());
+    }
+
+    #[test]
+    fn migrate_constraint() {
+        let accounts = |field: &str| {
+            syn::parse_str::<AccountsStruct>(&format!(
+                "pub struct Migrate<'info> {{ {} pub counter: Account<'info, Counter>, }}",
+                field
+            ))
+        };
+        assert!(accounts("#[account(mut, migrate)]").is_ok());
+        assert_eq!(
+            accounts("#[account(migrate, mut)]")
+                .unwrap_err()
+                .to_string(),
+            "mut must be provided before migrate"
+        );
+        assert!(accounts("#[account(mut, migrate = payer)]").is_err());
+    }
+}
@@ -84,6 +84,7 @@ pub fn parse_token(stream: ParseStream) -> ParseResult<ConstraintToken> {
         "executable" => {
             ConstraintToken::Executable(Context::new(ident.span(), ConstraintExecutable {}))
         }
+        "migrate" => ConstraintToken::Migrate(Context::new(ident.span(), ConstraintMigrate {})),
         "mint" => {
             stream.parse::<Token![:]>()?;
             stream.parse::<Token![:]>()?;
@@ -308,6 +309,7 @@ pub struct ConstraintGroupBuilder<'ty> {
     pub mint_freeze_authority: Option<Context<ConstraintMintFreezeAuthority>>,
     pub mint_decimals: Option<Context<ConstraintMintDecimals>>,
     pub bump: Option<Context<ConstraintTokenBump>>,
+    pub migrate: Option<Context<ConstraintMigrate>>,
 }
 
 impl<'ty> ConstraintGroupBuilder<'ty> {
@@ -338,6 +340,7 @@ impl<'ty> ConstraintGroupBuilder<'ty> {
             mint_freeze_authority: None,
             mint_decimals: None,
             bump: None,
+            migrate: None,
         }
     }
 
@@ -494,6 +497,7 @@ impl<'ty> ConstraintGroupBuilder<'ty> {
             mint_freeze_authority,
             mint_decimals,
             bump,
+            migrate,
         } = self;
 
         // Converts Option<Context<T>> -> Option<T>.
@@ -591,6 +595,7 @@ impl<'ty> ConstraintGroupBuilder<'ty> {
             address: into_inner!(address),
             associated_token: if !is_init { associated_token } else { None },
             seeds,
+            migrate: into_inner!(migrate),
         })
     }
 
@@ -620,6 +625,7 @@ impl<'ty> ConstraintGroupBuilder<'ty> {
             ConstraintToken::MintFreezeAuthority(c) => self.add_mint_freeze_authority(c),
             ConstraintToken::MintDecimals(c) => self.add_mint_decimals(c),
             ConstraintToken::Bump(c) => self.add_bump(c),
+            ConstraintToken::Migrate(c) => self.add_migrate(c),
         }
     }
 
@@ -669,6 +675,29 @@ impl<'ty> ConstraintGroupBuilder<'ty> {
         Ok(())
     }
 
+    fn add_migrate(&mut self, c: Context<ConstraintMigrate>) -> ParseResult<()> {
+        if !matches!(self.f_ty, Some(Ty::Account(_))) {
+            return Err(ParseError::new(c.span(), "migrate must be on an Account"));
+        }
+        if self.mutable.is_none() {
+            return Err(ParseError::new(
+                c.span(),
+                "mut must be provided before migrate",
+            ));
+        }
+        if self.init.is_some() || self.zeroed.is_some() {
+            return Err(ParseError::new(
+                c.span(),
+                "migrate can't be used on an account being initialized",
+            ));
+        }
+        if self.migrate.is_some() {
+            return Err(ParseError::new(c.span(), "migrate already provided"));
+        }
+        self.migrate.replace(c);
+        Ok(())
+    }
+
     fn add_address(&mut self, c: Context<ConstraintAddress>) -> ParseResult<()> {
         if self.address.is_some() {
             return Err(ParseError::new(c.span(), "address already provided"));
@@ -0,0 +1,46 @@
+use anchor_registry::server::Registry;
+use anyhow::Result;
+use clap::Clap;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::Server;
+use std::convert::Infallible;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug, Clap)]
+#[clap(version = env!("CARGO_PKG_VERSION"))]
+pub struct Opts {
+    /// Directory the packages are stored in.
+    #[clap(long, default_value = "registry")]
+    storage_dir: PathBuf,
+    /// Address to listen on.
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    address: SocketAddr,
+    /// File with the tokens allowed to publish, one per line.
+    #[clap(long)]
+    tokens: PathBuf,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opts = Opts::parse();
+    let tokens = fs::read_to_string(&opts.tokens)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(ToString::to_string)
+        .collect();
+    fs::create_dir_all(&opts.storage_dir)?;
+
+    let registry = Arc::new(Registry::new(opts.storage_dir, tokens));
+    let make_service = make_service_fn(move |_conn| {
+        let registry = registry.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| registry.clone().handle(req))) }
+    });
+
+    println!("Listening on http://{}", opts.address);
+    Server::bind(&opts.address).serve(make_service).await?;
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! The HTTP API of an Anchor package registry, which stores the verifiable
+//! builds published with `anchor publish`.
+//!
+//! Paths are relative to the registry url, i.e., the `url` of the
+//! `[registry]` section of Anchor.toml.
+//!
+//! | Method | Path                                         | Description                                   |
+//! |--------|----------------------------------------------|-----------------------------------------------|
+//! | `GET`  | `/api/v1/packages/<name>`                    | The [`PackageVersions`] of a package.         |
+//! | `GET`  | `/api/v1/packages/<name>/<version>`          | The [`PackageManifest`] of a version.         |
+//! | `GET`  | `/api/v1/packages/<name>/<version>/<file>`   | A file of a version, see [`FILES`].           |
+//! | `PUT`  | `/api/v1/packages/<name>/<version>/<file>`   | Uploads a file of an unpublished version.     |
+//! | `PUT`  | `/api/v1/packages/<name>/<version>`          | Publishes a version, given its manifest.      |
+//!
+//! A version is published in two steps: its files are uploaded, the workspace
+//! first, and then its manifest. Until then, the version isn't listed and
+//! its files can't be downloaded. Published versions are immutable, so that
+//! uploading to them fails with `409 Conflict`.
+//!
+//! `PUT` requests are authenticated with a bearer token, i.e., the token saved
+//! by `anchor login`, and fail with `401 Unauthorized` otherwise. Errors have
+//! an [`ApiError`] body.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "server")]
+pub mod server;
+
+pub const API_PREFIX: &str = "/api/v1/packages";
+
+/// Tarball of the workspace a version was built from, as packed by
+/// `anchor publish`.
+pub const WORKSPACE_FILE: &str = "workspace.tar.gz";
+/// IDL of the program, if it has one.
+pub const IDL_FILE: &str = "idl.json";
+/// The files of a version.
+pub const FILES: &[&str] = &[WORKSPACE_FILE, IDL_FILE];
+
+/// Metadata of a published version, i.e., of a verifiable build.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageManifest {
+    /// Name of the program's library.
+    pub name: String,
+    /// Version of the program's crate.
+    pub version: String,
+    /// Address the program is deployed at on mainnet.
+    pub address: String,
+    /// Hex encoded sha256 of the verifiable build, without trailing zeros.
+    pub binary_hash: String,
+    pub docker_image: String,
+    pub solana_version: Option<String>,
+    /// Commit of the workspace the build was made from.
+    pub git_commit: Option<String>,
+    /// Whether the version has an IDL file.
+    pub idl: bool,
+}
+
+/// The published versions of a package, in ascending order.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PackageVersions {
+    pub name: String,
+    pub versions: Vec<String>,
+}
+
+impl PackageVersions {
+    pub fn latest(&self) -> Option<&String> {
+        self.versions.last()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApiError {
+    pub error: String,
+}
+
+pub fn package_url(registry: &str, name: &str) -> String {
+    format!("{}{}/{}", registry.trim_end_matches('/'), API_PREFIX, name)
+}
+
+pub fn version_url(registry: &str, name: &str, version: &str) -> String {
+    format!("{}/{}", package_url(registry, name), version)
+}
+
+pub fn file_url(registry: &str, name: &str, version: &str, file: &str) -> String {
+    format!("{}/{}", version_url(registry, name, version), file)
+}
+
+/// Checks that a package name, i.e., a library name, is made of lowercase
+/// letters, digits, '-' and '_', so that it's safe to use as a path segment.
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    match valid {
+        true => Ok(()),
+        false => Err(anyhow!("Invalid package name: {}", name)),
+    }
+}
+
+/// Checks that a version is a semver version.
+pub fn validate_version(version: &str) -> Result<()> {
+    semver::Version::parse(version)
+        .map(|_| ())
+        .map_err(|e| anyhow!("Invalid version {}: {}", version, e))
+}
+
+/// Parses a package id, `<name>@<version>`, or `<name>` for the latest
+/// version.
+pub fn parse_package_id(id: &str) -> Result<(String, Option<String>)> {
+    let (name, version) = match id.split_once('@') {
+        Some((name, version)) => (name, Some(version.to_string())),
+        None => (id, None),
+    };
+    validate_name(name)?;
+    if let Some(version) = &version {
+        validate_version(version)?;
+    }
+    Ok((name.to_string(), version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_package_id() {
+        assert_eq!(
+            parse_package_id("my_program@0.1.0").unwrap(),
+            ("my_program".to_string(), Some("0.1.0".to_string()))
+        );
+        assert_eq!(
+            parse_package_id("my_program").unwrap(),
+            ("my_program".to_string(), None)
+        );
+        assert!(parse_package_id("../my_program@0.1.0").is_err());
+        assert!(parse_package_id("my_program@latest").is_err());
+    }
+
+    #[test]
+    fn test_urls() {
+        assert_eq!(
+            file_url("https://registry.com/", "my_program", "0.1.0", IDL_FILE),
+            "https://registry.com/api/v1/packages/my_program/0.1.0/idl.json"
+        );
+    }
+}
@@ -0,0 +1,399 @@
+//! Reference implementation of the registry, storing packages on local disk:
+//!
+//! ```text
+//! <storage>/<name>/<version>/manifest.json
+//! <storage>/<name>/<version>/workspace.tar.gz
+//! <storage>/<name>/<version>/idl.json
+//! ```
+//!
+//! A version is published once its `manifest.json` exists.
+
+use crate::{
+    validate_name, validate_version, ApiError, PackageManifest, PackageVersions, API_PREFIX, FILES,
+    IDL_FILE, WORKSPACE_FILE,
+};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+type ApiResult = Result<Response<Body>, (StatusCode, String)>;
+
+pub struct Registry {
+    storage: PathBuf,
+    /// Tokens allowed to publish.
+    tokens: Vec<String>,
+}
+
+impl Registry {
+    pub fn new(storage: PathBuf, tokens: Vec<String>) -> Self {
+        Self { storage, tokens }
+    }
+
+    pub async fn handle(self: Arc<Self>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        Ok(self
+            .route(req)
+            .await
+            .unwrap_or_else(|(status, error)| json(status, &ApiError { error })))
+    }
+
+    async fn route(&self, req: Request<Body>) -> ApiResult {
+        let segments: Vec<String> = match req
+            .uri()
+            .path()
+            .strip_prefix(API_PREFIX)
+            .and_then(|path| path.strip_prefix('/'))
+        {
+            Some(path) => path.split('/').map(ToString::to_string).collect(),
+            None => return Err(not_found()),
+        };
+        if let Some(name) = segments.first() {
+            validate_name(name).map_err(bad_request)?;
+        }
+        if let Some(version) = segments.get(1) {
+            validate_version(version).map_err(bad_request)?;
+        }
+
+        match (req.method().clone(), segments.as_slice()) {
+            (Method::GET, [name]) => self.versions(name),
+            (Method::GET, [name, version]) => self.download(name, version, MANIFEST_FILE),
+            (Method::GET, [name, version, file]) if FILES.contains(&file.as_str()) => {
+                self.download(name, version, file)
+            }
+            (Method::PUT, [name, version]) => {
+                self.authorize(&req)?;
+                let body = read_body(req).await?;
+                self.publish(name, version, &body)
+            }
+            (Method::PUT, [name, version, file]) if FILES.contains(&file.as_str()) => {
+                self.authorize(&req)?;
+                let body = read_body(req).await?;
+                self.upload(name, version, file, &body)
+            }
+            _ => Err(not_found()),
+        }
+    }
+
+    fn authorize(&self, req: &Request<Body>) -> Result<(), (StatusCode, String)> {
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match token {
+            Some(token) if self.tokens.iter().any(|t| t == token) => Ok(()),
+            _ => Err((StatusCode::UNAUTHORIZED, "Invalid token".to_string())),
+        }
+    }
+
+    fn version_dir(&self, name: &str, version: &str) -> PathBuf {
+        self.storage.join(name).join(version)
+    }
+
+    fn is_published(&self, name: &str, version: &str) -> bool {
+        self.version_dir(name, version).join(MANIFEST_FILE).exists()
+    }
+
+    fn versions(&self, name: &str) -> ApiResult {
+        let mut versions: Vec<semver::Version> = fs::read_dir(self.storage.join(name))
+            .map_err(|_| not_found())?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|version| self.is_published(name, version))
+            .filter_map(|version| semver::Version::parse(&version).ok())
+            .collect();
+        if versions.is_empty() {
+            return Err(not_found());
+        }
+        versions.sort();
+        Ok(json(
+            StatusCode::OK,
+            &PackageVersions {
+                name: name.to_string(),
+                versions: versions.iter().map(ToString::to_string).collect(),
+            },
+        ))
+    }
+
+    fn download(&self, name: &str, version: &str, file: &str) -> ApiResult {
+        if !self.is_published(name, version) {
+            return Err(not_found());
+        }
+        let contents =
+            fs::read(self.version_dir(name, version).join(file)).map_err(|_| not_found())?;
+        let content_type = match file {
+            WORKSPACE_FILE => "application/gzip",
+            _ => "application/json",
+        };
+        Response::builder()
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(contents))
+            .map_err(internal_error)
+    }
+
+    fn upload(&self, name: &str, version: &str, file: &str, body: &[u8]) -> ApiResult {
+        if self.is_published(name, version) {
+            return Err(conflict(name, version));
+        }
+        let dir = self.version_dir(name, version);
+        fs::create_dir_all(&dir).map_err(internal_error)?;
+        fs::write(dir.join(file), body).map_err(internal_error)?;
+        Ok(empty(StatusCode::OK))
+    }
+
+    fn publish(&self, name: &str, version: &str, body: &[u8]) -> ApiResult {
+        if self.is_published(name, version) {
+            return Err(conflict(name, version));
+        }
+        let manifest: PackageManifest = serde_json::from_slice(body).map_err(bad_request)?;
+        if manifest.name != name || manifest.version != version {
+            return Err(bad_request(format!(
+                "Manifest of {}@{} published as {}@{}",
+                manifest.name, manifest.version, name, version
+            )));
+        }
+        let dir = self.version_dir(name, version);
+        let mut required = vec![WORKSPACE_FILE];
+        if manifest.idl {
+            required.push(IDL_FILE);
+        }
+        if let Some(file) = required.iter().find(|file| !dir.join(file).exists()) {
+            return Err(bad_request(format!("Upload {} first", file)));
+        }
+
+        // Write the manifest atomically, as it marks the version as published.
+        let tmp = dir.join(format!("{}.tmp", MANIFEST_FILE));
+        fs::write(&tmp, body).map_err(internal_error)?;
+        fs::rename(&tmp, dir.join(MANIFEST_FILE)).map_err(internal_error)?;
+        Ok(empty(StatusCode::CREATED))
+    }
+}
+
+async fn read_body(req: Request<Body>) -> Result<hyper::body::Bytes, (StatusCode, String)> {
+    hyper::body::to_bytes(req.into_body())
+        .await
+        .map_err(bad_request)
+}
+
+fn json(status: StatusCode, value: &impl Serialize) -> Response<Body> {
+    let mut response = Response::new(Body::from(serde_json::to_vec(value).unwrap()));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    response
+}
+
+fn empty(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+fn not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Not found".to_string())
+}
+
+fn conflict(name: &str, version: &str) -> (StatusCode, String) {
+    (
+        StatusCode::CONFLICT,
+        format!("{}@{} is already published", name, version),
+    )
+}
+
+fn bad_request(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+fn internal_error(err: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "token";
+
+    fn registry(name: &str) -> Arc<Registry> {
+        let storage = std::env::temp_dir().join("anchor-registry").join(format!(
+            "{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&storage);
+        Arc::new(Registry::new(storage, vec![TOKEN.to_string()]))
+    }
+
+    fn manifest(name: &str, version: &str) -> Vec<u8> {
+        serde_json::to_vec(&PackageManifest {
+            name: name.to_string(),
+            version: version.to_string(),
+            address: "Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS".to_string(),
+            binary_hash: "00".to_string(),
+            docker_image: "projectserum/build:v0.19.0".to_string(),
+            solana_version: None,
+            git_commit: None,
+            idl: false,
+        })
+        .unwrap()
+    }
+
+    // Sends a request to the registry, returning the status and body of its
+    // response.
+    async fn send(
+        registry: &Arc<Registry>,
+        method: Method,
+        path: &str,
+        token: Option<&str>,
+        body: Vec<u8>,
+    ) -> (StatusCode, Vec<u8>) {
+        let mut req = Request::builder()
+            .method(method)
+            .uri(format!("{}/{}", API_PREFIX, path));
+        if let Some(token) = token {
+            req = req.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let response = registry
+            .clone()
+            .handle(req.body(Body::from(body)).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn publish_requires_token() {
+        let registry = registry("publish_requires_token");
+        for token in [None, Some("other")] {
+            let (status, _) = send(
+                &registry,
+                Method::PUT,
+                "my_program/0.1.0/workspace.tar.gz",
+                token,
+                b"workspace".to_vec(),
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            let (status, _) = send(
+                &registry,
+                Method::PUT,
+                "my_program/0.1.0",
+                token,
+                manifest("my_program", "0.1.0"),
+            )
+            .await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+        let (status, _) = send(&registry, Method::GET, "my_program", None, vec![]).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn publish_and_download() {
+        let registry = registry("publish_and_download");
+        let put = |path: &'static str, body: Vec<u8>| {
+            let registry = registry.clone();
+            async move {
+                send(&registry, Method::PUT, path, Some(TOKEN), body)
+                    .await
+                    .0
+            }
+        };
+        let get = |path: &'static str| {
+            let registry = registry.clone();
+            async move { send(&registry, Method::GET, path, None, vec![]).await }
+        };
+
+        // The manifest can only be published once the workspace is uploaded.
+        assert_eq!(
+            put("my_program/0.1.0", manifest("my_program", "0.1.0")).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            put("my_program/0.1.0/workspace.tar.gz", b"workspace".to_vec()).await,
+            StatusCode::OK
+        );
+        // Uploaded files aren't served before the version is published.
+        assert_eq!(
+            get("my_program/0.1.0/workspace.tar.gz").await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            put("my_program/0.1.0", manifest("my_program", "0.1.0")).await,
+            StatusCode::CREATED
+        );
+
+        assert_eq!(
+            get("my_program/0.1.0").await,
+            (StatusCode::OK, manifest("my_program", "0.1.0"))
+        );
+        assert_eq!(
+            get("my_program/0.1.0/workspace.tar.gz").await,
+            (StatusCode::OK, b"workspace".to_vec())
+        );
+        let (status, body) = get("my_program").await;
+        assert_eq!(status, StatusCode::OK);
+        let versions: PackageVersions = serde_json::from_slice(&body).unwrap();
+        assert_eq!(versions.versions, vec!["0.1.0".to_string()]);
+        assert_eq!(get("my_program/0.2.0").await.0, StatusCode::NOT_FOUND);
+        assert_eq!(
+            get("my_program/0.2.0/workspace.tar.gz").await.0,
+            StatusCode::NOT_FOUND
+        );
+
+        // Published versions are immutable.
+        assert_eq!(
+            put("my_program/0.1.0", manifest("my_program", "0.1.0")).await,
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            put("my_program/0.1.0/workspace.tar.gz", b"other".to_vec()).await,
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            get("my_program/0.1.0/workspace.tar.gz").await,
+            (StatusCode::OK, b"workspace".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn publish_rejects_mismatched_manifest() {
+        let registry = registry("publish_rejects_mismatched_manifest");
+        let (status, _) = send(
+            &registry,
+            Method::PUT,
+            "my_program/0.1.0/workspace.tar.gz",
+            Some(TOKEN),
+            b"workspace".to_vec(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        for manifest in [
+            manifest("other_program", "0.1.0"),
+            manifest("my_program", "0.2.0"),
+        ] {
+            let (status, body) = send(
+                &registry,
+                Method::PUT,
+                "my_program/0.1.0",
+                Some(TOKEN),
+                manifest,
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            let error: ApiError = serde_json::from_slice(&body).unwrap();
+            assert!(error.error.contains("published as my_program@0.1.0"));
+        }
+        let (status, _) = send(&registry, Method::GET, "my_program/0.1.0", None, vec![]).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
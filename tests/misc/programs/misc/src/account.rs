@@ -30,6 +30,7 @@ pub struct DataI16 {
 pub struct DataZeroCopy {
     pub data: u16,
     pub bump: u8,
+    pub _padding: u8,
 }
 
 #[account]
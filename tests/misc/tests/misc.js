@@ -430,6 +430,7 @@ describe("misc", () => {
     const account = await program.account.dataZeroCopy.fetch(data.publicKey);
     assert.ok(account.data === 10);
     assert.ok(account.bump === 2);
+    assert.ok(account.padding === 0);
   });
 
   let mint = undefined;
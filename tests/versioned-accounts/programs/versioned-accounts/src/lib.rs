@@ -0,0 +1,92 @@
+//! This example demonstrates how to change the layout of an account without
+//! breaking the accounts created with its previous layout.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+
+declare_id!("EeChhvocQs6WvLKefXQTxY8pWAibQkMPeDxiXh2yLNLo");
+
+#[program]
+pub mod versioned_accounts {
+    use super::*;
+
+    // Writes a counter with its first layout, as the program did before the
+    // counter was versioned.
+    pub fn create_v1(ctx: Context<CreateV1>, count: u64) -> ProgramResult {
+        let mut data = ctx.accounts.counter.try_borrow_mut_data()?;
+        let discriminator = hash(b"account:Counter").to_bytes();
+        data[..8].copy_from_slice(&discriminator[..8]);
+        CounterV1 { count }
+            .serialize(&mut &mut data[8..])
+            .map_err(|_| ProgramError::AccountDataTooSmall)?;
+        Ok(())
+    }
+
+    pub fn check(ctx: Context<Check>, count: u64, authority: Pubkey) -> ProgramResult {
+        let counter = &ctx.accounts.counter;
+        if counter.count != count || counter.authority != authority {
+            return Err(ErrorCode::UnexpectedCounter.into());
+        }
+        Ok(())
+    }
+
+    pub fn migrate(ctx: Context<Migrate>) -> ProgramResult {
+        ctx.accounts.counter.authority = ctx.accounts.authority.key();
+        Ok(())
+    }
+
+    pub fn increment(ctx: Context<Increment>) -> ProgramResult {
+        ctx.accounts.counter.count += 1;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct CreateV1<'info> {
+    #[account(mut, owner = ID)]
+    pub counter: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Check<'info> {
+    pub counter: Account<'info, Counter>,
+}
+
+#[derive(Accounts)]
+pub struct Migrate<'info> {
+    #[account(mut, migrate)]
+    pub counter: Account<'info, Counter>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Increment<'info> {
+    #[account(mut)]
+    pub counter: Account<'info, Counter>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CounterV1 {
+    pub count: u64,
+}
+
+#[account(version = 2, migrate_from = CounterV1)]
+pub struct Counter {
+    pub count: u64,
+    pub authority: Pubkey,
+}
+
+impl From<CounterV1> for Counter {
+    fn from(v1: CounterV1) -> Self {
+        Self {
+            count: v1.count,
+            authority: Pubkey::default(),
+        }
+    }
+}
+
+#[error]
+pub enum ErrorCode {
+    #[msg("The counter doesn't have the expected count and authority")]
+    UnexpectedCounter,
+}
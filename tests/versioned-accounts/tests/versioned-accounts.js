@@ -0,0 +1,113 @@
+const anchor = require("@project-serum/anchor");
+const assert = require("assert");
+const { PublicKey } = anchor.web3;
+
+describe("versioned-accounts", () => {
+  // Configure the client to use the local cluster.
+  anchor.setProvider(anchor.Provider.env());
+  const program = anchor.workspace.VersionedAccounts;
+  const provider = program.provider;
+
+  // Created with room for the first layout only.
+  const counter = anchor.web3.Keypair.generate();
+  // Created with the first layout, but with room for the current one.
+  const roomyCounter = anchor.web3.Keypair.generate();
+  // Discriminator and count.
+  const V1_SIZE = 8 + 8;
+  // Discriminator, count and authority.
+  const V2_SIZE = 8 + 8 + 32;
+
+  const createV1 = async (account, size) => {
+    await program.rpc.createV1(new anchor.BN(5), {
+      accounts: {
+        counter: account.publicKey,
+      },
+      instructions: [
+        await program.account.counter.createInstruction(account, size),
+      ],
+      signers: [account],
+    });
+  };
+
+  it("Creates counters with the first layout", async () => {
+    await createV1(counter, V1_SIZE);
+    await createV1(roomyCounter, V2_SIZE);
+  });
+
+  it("Reads counters written with the first layout", async () => {
+    for (const account of [counter, roomyCounter]) {
+      await program.rpc.check(
+        new anchor.BN(5),
+        new PublicKey(Buffer.alloc(32)),
+        {
+          accounts: {
+            counter: account.publicKey,
+          },
+        }
+      );
+    }
+  });
+
+  it("Fails to write the current layout without migrating", async () => {
+    try {
+      await program.rpc.increment({
+        accounts: {
+          counter: counter.publicKey,
+        },
+      });
+      assert.ok(false);
+    } catch (err) {
+      assert.strictEqual(err.msg, "Failed to serialize the account");
+    }
+  });
+
+  it("Fails to migrate a counter without room for its layout", async () => {
+    try {
+      await program.rpc.migrate({
+        accounts: {
+          counter: counter.publicKey,
+          authority: provider.wallet.publicKey,
+        },
+      });
+      assert.ok(false);
+    } catch (err) {
+      assert.strictEqual(err.msg, "A migrate constraint was violated");
+    }
+    // The counter is left with the first layout.
+    await program.rpc.check(new anchor.BN(5), new PublicKey(Buffer.alloc(32)), {
+      accounts: {
+        counter: counter.publicKey,
+      },
+    });
+  });
+
+  it("Migrates a counter to the current layout", async () => {
+    await program.rpc.migrate({
+      accounts: {
+        counter: roomyCounter.publicKey,
+        authority: provider.wallet.publicKey,
+      },
+    });
+
+    const info = await provider.connection.getAccountInfo(
+      roomyCounter.publicKey
+    );
+    assert.strictEqual(info.data.length, V2_SIZE);
+    const account = await program.account.counter.fetch(roomyCounter.publicKey);
+    assert.ok(account.count.eqn(5));
+    assert.ok(account.authority.equals(provider.wallet.publicKey));
+  });
+
+  it("Writes a migrated counter", async () => {
+    await program.rpc.increment({
+      accounts: {
+        counter: roomyCounter.publicKey,
+      },
+    });
+    await program.rpc.check(new anchor.BN(6), provider.wallet.publicKey, {
+      accounts: {
+        counter: roomyCounter.publicKey,
+      },
+    });
+  });
+});
@@ -19,9 +19,19 @@ export class AccountsCoder<A extends string = string> {
    */
   private accountLayouts: Map<A, Layout>;
 
+  /**
+   * Maps account type identifier to the discriminators and layouts of all
+   * its versions, the current one first.
+   */
+  private accountVersions: Map<
+    A,
+    { version: number; discriminator: Buffer; layout: Layout }[]
+  >;
+
   public constructor(idl: Idl) {
     if (idl.accounts === undefined) {
       this.accountLayouts = new Map();
+      this.accountVersions = new Map();
       return;
     }
     const layouts: [A, Layout][] = idl.accounts.map((acc) => {
@@ -29,6 +39,33 @@ export class AccountsCoder<A extends string = string> {
     });
 
     this.accountLayouts = new Map(layouts);
+    this.accountVersions = new Map(
+      idl.accounts.map((acc) => {
+        const version = acc.version ?? 1;
+        const versions = [
+          {
+            version,
+            discriminator: AccountsCoder.accountDiscriminator(
+              acc.name,
+              version
+            ),
+            layout: this.accountLayouts.get(acc.name as A) as Layout,
+          },
+          ...(acc.versions ?? []).map((v) => ({
+            version: v.version,
+            discriminator: AccountsCoder.accountDiscriminator(
+              acc.name,
+              v.version
+            ),
+            layout: IdlCoder.typeDefLayout(
+              { name: v.name, type: v.type },
+              idl.types
+            ),
+          })),
+        ];
+        return [acc.name as A, versions];
+      })
+    );
   }
 
   public async encode<T = any>(accountName: A, account: T): Promise<Buffer> {
@@ -39,28 +76,53 @@ export class AccountsCoder<A extends string = string> {
     }
     const len = layout.encode(account, buffer);
     let accountData = buffer.slice(0, len);
-    let discriminator = AccountsCoder.accountDiscriminator(accountName);
+    let discriminator = AccountsCoder.accountDiscriminator(
+      accountName,
+      this.accountVersions.get(accountName)?.[0].version
+    );
     return Buffer.concat([discriminator, accountData]);
   }
 
+  /**
+   * Decodes an account. Accounts written with a previous version of a
+   * versioned account are decoded with the layout of that version.
+   */
   public decode<T = any>(accountName: A, ix: Buffer): T {
     // Chop off the discriminator before decoding.
     const data = ix.slice(ACCOUNT_DISCRIMINATOR_SIZE);
-    const layout = this.accountLayouts.get(accountName);
+    const previous = this.accountVersions
+      .get(accountName)
+      ?.slice(1)
+      .find((v) => v.discriminator.equals(ix.slice(0, 8)));
+    const layout = previous?.layout ?? this.accountLayouts.get(accountName);
     if (!layout) {
       throw new Error(`Unknown account: ${accountName}`);
     }
     return layout.decode(data);
   }
 
+  /**
+   * Returns the version of the layout the account data was written with, or
+   * undefined if it isn't the data of the account.
+   */
+  public dataVersion(accountName: A, data: Buffer): number | undefined {
+    return this.accountVersions
+      .get(accountName)
+      ?.find((v) => v.discriminator.equals(data.slice(0, 8)))?.version;
+  }
+
   /**
    * Calculates and returns a unique 8 byte discriminator prepended to all anchor accounts.
    *
    * @param name The name of the account to calculate the discriminator.
+   * @param version The version of the account's layout, if it's versioned.
    */
-  public static accountDiscriminator(name: string): Buffer {
+  public static accountDiscriminator(name: string, version?: number): Buffer {
+    const suffix = version !== undefined && version > 1 ? `:v${version}` : "";
     return Buffer.from(
-      sha256.digest(`account:${camelcase(name, { pascalCase: true })}`)
+      sha256.digest(
+        `account:${camelcase(name, { pascalCase: true })}${suffix}`
+      )
     ).slice(0, ACCOUNT_DISCRIMINATOR_SIZE);
   }
 }
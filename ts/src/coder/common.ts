@@ -84,6 +84,17 @@ function typeSize(idl: Idl, ty: IdlType): number {
         let arraySize = ty.array[1];
         return typeSize(idl, arrayTy) * arraySize;
       }
+      if ("ringBuffer" in ty) {
+        const [itemTy, capacity] = ty.ringBuffer;
+        return 16 + typeSize(idl, itemTy) * capacity;
+      }
+      if ("slab" in ty) {
+        const [itemTy, capacity] = ty.slab;
+        return 24 + (8 + typeSize(idl, itemTy)) * capacity;
+      }
+      if ("bitMap" in ty) {
+        return 8 * ty.bitMap;
+      }
       throw new Error(`Invalid type ${JSON.stringify(ty)}`);
   }
 }
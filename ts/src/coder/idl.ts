@@ -99,6 +99,41 @@ export class IdlCoder {
             types
           );
           return borsh.array(innerLayout, arrayLen, fieldName);
+        } else if ("ringBuffer" in field.type) {
+          const [itemTy, capacity] = field.type.ringBuffer;
+          return borsh.struct(
+            [
+              borsh.u64("head"),
+              borsh.u64("len"),
+              borsh.array(
+                IdlCoder.fieldLayout({ name: undefined, type: itemTy }, types),
+                capacity,
+                "items"
+              ),
+            ],
+            fieldName
+          );
+        } else if ("slab" in field.type) {
+          const [itemTy, capacity] = field.type.slab;
+          return borsh.struct(
+            [
+              borsh.u64("len"),
+              borsh.u64("bump"),
+              borsh.u64("freeHead"),
+              borsh.array(borsh.u64(), capacity, "next"),
+              borsh.array(
+                IdlCoder.fieldLayout({ name: undefined, type: itemTy }, types),
+                capacity,
+                "items"
+              ),
+            ],
+            fieldName
+          );
+        } else if ("bitMap" in field.type) {
+          return borsh.struct(
+            [borsh.array(borsh.u64(), field.type.bitMap, "words")],
+            fieldName
+          );
         } else {
           throw new Error(`Not yet implemented: ${field}`);
         }
@@ -56,6 +56,9 @@ const LangErrorCode = {
   InstructionFallbackNotFound: 101,
   InstructionDidNotDeserialize: 102,
   InstructionDidNotSerialize: 103,
+  InstructionNotFound: 104,
+  InstructionProgramMismatch: 105,
+  InstructionDiscriminatorMismatch: 106,
 
   // IDL instructions.
   IdlInstructionStub: 1000,
@@ -82,6 +85,7 @@ const LangErrorCode = {
   ConstraintMintFreezeAuthority: 2017,
   ConstraintMintDecimals: 2018,
   ConstraintSpace: 2019,
+  ConstraintMigrate: 2020,
 
   // Accounts.
   AccountDiscriminatorAlreadySet: 3000,
@@ -123,6 +127,18 @@ const LangErrorMessage = new Map([
     LangErrorCode.InstructionDidNotSerialize,
     "The program could not serialize the given instruction",
   ],
+  [
+    LangErrorCode.InstructionNotFound,
+    "No instruction was found at the given index of the transaction",
+  ],
+  [
+    LangErrorCode.InstructionProgramMismatch,
+    "The instruction targets a different program than expected",
+  ],
+  [
+    LangErrorCode.InstructionDiscriminatorMismatch,
+    "8 byte instruction identifier did not match what was expected",
+  ],
 
   // Idl instructions.
   [
@@ -167,6 +183,7 @@ const LangErrorMessage = new Map([
     "A mint decimals constraint was violated",
   ],
   [LangErrorCode.ConstraintSpace, "A space constraint was violated"],
+  [LangErrorCode.ConstraintMigrate, "A migrate constraint was violated"],
 
   // Accounts.
   [
@@ -50,8 +50,18 @@ export type IdlAccount = {
   name: string;
   isMut: boolean;
   isSigner: boolean;
+  pda?: IdlPda;
 };
 
+export type IdlPda = {
+  seeds: IdlSeed[];
+};
+
+export type IdlSeed =
+  | { kind: "const"; value: number[] }
+  | { kind: "arg"; type: IdlType; path: string }
+  | { kind: "account"; path: string };
+
 // A nested/recursive version of IdlAccount.
 export type IdlAccounts = {
   name: string;
@@ -66,6 +76,16 @@ export type IdlField = {
 export type IdlTypeDef = {
   name: string;
   type: IdlTypeDefTy;
+  serialization?: "borsh" | "bytemuck";
+  version?: number;
+  versions?: IdlAccountVersion[];
+};
+
+// Layout of a previous version of a versioned account.
+export type IdlAccountVersion = {
+  version: number;
+  name: string;
+  type: IdlTypeDefTy;
 };
 
 export type IdlTypeDefTyStruct = {
@@ -100,7 +120,10 @@ export type IdlType =
   | IdlTypeDefined
   | IdlTypeOption
   | IdlTypeVec
-  | IdlTypeArray;
+  | IdlTypeArray
+  | IdlTypeRingBuffer
+  | IdlTypeSlab
+  | IdlTypeBitMap;
 
 // User defined type.
 export type IdlTypeDefined = {
@@ -119,6 +142,19 @@ export type IdlTypeArray = {
   array: [idlType: IdlType, size: number];
 };
 
+export type IdlTypeRingBuffer = {
+  ringBuffer: [idlType: IdlType, capacity: number];
+};
+
+export type IdlTypeSlab = {
+  slab: [idlType: IdlType, capacity: number];
+};
+
+// Number of 64-bit words.
+export type IdlTypeBitMap = {
+  bitMap: number;
+};
+
 export type IdlEnumVariant = {
   name: string;
   fields?: IdlEnumFields;
@@ -146,10 +146,12 @@ export class AccountClient<
     }
 
     // Assert the account discriminator is correct.
-    const discriminator = AccountsCoder.accountDiscriminator(
-      this._idlAccount.name
-    );
-    if (discriminator.compare(accountInfo.data.slice(0, 8))) {
+    if (
+      this._coder.accounts.dataVersion(
+        this._idlAccount.name,
+        accountInfo.data
+      ) === undefined
+    ) {
       throw new Error("Invalid account discriminator");
     }
 
@@ -188,15 +190,17 @@ export class AccountClient<
       commitment
     );
 
-    const discriminator = AccountsCoder.accountDiscriminator(
-      this._idlAccount.name
-    );
     // Decode accounts where discriminator is correct, null otherwise
     return accounts.map((account) => {
       if (account == null) {
         return null;
       }
-      if (discriminator.compare(account?.account.data.slice(0, 8))) {
+      if (
+        this._coder.accounts.dataVersion(
+          this._idlAccount.name,
+          account?.account.data
+        ) === undefined
+      ) {
         return null;
       }
       return this._coder.accounts.decode(
@@ -219,12 +223,16 @@ export class AccountClient<
    *
    *                When filters are of type `GetProgramAccountsFilter[]`,
    *                filters are appended after the discriminator filter.
+   *
+   *                Only accounts of the current version of a versioned
+   *                account are returned.
    */
   async all(
     filters?: Buffer | GetProgramAccountsFilter[]
   ): Promise<ProgramAccount<T>[]> {
     const discriminator = AccountsCoder.accountDiscriminator(
-      this._idlAccount.name
+      this._idlAccount.name,
+      this._idlAccount.version
     );
 
     let resp = await this._provider.connection.getProgramAccounts(